@@ -1,7 +1,12 @@
 use wdrf::minifilter::communication::client_communication::{
     FltClientCommunication, FltCommunicationCallback,
 };
-use wdrf_std::{dbg_break, io::Write, slice::tracked_slice::TrackedSlice, NtResult};
+use wdrf_std::{
+    dbg_break,
+    io::{self, Write},
+    slice::tracked_slice::TrackedSlice,
+    NtResult,
+};
 
 use maple::info;
 
@@ -14,12 +19,11 @@ impl FltCommunicationCallback for FltCallbackImpl {
         Ok(())
     }
 
-    fn message(&self, input: &[u8], output: Option<&mut TrackedSlice>) -> anyhow::Result<()> {
+    fn message(&self, input: &[u8], output: Option<&mut TrackedSlice>) -> io::Result<()> {
         dbg_break();
 
         if input.len() > 0 {
-            let s = core::str::from_utf8(input)
-                .map_err(|_| anyhow::Error::msg("Failed to convert message into utf8 string"))?;
+            let s = core::str::from_utf8(input).map_err(|_| io::Error::InvalidData)?;
             info!("Test {s}");
         }
 
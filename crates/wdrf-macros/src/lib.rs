@@ -4,7 +4,7 @@
 
 use core::marker::ConstParamTy;
 
-pub use wdrf_proc_macros::irql_check;
+pub use wdrf_proc_macros::{irql_check, DriverConfig};
 use windows_sys::Wdk::System::SystemServices::{
     KeGetCurrentIrql, APC_LEVEL, DISPATCH_LEVEL, PASSIVE_LEVEL,
 };
@@ -0,0 +1,131 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+
+#[derive(deluxe::ParseAttributes)]
+#[deluxe(attributes(config))]
+struct ConfigFieldAttributes {
+    #[deluxe(default)]
+    name: Option<syn::LitStr>,
+    #[deluxe(default)]
+    default: Option<syn::Expr>,
+    #[deluxe(default)]
+    range: Option<syn::Expr>,
+}
+
+///
+/// `log_level` is read from the `LogLevel` value
+///
+fn value_name(ident: &syn::Ident) -> String {
+    let ident = ident.to_string();
+    let ident = ident.strip_prefix("r#").unwrap_or(&ident);
+
+    ident
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .flat_map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect()
+}
+
+///
+/// Locals of the generated code, mixed site so a field named `store` or
+/// `value` does not shadow them
+///
+fn hygienic_ident(name: &str) -> syn::Ident {
+    syn::Ident::new(name, Span::mixed_site())
+}
+
+fn field_loader(
+    field: &syn::Field,
+    binding: &syn::Ident,
+    store: &syn::Ident,
+) -> deluxe::Result<TokenStream> {
+    let ConfigFieldAttributes {
+        name,
+        default,
+        range,
+    } = deluxe::parse_attributes(field)?;
+
+    let ident = field.ident.as_ref().unwrap();
+    let ty = &field.ty;
+    let name = name
+        .map(|name| name.value())
+        .unwrap_or_else(|| value_name(ident));
+
+    let value = hygienic_ident("value");
+    let check_range = range.map(|range| {
+        quote! {
+            if !(#range).contains(&#value) {
+                return ::core::result::Result::Err(
+                    ::wdrf::config::ConfigError::OutOfRange(#name),
+                );
+            }
+        }
+    });
+
+    let missing = match default {
+        Some(default) => quote! { #default },
+        None => quote! {
+            return ::core::result::Result::Err(::wdrf::config::ConfigError::Missing(#name))
+        },
+    };
+
+    Ok(quote! {
+        let #binding: #ty = match ::wdrf::config::read_field::<#ty, _>(#store, #name)? {
+            ::core::option::Option::Some(#value) => {
+                #check_range
+                #value
+            }
+            ::core::option::Option::None => #missing,
+        };
+    })
+}
+
+pub(crate) fn driver_config_derive_impl(item: TokenStream) -> deluxe::Result<TokenStream> {
+    let input: syn::DeriveInput = syn::parse2(item)?;
+
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "DriverConfig can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    let store = hygienic_ident("store");
+    let bindings = (0..fields.len())
+        .map(|index| format_ident!("field_{}", index, span = Span::mixed_site()))
+        .collect::<Vec<_>>();
+    let loaders = fields
+        .iter()
+        .zip(&bindings)
+        .map(|(field, binding)| field_loader(field, binding, &store))
+        .collect::<deluxe::Result<Vec<_>>>()?;
+    let idents = fields.iter().map(|field| field.ident.as_ref().unwrap());
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::wdrf::config::DriverConfig for #ident #ty_generics #where_clause {
+            fn load_from<__S: ::wdrf::config::ValueStore + ?Sized>(
+                #store: &__S,
+            ) -> ::core::result::Result<Self, ::wdrf::config::ConfigError> {
+                #(#loaders)*
+
+                ::core::result::Result::Ok(Self { #(#idents: #bindings),* })
+            }
+        }
+    })
+}
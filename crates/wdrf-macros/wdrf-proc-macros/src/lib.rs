@@ -1,6 +1,8 @@
+use driver_config::driver_config_derive_impl;
 use irql_check::irql_check_attr_impl;
 use proc_macro::TokenStream;
 
+mod driver_config;
 mod irql_check;
 
 #[proc_macro_attribute]
@@ -9,3 +11,17 @@ pub fn irql_check(attr: TokenStream, item: TokenStream) -> TokenStream {
         .unwrap()
         .into()
 }
+
+///
+/// Implements `wdrf::config::DriverConfig`, every field is read from the
+/// registry value named after it in PascalCase
+///
+/// Fields accept `#[config(name = "Value", default = expr, range = a..=b)]`.
+/// A field without a default fails the load when its value is missing.
+///
+#[proc_macro_derive(DriverConfig, attributes(config))]
+pub fn driver_config(item: TokenStream) -> TokenStream {
+    driver_config_derive_impl(item.into())
+        .unwrap_or_else(|e| e.into_compile_error())
+        .into()
+}
@@ -8,3 +8,24 @@ impl<T: Copy> AsAligned<T> {
         self.ptr
     }
 }
+
+///
+/// Keeps `T` on its own cache line so writes from different
+/// processors to neighbouring values do not contend
+///
+#[repr(align(64))]
+pub struct CacheAligned<T>(pub T);
+
+impl<T> core::ops::Deref for CacheAligned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> core::ops::DerefMut for CacheAligned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
@@ -1,6 +1,9 @@
 use core::{alloc::Allocator, pin::Pin};
 
-use crate::kmalloc::{GlobalKernelAllocator, TaggedObject};
+use crate::{
+    irql::BelowDispatch,
+    kmalloc::{GlobalKernelAllocator, TaggedObject},
+};
 
 #[allow(type_alias_bounds)]
 pub type Box<T: ?Sized, A: Allocator = GlobalKernelAllocator> = alloc::boxed::Box<T, A>;
@@ -26,6 +29,16 @@ pub trait BoxExt<T> {
     {
         Self::try_pin_in(value, GlobalKernelAllocator::new_for_tagged::<T>())
     }
+
+    fn try_create_paged<I: BelowDispatch>(
+        value: T,
+        irql: &I,
+    ) -> anyhow::Result<Box<T, GlobalKernelAllocator>>
+    where
+        T: TaggedObject,
+    {
+        Self::try_create_in(value, GlobalKernelAllocator::new_paged(T::tag(), irql))
+    }
 }
 
 impl<T> BoxExt<T> for Box<T> {
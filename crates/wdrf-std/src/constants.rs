@@ -55,3 +55,6 @@ extern "C" {
 extern "C" {
     pub static mut SeTokenObjectType: *mut POBJECT_TYPE;
 }
+extern "C" {
+    pub static mut CmKeyObjectType: *mut POBJECT_TYPE;
+}
@@ -0,0 +1,177 @@
+use core::{ffi::c_void, marker::PhantomData};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+use windows_sys::{
+    Wdk::{Foundation::KDPC, System::SystemServices::KeQueryActiveProcessorCountEx},
+    Win32::System::Threading::ALL_PROCESSOR_GROUPS,
+};
+
+use crate::{
+    constants::PoolFlags,
+    irql::Dispatch,
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    thread::this_thread::{self, ProcessorNumber},
+    vec::Vec,
+};
+
+type BroadcastRoutine = unsafe extern "system" fn(*mut KDPC, *mut c_void, *mut c_void, *mut c_void);
+
+//Not exposed by windows-sys
+#[link(name = "ntoskrnl")]
+extern "system" {
+    fn KeGenericCallDpc(routine: BroadcastRoutine, context: *mut c_void);
+    fn KeSignalCallDpcSynchronize(system_argument2: *mut c_void) -> u32;
+    fn KeSignalCallDpcDone(system_argument1: *mut c_void);
+}
+
+///
+/// The processor a `broadcast` closure is running on
+///
+pub struct BroadcastCpu<'a> {
+    processor: ProcessorNumber,
+    irql: Dispatch<'a>,
+    barrier: *mut c_void,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl<'a> BroadcastCpu<'a> {
+    #[inline]
+    pub fn processor(&self) -> ProcessorNumber {
+        self.processor
+    }
+
+    #[inline]
+    pub fn irql(&mut self) -> &mut Dispatch<'a> {
+        &mut self.irql
+    }
+
+    ///
+    /// Waits until every processor reached the same barrier
+    ///
+    /// Returns true on exactly one processor, the last one to arrive.
+    /// Every processor must call it the same number of times or the
+    /// others spin forever at DISPATCH_LEVEL.
+    ///
+    pub fn synchronize(&mut self) -> bool {
+        unsafe { KeSignalCallDpcSynchronize(self.barrier) != 0 }
+    }
+}
+
+struct BroadcastContext<'f, F> {
+    function: &'f F,
+}
+
+///
+/// Runs `f` once on every active processor at DISPATCH_LEVEL
+///
+/// Returns once all the processors finished, so `f` can borrow from the caller.
+/// The processors leave the DPC together, none of them resumes its
+/// previous work before the slowest one is done with `f`.
+///
+/// ```ignore
+/// dpc::broadcast(|cpu| {
+///     counters[cpu.processor().index as usize].store(0, Ordering::Relaxed);
+/// });
+/// ```
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+pub fn broadcast<F>(f: F)
+where
+    F: Fn(&mut BroadcastCpu<'_>) + Sync,
+{
+    let context = BroadcastContext { function: &f };
+
+    unsafe {
+        KeGenericCallDpc(
+            broadcast_routine::<F>,
+            &context as *const BroadcastContext<F> as *mut c_void,
+        );
+    }
+}
+
+///
+/// Like `broadcast` but keeps the value returned on each processor
+///
+/// The results are ordered by the system wide processor index.
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+pub fn broadcast_collect<R, F>(f: F) -> anyhow::Result<Vec<R>>
+where
+    R: Send,
+    F: Fn(&mut BroadcastCpu<'_>) -> R + Sync,
+{
+    let count = unsafe { KeQueryActiveProcessorCountEx(ALL_PROCESSOR_GROUPS) } as usize;
+
+    let mut slots = Vec::new_in(allocator());
+    slots
+        .try_reserve_exact(count)
+        .map_err(|_| anyhow::Error::msg("Failed to reserve broadcast slots"))?;
+    slots.resize_with(count, || None);
+
+    //Reserved before the call so nothing can fail after the closures ran
+    let mut results = Vec::new_in(allocator());
+    results
+        .try_reserve_exact(count)
+        .map_err(|_| anyhow::Error::msg("Failed to reserve broadcast results"))?;
+
+    let output = Slots {
+        ptr: slots.as_mut_ptr(),
+        len: count,
+    };
+    broadcast(|cpu| {
+        let result = f(cpu);
+        output.store(cpu.processor().index as usize, result);
+    });
+
+    results.extend(slots.into_iter().flatten());
+    Ok(results)
+}
+
+struct Slots<R> {
+    ptr: *mut Option<R>,
+    len: usize,
+}
+
+unsafe impl<R: Send> Sync for Slots<R> {}
+
+impl<R> Slots<R> {
+    fn store(&self, index: usize, value: R) {
+        //Each processor only writes the slot of its own index,
+        //processors added after the count was queried are ignored
+        if index < self.len {
+            unsafe { *self.ptr.add(index) = Some(value) };
+        }
+    }
+}
+
+fn allocator() -> GlobalKernelAllocator {
+    GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"dpcb"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    )
+}
+
+unsafe extern "system" fn broadcast_routine<F>(
+    _dpc: *mut KDPC,
+    context: *mut c_void,
+    system_argument1: *mut c_void,
+    system_argument2: *mut c_void,
+) where
+    F: Fn(&mut BroadcastCpu<'_>) + Sync,
+{
+    let context = &*(context as *const BroadcastContext<F>);
+
+    let mut cpu = BroadcastCpu {
+        processor: this_thread::current_processor(),
+        irql: Dispatch::new_unchecked(),
+        barrier: system_argument2,
+        _not_send: PhantomData,
+    };
+    (context.function)(&mut cpu);
+
+    //Keeps every processor in the DPC until all of them ran the closure
+    KeSignalCallDpcSynchronize(system_argument2);
+    KeSignalCallDpcDone(system_argument1);
+}
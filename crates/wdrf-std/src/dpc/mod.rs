@@ -0,0 +1,158 @@
+//!
+//! Deferred procedure calls
+//!
+//! `queue_dpc` runs a closure at DISPATCH_LEVEL on the current processor,
+//! `queue_dpc_on` on a given one and `broadcast` on every active processor.
+//! The closures receive the `Dispatch` token, anything they touch must be
+//! non paged and must not wait.
+//!
+//! Queued DPCs run code from the driver image, `flush` must be called
+//! from the unload routine before the image goes away.
+//!
+
+mod broadcast;
+
+pub use broadcast::{broadcast, broadcast_collect, BroadcastCpu};
+
+use core::ffi::c_void;
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::{DISPATCH_LEVEL, PASSIVE_LEVEL};
+use windows_sys::{
+    Wdk::{
+        Foundation::KDPC,
+        System::SystemServices::{
+            HighImportance, KeFlushQueuedDpcs, KeGetProcessorNumberFromIndex, KeInitializeDpc,
+            KeInsertQueueDpc, KeSetImportanceDpc, KeSetTargetProcessorDpcEx, LowImportance,
+            MediumHighImportance, MediumImportance,
+        },
+    },
+    Win32::System::Kernel::PROCESSOR_NUMBER,
+};
+
+use crate::{
+    boxed::{Box, BoxExt},
+    constants::PoolFlags,
+    irql::Dispatch,
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    nt_success,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DpcImportance {
+    Low,
+    Medium,
+    MediumHigh,
+    High,
+}
+
+impl DpcImportance {
+    fn as_wdm_value(self) -> i32 {
+        match self {
+            DpcImportance::Low => LowImportance,
+            DpcImportance::Medium => MediumImportance,
+            DpcImportance::MediumHigh => MediumHighImportance,
+            DpcImportance::High => HighImportance,
+        }
+    }
+}
+
+struct DpcItem<F> {
+    dpc: KDPC,
+    function: F,
+}
+
+///
+/// Runs `f` at DISPATCH_LEVEL on the current processor
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+pub fn queue_dpc<F>(f: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&mut Dispatch<'_>) + Send + 'static,
+{
+    queue(None, DpcImportance::Medium, f)
+}
+
+///
+/// Runs `f` at DISPATCH_LEVEL on the processor with the system wide `index`
+///
+/// `High` importance makes the target processor drain its DPC queue right away,
+/// the other levels may wait for its next clock tick.
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+pub fn queue_dpc_on<F>(index: u32, importance: DpcImportance, f: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&mut Dispatch<'_>) + Send + 'static,
+{
+    queue(Some(index), importance, f)
+}
+
+///
+/// Waits for every DPC queued on any processor to finish
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+pub fn flush() {
+    unsafe { KeFlushQueuedDpcs() };
+}
+
+fn queue<F>(target: Option<u32>, importance: DpcImportance, f: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&mut Dispatch<'_>) + Send + 'static,
+{
+    let item = Box::try_create_in(
+        DpcItem {
+            dpc: unsafe { core::mem::zeroed() },
+            function: f,
+        },
+        allocator(),
+    )?;
+
+    unsafe {
+        let item = Box::into_raw(item);
+        let dpc: *mut KDPC = &mut (*item).dpc;
+
+        let routine: unsafe extern "system" fn(*mut KDPC, *mut c_void, *mut c_void, *mut c_void) =
+            dpc_routine::<F>;
+        KeInitializeDpc(dpc, Some(core::mem::transmute(routine)), item.cast());
+        KeSetImportanceDpc(dpc, importance.as_wdm_value());
+
+        if let Some(index) = target {
+            let mut number: PROCESSOR_NUMBER = core::mem::zeroed();
+            if !nt_success(KeGetProcessorNumberFromIndex(index, &mut number))
+                || !nt_success(KeSetTargetProcessorDpcEx(dpc, &number))
+            {
+                drop(Box::from_raw_in(item, allocator()));
+                return Err(anyhow::Error::msg("Invalid DPC target processor"));
+            }
+        }
+
+        //A freshly initialized DPC can not already be queued
+        KeInsertQueueDpc(dpc, core::ptr::null(), core::ptr::null());
+    }
+
+    Ok(())
+}
+
+fn allocator() -> GlobalKernelAllocator {
+    GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"dpcq"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    )
+}
+
+unsafe extern "system" fn dpc_routine<F>(
+    _dpc: *mut KDPC,
+    context: *mut c_void,
+    _arg1: *mut c_void,
+    _arg2: *mut c_void,
+) where
+    F: FnOnce(&mut Dispatch<'_>) + Send + 'static,
+{
+    //The KDPC is no longer used by the kernel once its routine runs
+    let item = Box::from_raw_in(context as *mut DpcItem<F>, allocator());
+    let DpcItem { function, .. } = Box::into_inner(item);
+
+    let mut irql = Dispatch::new_unchecked();
+    function(&mut irql);
+}
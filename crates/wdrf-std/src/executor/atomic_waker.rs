@@ -0,0 +1,92 @@
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU8, Ordering},
+    task::Waker,
+};
+
+const IDLE: u8 = 0;
+const REGISTERING: u8 = 1;
+const WAKING: u8 = 2;
+
+///
+/// Single waker slot shared between a future and whoever completes it
+///
+/// `wake` can be called from any thread and at DISPATCH_LEVEL,
+/// it never allocates or blocks.
+///
+pub struct AtomicWaker {
+    state: AtomicU8,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+unsafe impl Send for AtomicWaker {}
+unsafe impl Sync for AtomicWaker {}
+
+impl AtomicWaker {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(IDLE),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    ///
+    /// Replaces the stored waker, only the task polling the future may call it
+    ///
+    pub fn register(&self, waker: &Waker) {
+        match self
+            .state
+            .compare_exchange(IDLE, REGISTERING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                unsafe {
+                    let slot = &mut *self.waker.get();
+                    if !slot.as_ref().is_some_and(|old| old.will_wake(waker)) {
+                        *slot = Some(waker.clone());
+                    }
+                }
+
+                if self
+                    .state
+                    .compare_exchange(REGISTERING, IDLE, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    //A wake arrived while registering, it could not take the waker
+                    let waker = unsafe { (*self.waker.get()).take() };
+                    self.state.store(IDLE, Ordering::Release);
+
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            }
+            //Being woken right now, poll again instead of waiting
+            Err(_) => waker.wake_by_ref(),
+        }
+    }
+
+    pub fn wake(&self) {
+        if let Some(waker) = self.take() {
+            waker.wake();
+        }
+    }
+
+    pub fn take(&self) -> Option<Waker> {
+        match self.state.fetch_or(WAKING, Ordering::AcqRel) {
+            IDLE => {
+                let waker = unsafe { (*self.waker.get()).take() };
+                self.state.fetch_and(!WAKING, Ordering::Release);
+
+                waker
+            }
+            //The registering side sees the flag and wakes itself
+            _ => None,
+        }
+    }
+}
+
+impl Default for AtomicWaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
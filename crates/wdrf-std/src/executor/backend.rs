@@ -0,0 +1,318 @@
+//!
+//! The executor only needs a short lock, a way to put a worker to sleep
+//! and a one shot timer for `time::sleep`
+//!
+//! Drivers use a kernel spin lock and a `KeEvent`, host builds use
+//! the std primitives so the scheduling logic runs in unit tests.
+//!
+
+#[cfg(not(test))]
+pub(super) use kernel::*;
+
+#[cfg(not(test))]
+mod kernel {
+    use core::{cell::UnsafeCell, ffi::c_void, time::Duration};
+
+    use windows_sys::{
+        Wdk::{
+            Foundation::KDPC,
+            System::SystemServices::{
+                ExAcquireSpinLockExclusive, ExReleaseSpinLockExclusive, KeCancelTimer,
+                KeFlushQueuedDpcs, KeInitializeDpc, KeInitializeTimerEx, KeRemoveQueueDpc,
+                KeSetTimerEx, KTIMER,
+            },
+        },
+        Win32::System::Kernel::NotificationTimer,
+    };
+
+    use crate::{
+        irql::Passive,
+        kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+        sync::arc::{Arc, ArcExt},
+        sys::{
+            event::{EventType, KeEvent},
+            wait_object,
+        },
+        time::duration_to_ticks,
+    };
+
+    use crate::executor::time::SleepState;
+
+    ///
+    /// Raises to DISPATCH_LEVEL so a DPC waking a task on the same processor
+    /// can never spin on a lock held by the preempted worker
+    ///
+    pub(in crate::executor) struct RawLock {
+        lock: UnsafeCell<i32>,
+    }
+
+    unsafe impl Send for RawLock {}
+    unsafe impl Sync for RawLock {}
+
+    impl RawLock {
+        pub(in crate::executor) const fn new() -> Self {
+            Self {
+                lock: UnsafeCell::new(0),
+            }
+        }
+
+        pub(in crate::executor) fn with<R>(&self, f: impl FnOnce() -> R) -> R {
+            unsafe {
+                let old_irql = ExAcquireSpinLockExclusive(self.lock.get());
+                let result = f();
+                ExReleaseSpinLockExclusive(self.lock.get(), old_irql);
+
+                result
+            }
+        }
+    }
+
+    ///
+    /// Auto reset event, one `unpark` releases one parked thread
+    /// or the next one that parks
+    ///
+    pub(in crate::executor) struct Parker {
+        event: KeEvent,
+    }
+
+    unsafe impl Send for Parker {}
+    unsafe impl Sync for Parker {}
+
+    impl Parker {
+        ///
+        /// # Safety
+        ///
+        /// `init` must be called once the parker reached its final address
+        ///
+        pub(in crate::executor) unsafe fn new() -> Self {
+            Self {
+                event: KeEvent::new(),
+            }
+        }
+
+        ///
+        /// # Safety
+        ///
+        /// Called once, after the parker reached its final address
+        ///
+        pub(in crate::executor) unsafe fn init(&self) {
+            self.event.init(EventType::Synchronization, false);
+        }
+
+        pub(in crate::executor) fn park(&self) {
+            let _ = wait_object(&self.event, None);
+        }
+
+        pub(in crate::executor) fn unpark(&self) {
+            self.event.signal();
+        }
+    }
+
+    struct SleepTimerInner {
+        timer: UnsafeCell<KTIMER>,
+        dpc: UnsafeCell<KDPC>,
+        state: Arc<SleepState>,
+    }
+
+    unsafe impl Send for SleepTimerInner {}
+    unsafe impl Sync for SleepTimerInner {}
+
+    impl TaggedObject for SleepTimerInner {
+        fn tag() -> MemoryTag {
+            MemoryTag::new_from_bytes(b"exst")
+        }
+    }
+
+    ///
+    /// One shot `KTIMER` whose DPC owns a reference to the timer memory
+    ///
+    /// Dropping it only cancels the timer, if the DPC is already queued or
+    /// running the DPC frees the memory, so the drop works at DISPATCH_LEVEL.
+    ///
+    pub(in crate::executor) struct SleepTimer {
+        inner: Arc<SleepTimerInner>,
+    }
+
+    impl SleepTimer {
+        pub(in crate::executor) fn try_start(
+            state: Arc<SleepState>,
+            due: Duration,
+        ) -> anyhow::Result<Self> {
+            let inner = Arc::try_create(SleepTimerInner {
+                timer: unsafe { core::mem::zeroed() },
+                dpc: unsafe { core::mem::zeroed() },
+                state,
+            })?;
+
+            unsafe {
+                //Released by the DPC or by the drop that dequeues it
+                let context = Arc::into_raw(inner.clone());
+                let routine: unsafe extern "system" fn(
+                    *mut KDPC,
+                    *mut c_void,
+                    *mut c_void,
+                    *mut c_void,
+                ) = sleep_timer_dpc_routine;
+
+                KeInitializeTimerEx(inner.timer.get(), NotificationTimer);
+                KeInitializeDpc(
+                    inner.dpc.get(),
+                    Some(core::mem::transmute(routine)),
+                    context as *const c_void,
+                );
+                KeSetTimerEx(
+                    inner.timer.get(),
+                    -duration_to_ticks(due),
+                    0,
+                    inner.dpc.get(),
+                );
+            }
+
+            Ok(Self { inner })
+        }
+    }
+
+    impl Drop for SleepTimer {
+        fn drop(&mut self) {
+            unsafe {
+                let cancelled = KeCancelTimer(self.inner.timer.get()) != 0;
+                let dequeued = KeRemoveQueueDpc(self.inner.dpc.get()) != 0;
+
+                if cancelled || dequeued {
+                    //The DPC will not run, drop the reference it owned
+                    Arc::decrement_strong_count_in(
+                        Arc::as_ptr(&self.inner),
+                        GlobalKernelAllocator::new_for_tagged::<SleepTimerInner>(),
+                    );
+                } else if Passive::try_current().is_some() {
+                    //The DPC frees the memory either way, the flush keeps it
+                    //from running past the driver unload
+                    KeFlushQueuedDpcs();
+                }
+            }
+        }
+    }
+
+    unsafe extern "system" fn sleep_timer_dpc_routine(
+        _dpc: *mut KDPC,
+        context: *mut c_void,
+        _arg1: *mut c_void,
+        _arg2: *mut c_void,
+    ) {
+        let inner = Arc::from_raw_in(
+            context as *const SleepTimerInner,
+            GlobalKernelAllocator::new_for_tagged::<SleepTimerInner>(),
+        );
+
+        inner.state.fire();
+    }
+}
+
+#[cfg(test)]
+pub(super) use host::*;
+
+#[cfg(test)]
+mod host {
+    extern crate std;
+
+    use core::{
+        hint,
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+    use std::sync::{Arc as StdArc, Condvar, Mutex};
+
+    use crate::sync::arc::Arc;
+
+    use crate::executor::time::SleepState;
+
+    pub(in crate::executor) struct RawLock {
+        lock: AtomicBool,
+    }
+
+    impl RawLock {
+        pub(in crate::executor) const fn new() -> Self {
+            Self {
+                lock: AtomicBool::new(false),
+            }
+        }
+
+        pub(in crate::executor) fn with<R>(&self, f: impl FnOnce() -> R) -> R {
+            while self
+                .lock
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                hint::spin_loop();
+            }
+
+            let result = f();
+            self.lock.store(false, Ordering::Release);
+
+            result
+        }
+    }
+
+    pub(in crate::executor) struct Parker {
+        signaled: Mutex<bool>,
+        condvar: Condvar,
+    }
+
+    impl Parker {
+        pub(in crate::executor) unsafe fn new() -> Self {
+            Self {
+                signaled: Mutex::new(false),
+                condvar: Condvar::new(),
+            }
+        }
+
+        pub(in crate::executor) unsafe fn init(&self) {}
+
+        pub(in crate::executor) fn park(&self) {
+            let mut signaled = self.signaled.lock().unwrap();
+            while !*signaled {
+                signaled = self.condvar.wait(signaled).unwrap();
+            }
+            *signaled = false;
+        }
+
+        pub(in crate::executor) fn unpark(&self) {
+            *self.signaled.lock().unwrap() = true;
+            self.condvar.notify_one();
+        }
+    }
+
+    ///
+    /// Fires from a std thread, cancelling only stops the wake
+    ///
+    pub(in crate::executor) struct SleepTimer {
+        cancelled: StdArc<AtomicBool>,
+    }
+
+    impl SleepTimer {
+        pub(in crate::executor) fn try_start(
+            state: Arc<SleepState>,
+            due: Duration,
+        ) -> anyhow::Result<Self> {
+            let cancelled = StdArc::new(AtomicBool::new(false));
+
+            {
+                let cancelled = cancelled.clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(due);
+                    if !cancelled.load(Ordering::Acquire) {
+                        state.fire();
+                    }
+                });
+            }
+
+            Ok(Self { cancelled })
+        }
+    }
+
+    impl Drop for SleepTimer {
+        fn drop(&mut self) {
+            self.cancelled.store(true, Ordering::Release);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::{
+    collections::vec_deq::VecDeque,
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+    sync::{
+        arc::{Arc, ArcExt},
+        cancel::CancellationToken,
+    },
+};
+
+use super::{atomic_waker::AtomicWaker, backend::RawLock};
+
+struct Inner<T> {
+    lock: RawLock,
+    queue: UnsafeCell<VecDeque<T>>,
+    capacity: usize,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    waker: AtomicWaker,
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> TaggedObject for Inner<T> {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"exch")
+    }
+}
+
+impl<T> Inner<T> {
+    fn with_queue<R>(&self, f: impl FnOnce(&mut VecDeque<T>) -> R) -> R {
+        self.lock.with(|| f(unsafe { &mut *self.queue.get() }))
+    }
+}
+
+///
+/// The token passed to `recv_cancellable` was cancelled
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RecvCancelled;
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum TrySendError<T> {
+    Full(T),
+    ///
+    /// The receiver was dropped
+    ///
+    Closed(T),
+}
+
+///
+/// Bounded multi producer, single consumer channel
+///
+/// The queue is allocated upfront so `try_send` never allocates,
+/// it can be called from callbacks running at DISPATCH_LEVEL as long as `T` is non paged.
+/// `recv` resolves to `None` once every sender was dropped and the queue is empty.
+///
+pub fn channel<T>(capacity: usize) -> anyhow::Result<(Sender<T>, Receiver<T>)> {
+    if capacity == 0 {
+        return Err(anyhow::Error::msg("Channel capacity must not be zero"));
+    }
+
+    let mut queue = VecDeque::new_in(GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"exqu"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    ));
+    queue
+        .try_reserve_exact(capacity)
+        .map_err(|_| anyhow::Error::msg("Failed to reserve channel queue"))?;
+
+    let inner = Arc::try_create(Inner {
+        lock: RawLock::new(),
+        queue: UnsafeCell::new(queue),
+        capacity,
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+        waker: AtomicWaker::new(),
+    })?;
+
+    Ok((
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    ))
+}
+
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Sender<T> {
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if !self.inner.receiver_alive.load(Ordering::Acquire) {
+            return Err(TrySendError::Closed(value));
+        }
+
+        self.inner.with_queue(|queue| {
+            if queue.len() >= self.inner.capacity {
+                return Err(TrySendError::Full(value));
+            }
+
+            //The capacity was reserved when the channel was created
+            queue.push_back(value);
+            Ok(())
+        })?;
+
+        self.inner.waker.wake();
+        Ok(())
+    }
+
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        !self.inner.receiver_alive.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.inner.senders.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.waker.wake();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Receiver<T> {
+    ///
+    /// Resolves to the next value or `None` once all the senders are gone
+    ///
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+
+    ///
+    /// Like `recv` but resolves to `Err(RecvCancelled)` once `token` is cancelled
+    ///
+    /// A queued value is still returned first. The future waits on a child
+    /// of `token`, creating it is the allocation that can fail.
+    ///
+    pub fn recv_cancellable(
+        &mut self,
+        token: &CancellationToken,
+    ) -> anyhow::Result<RecvCancellable<'_, T>> {
+        Ok(RecvCancellable {
+            receiver: self,
+            token: token.try_child()?,
+        })
+    }
+
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.inner.with_queue(|queue| queue.pop_front())
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if let Some(value) = self.try_recv() {
+            return Poll::Ready(Some(value));
+        }
+
+        self.inner.waker.register(cx.waker());
+
+        //Checked again so a value or the last sender drop racing with
+        //the registration is not missed
+        if let Some(value) = self.try_recv() {
+            return Poll::Ready(Some(value));
+        }
+
+        if self.inner.senders.load(Ordering::Acquire) == 0 {
+            return Poll::Ready(self.try_recv());
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.inner.receiver_alive.store(false, Ordering::Release);
+    }
+}
+
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<'a, T> Future for Recv<'a, T> {
+    type Output = Option<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+pub struct RecvCancellable<'a, T> {
+    receiver: &'a mut Receiver<T>,
+    token: CancellationToken,
+}
+
+impl<'a, T> Future for RecvCancellable<'a, T> {
+    type Output = Result<Option<T>, RecvCancelled>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Poll::Ready(value) = self.receiver.poll_recv(cx) {
+            return Poll::Ready(Ok(value));
+        }
+
+        //Registered before the check, `cancel` sets the flag before waking
+        self.token.register_waker(cx.waker());
+        if self.token.is_cancelled() {
+            return Poll::Ready(Err(RecvCancelled));
+        }
+
+        Poll::Pending
+    }
+}
@@ -0,0 +1,418 @@
+//!
+//! Minimal `async` executor
+//!
+//! Futures are spawned on an `Executor` and polled by the threads that call `run`,
+//! `runtime::Runtime` owns a set of system threads doing that.
+//! Every allocation is fallible: spawning returns an error instead of
+//! bugchecking and waking a task never allocates.
+//!
+//! The leaf futures live next to it:
+//! - `reactor` waits for kernel objects like `Event` and `Semaphore`
+//! - `time::sleep` completes from a timer DPC
+//! - `channel` and `oneshot` pass values between tasks and callbacks
+//!
+//! ```ignore
+//! let runtime = Runtime::builder().worker_threads(2).build()?;
+//!
+//! let reply = client.send_message_with_reply_async(&runtime, request, 512, Timeout::infinite())?;
+//! let scan_done = runtime.reactor().wait(scan_event.clone())?;
+//!
+//! let handle = runtime.spawn(async move {
+//!     scan_done.await;
+//!     complete_pended_operation(reply.await?)
+//! })?;
+//! ```
+//!
+
+pub mod atomic_waker;
+mod backend;
+pub mod channel;
+pub mod oneshot;
+mod queue;
+pub mod reactor;
+pub mod runtime;
+mod task;
+pub mod time;
+
+pub use task::JoinHandle;
+
+use core::{
+    future::Future,
+    pin::{pin, Pin},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+
+use crate::{
+    boxed::{Box, BoxExt},
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+    sync::arc::{Arc, ArcExt},
+};
+
+use backend::Parker;
+use queue::{RunQueue, TaskSet};
+use task::{BoxFuture, JoinState, Task, TaskFuture};
+
+struct Shared {
+    queue: RunQueue,
+    tasks: TaskSet,
+    parker: Parker,
+    closed: AtomicBool,
+    //Executor handles, the tasks keep `Shared` alive on their own
+    handles: AtomicUsize,
+}
+
+impl TaggedObject for Shared {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"exsh")
+    }
+}
+
+impl Shared {
+    fn enqueue(&self, task: Arc<Task>) {
+        //The task set keeps the task alive until `close` dropped its future,
+        //so this never drops a future from a DPC
+        if self.is_closed() {
+            return;
+        }
+
+        self.queue.push(task);
+        self.parker.unpark();
+    }
+
+    #[inline]
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+///
+/// Queue of spawned tasks shared by the threads running them
+///
+/// Cloning the executor gives another handle to the same queue.
+/// Dropping the last handle closes the executor, which breaks the reference
+/// cycle between the tasks and the queue, so it must happen at PASSIVE_LEVEL.
+///
+pub struct Executor {
+    shared: Arc<Shared>,
+}
+
+impl Executor {
+    pub fn try_create() -> anyhow::Result<Self> {
+        let shared = Arc::try_create(Shared {
+            queue: RunQueue::new(),
+            tasks: TaskSet::new(),
+            parker: unsafe { Parker::new() },
+            closed: AtomicBool::new(false),
+            handles: AtomicUsize::new(1),
+        })?;
+        unsafe { shared.parker.init() };
+
+        Ok(Self { shared })
+    }
+
+    pub fn spawn<F>(&self, future: F) -> anyhow::Result<JoinHandle<F::Output>>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        if self.is_closed() {
+            return Err(anyhow::Error::msg("Executor is closed"));
+        }
+
+        let join = JoinState::try_create()?;
+        let future: BoxFuture = Box::into_pin(Box::try_create_in(
+            TaskFuture {
+                future,
+                join: join.clone(),
+            },
+            GlobalKernelAllocator::new_for_tagged::<Task>(),
+        )?);
+
+        let task = Task::try_create(future, self.shared.clone())?;
+        if !self.shared.tasks.insert(&task) {
+            return Err(anyhow::Error::msg("Executor is closed"));
+        }
+        task.schedule();
+
+        Ok(JoinHandle::new(join))
+    }
+
+    ///
+    /// Polls tasks on the calling thread until the executor is closed
+    ///
+    pub fn run(&self) {
+        while !self.is_closed() {
+            match self.shared.queue.pop() {
+                Some(task) => {
+                    //Let another parked worker take the rest of the queue
+                    if self.shared.queue.len() != 0 {
+                        self.shared.parker.unpark();
+                    }
+
+                    task.run();
+                }
+                None => self.shared.parker.park(),
+            }
+        }
+
+        //Every parked worker wakes the next one on its way out
+        self.shared.parker.unpark();
+    }
+
+    ///
+    /// Polls the queued tasks on the calling thread until none is ready
+    ///
+    pub fn run_until_stalled(&self) {
+        while !self.is_closed() {
+            match self.shared.queue.pop() {
+                Some(task) => task.run(),
+                None => break,
+            }
+        }
+    }
+
+    ///
+    /// Stops the `run` loops and drops the futures of the tasks that did not complete
+    ///
+    /// A future that a worker is polling is dropped by that worker once the poll returns.
+    /// Wakes coming after the close, e.g. from a timer DPC, only release the task.
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn close(&self) {
+        if self.shared.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        self.shared.parker.unpark();
+        while self.shared.queue.pop().is_some() {}
+
+        self.shared.tasks.close();
+        while let Some(task) = self.shared.tasks.pop() {
+            task.cancel();
+        }
+    }
+
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.shared.is_closed()
+    }
+}
+
+impl Clone for Executor {
+    fn clone(&self) -> Self {
+        self.shared.handles.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        if self.shared.handles.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.close();
+        }
+    }
+}
+
+///
+/// Runs `future` to completion on the calling thread
+///
+/// Spawned tasks are not polled by `block_on`, some thread must `run` the executor.
+///
+pub fn block_on<F: Future>(future: F) -> anyhow::Result<F::Output> {
+    let parker = Arc::try_create(BlockOnParker(unsafe { Parker::new() }))?;
+    unsafe { parker.0.init() };
+
+    let waker = unsafe {
+        Waker::from_raw(RawWaker::new(
+            Arc::into_raw(parker.clone()).cast(),
+            &BLOCK_ON_VTABLE,
+        ))
+    };
+    let mut cx = Context::from_waker(&waker);
+
+    let mut future = pin!(future);
+    loop {
+        match Pin::as_mut(&mut future).poll(&mut cx) {
+            Poll::Ready(output) => return Ok(output),
+            Poll::Pending => parker.0.park(),
+        }
+    }
+}
+
+struct BlockOnParker(Parker);
+
+impl TaggedObject for BlockOnParker {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"exbo")
+    }
+}
+
+static BLOCK_ON_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    block_on_clone,
+    block_on_wake,
+    block_on_wake_by_ref,
+    block_on_drop,
+);
+
+unsafe fn block_on_clone(data: *const ()) -> RawWaker {
+    Arc::increment_strong_count_in(
+        data as *const BlockOnParker,
+        GlobalKernelAllocator::new_for_tagged::<BlockOnParker>(),
+    );
+    RawWaker::new(data, &BLOCK_ON_VTABLE)
+}
+
+unsafe fn block_on_wake(data: *const ()) {
+    block_on_wake_by_ref(data);
+    block_on_drop(data);
+}
+
+unsafe fn block_on_wake_by_ref(data: *const ()) {
+    (*(data as *const BlockOnParker)).0.unpark();
+}
+
+unsafe fn block_on_drop(data: *const ()) {
+    Arc::decrement_strong_count_in(
+        data as *const BlockOnParker,
+        GlobalKernelAllocator::new_for_tagged::<BlockOnParker>(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration,
+    };
+
+    use super::{block_on, channel, oneshot, time, Executor};
+
+    extern crate std;
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let executor = Executor::try_create()?;
+
+        let (tx, mut rx) = channel::channel::<u32>(4)?;
+        let (done_tx, done_rx) = oneshot::channel::<u32>()?;
+
+        let worker = {
+            let executor = executor.clone();
+            std::thread::spawn(move || executor.run())
+        };
+
+        let sum = executor.spawn(async move {
+            let mut sum = 0;
+            while let Some(value) = rx.recv().await {
+                sum += value;
+            }
+
+            sum
+        })?;
+
+        let forward = executor.spawn(async move {
+            let sum = sum.await;
+            let _ = done_tx.send(sum);
+        })?;
+
+        let producer = std::thread::spawn(move || {
+            for value in 1..=10 {
+                while tx.try_send(value).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        assert_eq!(block_on(done_rx)?, Ok(55));
+        block_on(forward)?;
+
+        producer.join().unwrap();
+        executor.close();
+        worker.join().unwrap();
+
+        assert!(executor.spawn(async {}).is_err());
+
+        Ok(())
+    }
+
+    static SLEEPER_DROPS: AtomicU32 = AtomicU32::new(0);
+
+    struct Sleeper;
+
+    impl Drop for Sleeper {
+        fn drop(&mut self) {
+            SLEEPER_DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    static PENDING_DROPS: AtomicU32 = AtomicU32::new(0);
+
+    struct Pending;
+
+    impl Drop for Pending {
+        fn drop(&mut self) {
+            PENDING_DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn drop_last_handle() -> anyhow::Result<()> {
+        let executor = Executor::try_create()?;
+        let (tx, mut rx) = channel::channel::<u32>(1)?;
+
+        let other = executor.clone();
+        let waiting = executor.spawn(async move {
+            let _pending = Pending;
+            rx.recv().await
+        })?;
+
+        executor.run_until_stalled();
+        assert!(!waiting.is_finished());
+
+        drop(executor);
+        assert!(!other.is_closed());
+        assert_eq!(PENDING_DROPS.load(Ordering::SeqCst), 0);
+
+        //The task holds the queue alive, the last handle still closes it
+        drop(other);
+        assert_eq!(PENDING_DROPS.load(Ordering::SeqCst), 1);
+        assert!(tx.is_closed());
+
+        Ok(())
+    }
+
+    #[test]
+    fn close_with_sleeping_task() -> anyhow::Result<()> {
+        let executor = Executor::try_create()?;
+        let (started_tx, started_rx) = oneshot::channel::<()>()?;
+
+        let sleeping = executor.spawn(async move {
+            let _sleeper = Sleeper;
+            let sleep = time::sleep(Duration::from_millis(50)).unwrap();
+
+            let _ = started_tx.send(());
+            sleep.await;
+        })?;
+
+        executor.run_until_stalled();
+        assert_eq!(block_on(started_rx)?, Ok(()));
+        assert!(!sleeping.is_finished());
+
+        //The pending future is dropped by close, not by the timer wake
+        executor.close();
+        assert_eq!(SLEEPER_DROPS.load(Ordering::SeqCst), 1);
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(SLEEPER_DROPS.load(Ordering::SeqCst), 1);
+        assert!(!sleeping.is_finished());
+
+        Ok(())
+    }
+}
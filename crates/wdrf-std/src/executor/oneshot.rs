@@ -0,0 +1,157 @@
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU8, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::{
+    kmalloc::{MemoryTag, TaggedObject},
+    sync::arc::{Arc, ArcExt},
+};
+
+use super::atomic_waker::AtomicWaker;
+
+const EMPTY: u8 = 0;
+const FULL: u8 = 1;
+const CLOSED: u8 = 2;
+
+struct Inner<T> {
+    state: AtomicU8,
+    value: UnsafeCell<Option<T>>,
+    waker: AtomicWaker,
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> TaggedObject for Inner<T> {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"exos")
+    }
+}
+
+///
+/// The sender was dropped without sending a value
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Canceled;
+
+///
+/// Single value channel, the `Receiver` is the future
+///
+/// `send` never blocks or allocates, completion callbacks and DPCs can use it.
+///
+pub fn channel<T>() -> anyhow::Result<(Sender<T>, Receiver<T>)> {
+    let inner = Arc::try_create(Inner {
+        state: AtomicU8::new(EMPTY),
+        value: UnsafeCell::new(None),
+        waker: AtomicWaker::new(),
+    })?;
+
+    Ok((
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    ))
+}
+
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Sender<T> {
+    ///
+    /// Gives the value back if the receiver was dropped
+    ///
+    pub fn send(self, value: T) -> Result<(), T> {
+        if self.inner.state.load(Ordering::Acquire) == CLOSED {
+            return Err(value);
+        }
+
+        //Only the sender writes the value and it is consumed by `send`
+        unsafe { *self.inner.value.get() = Some(value) };
+
+        match self
+            .inner
+            .state
+            .compare_exchange(EMPTY, FULL, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => {
+                //Drop does not close a channel that is already full
+                self.inner.waker.wake();
+                Ok(())
+            }
+            Err(_) => {
+                let value = unsafe { (*self.inner.value.get()).take() };
+                match value {
+                    Some(value) => Err(value),
+                    None => unreachable!(),
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.inner.state.load(Ordering::Acquire) == CLOSED
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self
+            .inner
+            .state
+            .compare_exchange(EMPTY, CLOSED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            self.inner.waker.wake();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Receiver<T> {
+    fn take(&self) -> Option<Result<T, Canceled>> {
+        match self.inner.state.load(Ordering::Acquire) {
+            FULL => match unsafe { (*self.inner.value.get()).take() } {
+                Some(value) => Some(Ok(value)),
+                None => panic!("oneshot::Receiver polled after completion"),
+            },
+            CLOSED => Some(Err(Canceled)),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, Canceled>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(result) = self.take() {
+            return Poll::Ready(result);
+        }
+
+        self.inner.waker.register(cx.waker());
+        match self.take() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        //A value that was already sent is dropped together with `inner`
+        let _ =
+            self.inner
+                .state
+                .compare_exchange(EMPTY, CLOSED, Ordering::AcqRel, Ordering::Acquire);
+    }
+}
@@ -0,0 +1,203 @@
+use core::cell::UnsafeCell;
+
+use crate::sync::arc::Arc;
+
+use super::{backend::RawLock, task::Task};
+
+struct List {
+    head: *const Task,
+    tail: *const Task,
+    len: usize,
+}
+
+///
+/// Intrusive FIFO of scheduled tasks
+///
+/// Tasks are linked through `Task::next` so scheduling never allocates,
+/// the queue owns one strong reference per linked task.
+/// Waking only takes a spin lock so it works at any IRQL up to DISPATCH_LEVEL.
+///
+pub(super) struct RunQueue {
+    lock: RawLock,
+    list: UnsafeCell<List>,
+}
+
+unsafe impl Send for RunQueue {}
+unsafe impl Sync for RunQueue {}
+
+impl RunQueue {
+    pub(super) const fn new() -> Self {
+        Self {
+            lock: RawLock::new(),
+            list: UnsafeCell::new(List {
+                head: core::ptr::null(),
+                tail: core::ptr::null(),
+                len: 0,
+            }),
+        }
+    }
+
+    pub(super) fn push(&self, task: Arc<Task>) {
+        let task = Arc::into_raw(task);
+
+        self.with_list(|list| unsafe {
+            *(*task).next.get() = core::ptr::null();
+
+            if list.tail.is_null() {
+                list.head = task;
+            } else {
+                *(*list.tail).next.get() = task;
+            }
+            list.tail = task;
+            list.len += 1;
+        });
+    }
+
+    pub(super) fn pop(&self) -> Option<Arc<Task>> {
+        let task = self.with_list(|list| unsafe {
+            let task = list.head;
+            if !task.is_null() {
+                list.head = *(*task).next.get();
+                if list.head.is_null() {
+                    list.tail = core::ptr::null();
+                }
+                list.len -= 1;
+            }
+
+            task
+        });
+
+        if task.is_null() {
+            None
+        } else {
+            Some(unsafe { Arc::from_raw_in(task, Task::allocator()) })
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.with_list(|list| list.len)
+    }
+
+    fn with_list<R>(&self, f: impl FnOnce(&mut List) -> R) -> R {
+        self.lock.with(|| f(unsafe { &mut *self.list.get() }))
+    }
+}
+
+impl Drop for RunQueue {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+struct SetList {
+    head: *const Task,
+    closed: bool,
+}
+
+///
+/// Intrusive list of every task that was spawned and did not complete
+///
+/// It owns one strong reference per task so `Executor::close` can drop the
+/// futures at PASSIVE_LEVEL, instead of the last waker dropping them from
+/// whatever context it runs in.
+///
+pub(super) struct TaskSet {
+    lock: RawLock,
+    list: UnsafeCell<SetList>,
+}
+
+unsafe impl Send for TaskSet {}
+unsafe impl Sync for TaskSet {}
+
+impl TaskSet {
+    pub(super) const fn new() -> Self {
+        Self {
+            lock: RawLock::new(),
+            list: UnsafeCell::new(SetList {
+                head: core::ptr::null(),
+                closed: false,
+            }),
+        }
+    }
+
+    ///
+    /// Returns false once the set is closed
+    ///
+    pub(super) fn insert(&self, task: &Arc<Task>) -> bool {
+        self.with_list(|list| unsafe {
+            if list.closed {
+                return false;
+            }
+
+            let task = Arc::into_raw(task.clone());
+            *(*task).set_prev.get() = core::ptr::null();
+            *(*task).set_next.get() = list.head;
+            if !list.head.is_null() {
+                *(*list.head).set_prev.get() = task;
+            }
+            list.head = task;
+            *(*task).in_set.get() = true;
+
+            true
+        })
+    }
+
+    ///
+    /// Unlinks `task` if it is still in the set
+    ///
+    pub(super) fn remove(&self, task: &Task) -> Option<Arc<Task>> {
+        let task = self.with_list(|list| unsafe { Self::unlink(list, task) });
+
+        task.map(|task| unsafe { Arc::from_raw_in(task, Task::allocator()) })
+    }
+
+    ///
+    /// Rejects new tasks, the ones in the set are taken out with `pop`
+    ///
+    pub(super) fn close(&self) {
+        self.with_list(|list| list.closed = true);
+    }
+
+    pub(super) fn pop(&self) -> Option<Arc<Task>> {
+        let task = self.with_list(|list| unsafe {
+            let head = list.head;
+            if head.is_null() {
+                None
+            } else {
+                Self::unlink(list, &*head)
+            }
+        });
+
+        task.map(|task| unsafe { Arc::from_raw_in(task, Task::allocator()) })
+    }
+
+    unsafe fn unlink(list: &mut SetList, task: &Task) -> Option<*const Task> {
+        if !*task.in_set.get() {
+            return None;
+        }
+
+        let prev = *task.set_prev.get();
+        let next = *task.set_next.get();
+        if prev.is_null() {
+            list.head = next;
+        } else {
+            *(*prev).set_next.get() = next;
+        }
+        if !next.is_null() {
+            *(*next).set_prev.get() = prev;
+        }
+        *task.in_set.get() = false;
+
+        Some(task as *const Task)
+    }
+
+    fn with_list<R>(&self, f: impl FnOnce(&mut SetList) -> R) -> R {
+        self.lock.with(|| f(unsafe { &mut *self.list.get() }))
+    }
+}
+
+impl Drop for TaskSet {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
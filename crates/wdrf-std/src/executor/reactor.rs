@@ -0,0 +1,363 @@
+use core::{
+    ffi::c_void,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+use windows_sys::{
+    Wdk::{
+        Foundation::KWAIT_BLOCK,
+        System::SystemServices::{Executive, KeWaitForMultipleObjects, KernelMode},
+    },
+    Win32::{
+        Foundation::{
+            STATUS_ABANDONED_WAIT_0, STATUS_ABANDONED_WAIT_63, STATUS_WAIT_0, STATUS_WAIT_63,
+        },
+        System::{Kernel::WaitAny, SystemServices::MAXIMUM_WAIT_OBJECTS},
+    },
+};
+
+use crate::{
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+    sync::{
+        arc::{Arc, ArcExt},
+        cancel::CancellationToken,
+        ExSpinMutex,
+    },
+    sys::{
+        event::{EventType, KeEvent},
+        WaitResponse, WaitableKernelObject, WaitableObject,
+    },
+    thread::{self, JoinHandle},
+    traits::DispatchSafe,
+    vec::{Vec, VecExt},
+};
+
+use super::atomic_waker::AtomicWaker;
+
+//The stop token and the change event take the first two wait slots
+const RESERVED_OBJECTS: usize = 2;
+const MAX_REGISTRATIONS: usize = MAXIMUM_WAIT_OBJECTS as usize - RESERVED_OBJECTS;
+
+const PENDING: u8 = 0;
+const SIGNALED: u8 = 1;
+const ABANDONED: u8 = 2;
+const STOPPED: u8 = 3;
+const DROPPED: u8 = 4;
+
+type SharedObject = Arc<dyn WaitableObject + Send + Sync>;
+
+struct WaitState {
+    result: AtomicU8,
+    waker: AtomicWaker,
+}
+
+impl TaggedObject for WaitState {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"exws")
+    }
+}
+
+impl WaitState {
+    fn complete(&self, result: u8) {
+        if self
+            .result
+            .compare_exchange(PENDING, result, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            self.waker.wake();
+        }
+    }
+}
+
+struct Registration {
+    object: SharedObject,
+    state: Arc<WaitState>,
+}
+
+struct Registrations(Vec<Registration>);
+
+unsafe impl DispatchSafe for Registrations {}
+
+struct ReactorShared {
+    pending: ExSpinMutex<Registrations>,
+    registered: AtomicUsize,
+    changed: KeEvent,
+    stop: CancellationToken,
+}
+
+unsafe impl Send for ReactorShared {}
+unsafe impl Sync for ReactorShared {}
+
+impl TaggedObject for ReactorShared {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"exrs")
+    }
+}
+
+///
+/// Turns kernel object waits into futures
+///
+/// A dedicated system thread waits on every registered object with
+/// `KeWaitForMultipleObjects` and wakes the task once its object is signaled.
+/// At most `MAXIMUM_WAIT_OBJECTS - 2` waits can be pending at the same time.
+///
+/// The wait is satisfied by the reactor thread, a `Semaphore` count or a
+/// synchronization `Event` is consumed even if the future is dropped right after.
+///
+pub struct Reactor {
+    shared: Arc<ReactorShared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Reactor {
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn try_create() -> anyhow::Result<Self> {
+        let mut pending = Vec::new_in(allocator());
+        pending
+            .try_reserve_exact(MAX_REGISTRATIONS)
+            .map_err(|_| anyhow::Error::msg("Failed to reserve reactor registrations"))?;
+
+        let shared = Arc::try_create(ReactorShared {
+            pending: ExSpinMutex::new(Registrations(pending)),
+            registered: AtomicUsize::new(0),
+            changed: unsafe { KeEvent::new() },
+            stop: CancellationToken::try_create()?,
+        })?;
+        unsafe { shared.changed.init(EventType::Synchronization, false) };
+
+        let mut state = ReactorThread {
+            shared: shared.clone(),
+            active: Vec::new_in(allocator()),
+            wait_blocks: Vec::new_in(allocator()),
+        };
+        state
+            .active
+            .try_reserve_exact(MAX_REGISTRATIONS)
+            .map_err(|_| anyhow::Error::msg("Failed to reserve reactor registrations"))?;
+        state
+            .wait_blocks
+            .try_resize(MAXIMUM_WAIT_OBJECTS as usize, unsafe {
+                core::mem::zeroed::<KWAIT_BLOCK>()
+            })?;
+
+        let thread = thread::Builder::new()
+            .name("wdrf-reactor")
+            .spawn(move || state.run())
+            .map_err(|_| anyhow::Error::msg("Failed to spawn the reactor thread"))?;
+
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    ///
+    /// Resolves once `object` is signaled
+    ///
+    /// The future returns `WaitResponse::Success`, `WaitResponse::Abandoned(0)`
+    /// for an abandoned mutex or `WaitResponse::Cancelled` if the reactor stopped.
+    ///
+    pub fn wait<W>(&self, object: Arc<W>) -> anyhow::Result<WaitFuture>
+    where
+        W: WaitableObject + Send + Sync + 'static,
+    {
+        if self.shared.stop.is_cancelled() {
+            return Err(anyhow::Error::msg("Reactor is stopped"));
+        }
+
+        if self.shared.registered.fetch_add(1, Ordering::AcqRel) >= MAX_REGISTRATIONS {
+            self.shared.registered.fetch_sub(1, Ordering::AcqRel);
+            return Err(anyhow::Error::msg("Reactor has too many pending waits"));
+        }
+
+        let state = match Arc::try_create(WaitState {
+            result: AtomicU8::new(PENDING),
+            waker: AtomicWaker::new(),
+        }) {
+            Ok(state) => state,
+            Err(e) => {
+                self.shared.registered.fetch_sub(1, Ordering::AcqRel);
+                return Err(e);
+            }
+        };
+
+        //`registered` bounds the pending registrations, the push never allocates
+        self.shared.pending.write_raw().0.push(Registration {
+            object,
+            state: state.clone(),
+        });
+        self.shared.changed.signal();
+
+        Ok(WaitFuture {
+            state,
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+impl Drop for Reactor {
+    fn drop(&mut self) {
+        self.shared.stop.cancel();
+
+        if let Some(thread) = self.thread.take() {
+            thread.join();
+        }
+    }
+}
+
+pub struct WaitFuture {
+    state: Arc<WaitState>,
+    shared: Arc<ReactorShared>,
+}
+
+impl WaitFuture {
+    fn result(&self) -> Option<WaitResponse> {
+        match self.state.result.load(Ordering::Acquire) {
+            SIGNALED => Some(WaitResponse::Success),
+            ABANDONED => Some(WaitResponse::Abandoned(0)),
+            STOPPED => Some(WaitResponse::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+impl Future for WaitFuture {
+    type Output = WaitResponse;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WaitResponse> {
+        if let Some(result) = self.result() {
+            return Poll::Ready(result);
+        }
+
+        self.state.waker.register(cx.waker());
+        match self.result() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for WaitFuture {
+    fn drop(&mut self) {
+        if self
+            .state
+            .result
+            .compare_exchange(PENDING, DROPPED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            //Lets the reactor stop waiting on the object
+            self.shared.changed.signal();
+        }
+    }
+}
+
+struct ReactorThread {
+    shared: Arc<ReactorShared>,
+    active: Vec<Registration>,
+    wait_blocks: Vec<KWAIT_BLOCK>,
+}
+
+unsafe impl Send for ReactorThread {}
+
+impl ReactorThread {
+    fn run(mut self) {
+        let mut objects = [core::ptr::null::<c_void>(); MAXIMUM_WAIT_OBJECTS as usize];
+        objects[0] = Self::object_ptr(&self.shared.stop);
+        objects[1] = Self::object_ptr(&self.shared.changed);
+
+        loop {
+            self.refresh();
+
+            for (slot, registration) in objects[RESERVED_OBJECTS..]
+                .iter_mut()
+                .zip(self.active.iter())
+            {
+                *slot = Self::object_ptr(&*registration.object);
+            }
+
+            let count = RESERVED_OBJECTS + self.active.len();
+            let status = unsafe {
+                KeWaitForMultipleObjects(
+                    count as _,
+                    objects.as_ptr(),
+                    WaitAny,
+                    Executive,
+                    KernelMode as _,
+                    false as _,
+                    core::ptr::null(),
+                    self.wait_blocks.as_mut_ptr(),
+                )
+            };
+
+            let (index, result) = match status {
+                STATUS_WAIT_0..=STATUS_WAIT_63 => ((status - STATUS_WAIT_0) as usize, SIGNALED),
+                STATUS_ABANDONED_WAIT_0..=STATUS_ABANDONED_WAIT_63 => {
+                    ((status - STATUS_ABANDONED_WAIT_0) as usize, ABANDONED)
+                }
+                _ => panic!("Unexpected reactor wait status: {}", status),
+            };
+
+            match index {
+                0 => break,
+                1 => {}
+                _ => {
+                    let registration = self.active.swap_remove(index - RESERVED_OBJECTS);
+                    self.finish(registration, result);
+                }
+            }
+        }
+
+        self.refresh();
+        while let Some(registration) = self.active.pop() {
+            self.finish(registration, STOPPED);
+        }
+    }
+
+    ///
+    /// Picks up new registrations and forgets the ones whose future was dropped
+    ///
+    fn refresh(&mut self) {
+        {
+            let mut pending = self.shared.pending.write_raw();
+            //Both vectors hold at most `MAX_REGISTRATIONS` entries together
+            self.active.extend(pending.0.drain(..));
+        }
+
+        let mut index = 0;
+        while index < self.active.len() {
+            if self.active[index].state.result.load(Ordering::Acquire) == DROPPED {
+                let registration = self.active.swap_remove(index);
+                self.finish(registration, DROPPED);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    fn finish(&self, registration: Registration, result: u8) {
+        registration.state.complete(result);
+        self.shared.registered.fetch_sub(1, Ordering::AcqRel);
+
+        //The object reference is released here, at PASSIVE_LEVEL
+        drop(registration);
+    }
+
+    fn object_ptr(object: &dyn WaitableObject) -> *const c_void {
+        let ptr: *const WaitableKernelObject = object.kernel_object();
+        ptr.cast()
+    }
+}
+
+fn allocator() -> GlobalKernelAllocator {
+    GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"exrr"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    )
+}
@@ -0,0 +1,181 @@
+use core::future::Future;
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+
+use crate::{
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    thread,
+    vec::{Vec, VecExt},
+    workqueue::pool::{TaskPriority, ThreadPool},
+};
+
+use super::{block_on, oneshot, reactor::Reactor, Executor, JoinHandle};
+
+///
+/// Configures a `Runtime`
+///
+/// ```ignore
+/// let runtime = Runtime::builder()
+///     .worker_threads(2)
+///     .blocking_threads(1)
+///     .build()?;
+/// ```
+///
+pub struct Builder {
+    worker_threads: usize,
+    blocking_threads: usize,
+    blocking_capacity: usize,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            worker_threads: 1,
+            blocking_threads: 1,
+            blocking_capacity: 64,
+        }
+    }
+
+    ///
+    /// Threads polling the futures
+    ///
+    pub fn worker_threads(mut self, threads: usize) -> Self {
+        self.worker_threads = threads;
+        self
+    }
+
+    ///
+    /// Threads running the closures passed to `spawn_blocking`
+    ///
+    pub fn blocking_threads(mut self, threads: usize) -> Self {
+        self.blocking_threads = threads;
+        self
+    }
+
+    pub fn blocking_capacity(mut self, capacity: usize) -> Self {
+        self.blocking_capacity = capacity;
+        self
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn build(self) -> anyhow::Result<Runtime> {
+        if self.worker_threads == 0 {
+            return Err(anyhow::Error::msg(
+                "Runtime needs at least one worker thread",
+            ));
+        }
+
+        let executor = Executor::try_create()?;
+        let reactor = Reactor::try_create()?;
+        let blocking = ThreadPool::try_create(self.blocking_threads, self.blocking_capacity)?;
+
+        let mut runtime = Runtime {
+            executor,
+            reactor,
+            blocking,
+            workers: Vec::new_in(GlobalKernelAllocator::new(
+                MemoryTag::new_from_bytes(b"exwk"),
+                PoolFlags::POOL_FLAG_NON_PAGED,
+            )),
+        };
+
+        runtime
+            .workers
+            .try_reserve_exact(self.worker_threads)
+            .map_err(|_| anyhow::Error::msg("Failed to reserve runtime workers"))?;
+        for _ in 0..self.worker_threads {
+            let executor = runtime.executor.clone();
+            let worker = thread::Builder::new()
+                .name("wdrf-executor")
+                .spawn(move || executor.run())
+                .map_err(|_| anyhow::Error::msg("Failed to spawn runtime worker"))?;
+
+            //Capacity was reserved above
+            runtime.workers.try_push(worker)?;
+        }
+
+        Ok(runtime)
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// Executor driven by system threads together with its reactor
+/// and a pool for blocking calls
+///
+/// Dropping the runtime stops the workers and drops the tasks that did not complete,
+/// it must happen at PASSIVE_LEVEL and not from one of the runtime threads.
+///
+pub struct Runtime {
+    executor: Executor,
+    reactor: Reactor,
+    blocking: ThreadPool,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl Runtime {
+    #[inline]
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    pub fn spawn<F>(&self, future: F) -> anyhow::Result<JoinHandle<F::Output>>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.executor.spawn(future)
+    }
+
+    ///
+    /// Runs `future` on the calling thread, which must not be a runtime worker
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn block_on<F: Future>(&self, future: F) -> anyhow::Result<F::Output> {
+        block_on(future)
+    }
+
+    ///
+    /// Runs a blocking call on the blocking pool and resolves to its result
+    ///
+    pub fn spawn_blocking<T, F>(&self, f: F) -> anyhow::Result<oneshot::Receiver<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel()?;
+        self.blocking.execute(TaskPriority::Normal, move || {
+            let _ = sender.send(f());
+        })?;
+
+        Ok(receiver)
+    }
+
+    #[inline]
+    pub fn executor(&self) -> &Executor {
+        &self.executor
+    }
+
+    #[inline]
+    pub fn reactor(&self) -> &Reactor {
+        &self.reactor
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        self.executor.close();
+
+        while let Some(worker) = self.workers.pop() {
+            worker.join();
+        }
+    }
+}
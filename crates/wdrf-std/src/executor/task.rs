@@ -0,0 +1,281 @@
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use crate::{
+    boxed::Box,
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+    sync::arc::{Arc, ArcExt},
+};
+
+use super::{atomic_waker::AtomicWaker, Shared};
+
+pub(super) type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+const IDLE: u8 = 0;
+const SCHEDULED: u8 = 1;
+const RUNNING: u8 = 2;
+//Woken while being polled, polled again right after
+const NOTIFIED: u8 = 3;
+const COMPLETE: u8 = 4;
+
+pub(super) struct Task {
+    state: AtomicU8,
+    future: UnsafeCell<Option<BoxFuture>>,
+    pub(super) next: UnsafeCell<*const Task>,
+    //Links of the executor `TaskSet`, guarded by its lock
+    pub(super) set_prev: UnsafeCell<*const Task>,
+    pub(super) set_next: UnsafeCell<*const Task>,
+    pub(super) in_set: UnsafeCell<bool>,
+    shared: Arc<Shared>,
+}
+
+unsafe impl Send for Task {}
+unsafe impl Sync for Task {}
+
+impl TaggedObject for Task {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"extk")
+    }
+}
+
+impl Task {
+    pub(super) fn try_create(future: BoxFuture, shared: Arc<Shared>) -> anyhow::Result<Arc<Self>> {
+        Arc::try_create(Self {
+            state: AtomicU8::new(IDLE),
+            future: UnsafeCell::new(Some(future)),
+            next: UnsafeCell::new(core::ptr::null()),
+            set_prev: UnsafeCell::new(core::ptr::null()),
+            set_next: UnsafeCell::new(core::ptr::null()),
+            in_set: UnsafeCell::new(false),
+            shared,
+        })
+    }
+
+    #[inline]
+    pub(super) fn allocator() -> GlobalKernelAllocator {
+        GlobalKernelAllocator::new_for_tagged::<Self>()
+    }
+
+    pub(super) fn schedule(self: &Arc<Self>) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let next = match state {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+
+            match self
+                .state
+                .compare_exchange(state, next, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => break,
+                Err(actual) => state = actual,
+            }
+        }
+
+        if state == IDLE {
+            self.shared.enqueue(self.clone());
+        }
+    }
+
+    ///
+    /// Polls the future once, only called by the worker that popped the task
+    ///
+    pub(super) fn run(self: Arc<Self>) {
+        //Cancelled by `Executor::close` while it was queued
+        if self
+            .state
+            .compare_exchange(SCHEDULED, RUNNING, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return;
+        }
+
+        let waker = unsafe { Waker::from_raw(raw_waker(&self)) };
+        let mut cx = Context::from_waker(&waker);
+
+        let future = unsafe { &mut *self.future.get() };
+        let completed = match future {
+            Some(ref mut f) => f.as_mut().poll(&mut cx).is_ready(),
+            None => true,
+        };
+
+        if completed {
+            *future = None;
+            self.state.store(COMPLETE, Ordering::Release);
+            self.shared.tasks.remove(&self);
+            return;
+        }
+
+        //`close` skips a task that is being polled, the state is published
+        //before reading `closed` so either this worker or `close` drops the future
+        if self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            if self.shared.is_closed() {
+                self.cancel();
+            }
+            return;
+        }
+
+        //Woken during the poll, the wake left the scheduling to us
+        self.state.store(SCHEDULED, Ordering::SeqCst);
+        if self.shared.is_closed() {
+            self.cancel();
+        } else {
+            self.shared.enqueue(self.clone());
+        }
+    }
+
+    ///
+    /// Drops the future of an idle or queued task, a task being polled
+    /// is left to its worker
+    ///
+    pub(super) fn cancel(&self) {
+        let mut state = self.state.load(Ordering::SeqCst);
+        loop {
+            if state != IDLE && state != SCHEDULED {
+                return;
+            }
+
+            match self
+                .state
+                .compare_exchange(state, COMPLETE, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => break,
+                Err(actual) => state = actual,
+            }
+        }
+
+        unsafe { *self.future.get() = None };
+    }
+}
+
+fn raw_waker(task: &Arc<Task>) -> RawWaker {
+    let task = Arc::into_raw(task.clone());
+    RawWaker::new(task.cast(), &WAKER_VTABLE)
+}
+
+static WAKER_VTABLE: RawWakerVTable =
+    RawWakerVTable::new(waker_clone, waker_wake, waker_wake_by_ref, waker_drop);
+
+unsafe fn waker_clone(data: *const ()) -> RawWaker {
+    Arc::increment_strong_count_in(data as *const Task, Task::allocator());
+    RawWaker::new(data, &WAKER_VTABLE)
+}
+
+unsafe fn waker_wake(data: *const ()) {
+    let task = Arc::from_raw_in(data as *const Task, Task::allocator());
+    task.schedule();
+}
+
+unsafe fn waker_wake_by_ref(data: *const ()) {
+    let task =
+        core::mem::ManuallyDrop::new(Arc::from_raw_in(data as *const Task, Task::allocator()));
+    task.schedule();
+}
+
+unsafe fn waker_drop(data: *const ()) {
+    Arc::decrement_strong_count_in(data as *const Task, Task::allocator());
+}
+
+pub(super) struct JoinState<T> {
+    done: AtomicBool,
+    result: UnsafeCell<Option<T>>,
+    waker: AtomicWaker,
+}
+
+unsafe impl<T: Send> Send for JoinState<T> {}
+unsafe impl<T: Send> Sync for JoinState<T> {}
+
+impl<T> TaggedObject for JoinState<T> {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"exjn")
+    }
+}
+
+impl<T> JoinState<T> {
+    pub(super) fn try_create() -> anyhow::Result<Arc<Self>> {
+        Arc::try_create(Self {
+            done: AtomicBool::new(false),
+            result: UnsafeCell::new(None),
+            waker: AtomicWaker::new(),
+        })
+    }
+}
+
+///
+/// Runs the spawned future and hands its output to the `JoinHandle`
+///
+pub(super) struct TaskFuture<F: Future> {
+    pub(super) future: F,
+    pub(super) join: Arc<JoinState<F::Output>>,
+}
+
+impl<F: Future> Future for TaskFuture<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        //The future is never moved out of the pinned task
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        match future.poll(cx) {
+            Poll::Ready(output) => {
+                unsafe { *this.join.result.get() = Some(output) };
+                this.join.done.store(true, Ordering::Release);
+                this.join.waker.wake();
+
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+///
+/// Resolves to the output of a spawned task
+///
+/// Dropping the handle detaches the task, it keeps running.
+///
+pub struct JoinHandle<T> {
+    join: Arc<JoinState<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub(super) fn new(join: Arc<JoinState<T>>) -> Self {
+        Self { join }
+    }
+
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.join.done.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        if !self.is_finished() {
+            self.join.waker.register(cx.waker());
+
+            if !self.is_finished() {
+                return Poll::Pending;
+            }
+        }
+
+        match unsafe { (*self.join.result.get()).take() } {
+            Some(output) => Poll::Ready(output),
+            None => panic!("JoinHandle polled after completion"),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::DISPATCH_LEVEL;
+
+use crate::{
+    kmalloc::{MemoryTag, TaggedObject},
+    sync::arc::{Arc, ArcExt},
+};
+
+use super::{atomic_waker::AtomicWaker, backend::SleepTimer};
+
+pub(super) struct SleepState {
+    fired: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl TaggedObject for SleepState {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"exsl")
+    }
+}
+
+impl SleepState {
+    pub(super) fn fire(&self) {
+        self.fired.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+///
+/// Completes once `duration` elapsed
+///
+/// The timer is armed right away, not on the first poll.
+/// Dropping the future cancels the timer without waiting for its DPC,
+/// so it can happen at DISPATCH_LEVEL, e.g. from the DPC waking another task.
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+pub fn sleep(duration: Duration) -> anyhow::Result<Sleep> {
+    let state = Arc::try_create(SleepState {
+        fired: AtomicBool::new(false),
+        waker: AtomicWaker::new(),
+    })?;
+
+    let timer = SleepTimer::try_start(state.clone(), duration)?;
+
+    Ok(Sleep {
+        state,
+        _timer: timer,
+    })
+}
+
+pub struct Sleep {
+    state: Arc<SleepState>,
+    //Cancelled when the future is dropped
+    _timer: SleepTimer,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.state.fired.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+
+        self.state.waker.register(cx.waker());
+        if self.state.fired.load(Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use windows_sys::{
+    Wdk::Storage::FileSystem::FILE_NETWORK_OPEN_INFORMATION,
+    Win32::Storage::FileSystem::{FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_READONLY},
+};
+
+use crate::time::SystemTime;
+
+///
+/// Snapshot of `FileNetworkOpenInformation` for an open file
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    len: u64,
+    allocation_size: u64,
+    attributes: u32,
+    created: u64,
+    accessed: u64,
+    modified: u64,
+    changed: u64,
+}
+
+impl Metadata {
+    pub(super) fn from_info(info: &FILE_NETWORK_OPEN_INFORMATION) -> Self {
+        Self {
+            len: info.EndOfFile as u64,
+            allocation_size: info.AllocationSize as u64,
+            attributes: info.FileAttributes,
+            created: info.CreationTime as u64,
+            accessed: info.LastAccessTime as u64,
+            modified: info.LastWriteTime as u64,
+            changed: info.ChangeTime as u64,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    ///
+    /// Bytes reserved on disk, usually a multiple of the cluster size
+    ///
+    #[inline]
+    pub fn allocation_size(&self) -> u64 {
+        self.allocation_size
+    }
+
+    ///
+    /// Raw `FILE_ATTRIBUTE_*` flags
+    ///
+    #[inline]
+    pub fn attributes(&self) -> u32 {
+        self.attributes
+    }
+
+    #[inline]
+    pub fn is_dir(&self) -> bool {
+        self.attributes & FILE_ATTRIBUTE_DIRECTORY != 0
+    }
+
+    #[inline]
+    pub fn is_file(&self) -> bool {
+        !self.is_dir()
+    }
+
+    #[inline]
+    pub fn is_readonly(&self) -> bool {
+        self.attributes & FILE_ATTRIBUTE_READONLY != 0
+    }
+
+    #[inline]
+    pub fn created(&self) -> SystemTime {
+        SystemTime::from_raw(self.created)
+    }
+
+    #[inline]
+    pub fn accessed(&self) -> SystemTime {
+        SystemTime::from_raw(self.accessed)
+    }
+
+    #[inline]
+    pub fn modified(&self) -> SystemTime {
+        SystemTime::from_raw(self.modified)
+    }
+
+    ///
+    /// Last change of the data or the attributes
+    ///
+    #[inline]
+    pub fn changed(&self) -> SystemTime {
+        SystemTime::from_raw(self.changed)
+    }
+}
@@ -0,0 +1,364 @@
+//!
+//! File access from drivers through the `Zw*File` routines
+//!
+//! Paths are NT paths such as `\??\C:\dir\file.txt` or
+//! `\SystemRoot\...`. Every call must be made at PASSIVE_LEVEL, usually
+//! from a worker thread, never from inside a file system callback that
+//! holds locks the file system may need.
+//!
+
+mod metadata;
+mod open_options;
+
+pub use metadata::Metadata;
+pub use open_options::OpenOptions;
+
+use core::mem::size_of;
+
+use nt_string::unicode_string::NtUnicodeStr;
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+use windows_sys::{
+    Wdk::{
+        Storage::FileSystem::{
+            ZwFlushBuffersFile, FILE_DISPOSITION_INFORMATION, FILE_NETWORK_OPEN_INFORMATION,
+            FILE_POSITION_INFORMATION, FILE_RENAME_INFORMATION, FILE_STANDARD_INFORMATION,
+        },
+        System::SystemServices::{
+            ZwQueryInformationFile, ZwReadFile, ZwSetInformationFile, ZwWriteFile,
+            FILE_END_OF_FILE_INFORMATION,
+        },
+    },
+    Win32::{
+        Foundation::{STATUS_END_OF_FILE, STATUS_INVALID_PARAMETER, STATUS_NO_MEMORY},
+        System::{WindowsProgramming::FILE_INFORMATION_CLASS, IO::IO_STATUS_BLOCK},
+    },
+};
+
+use crate::{
+    constants::PoolFlags,
+    io::{self, Read, Seek, SeekFrom, Write},
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    object::handle::Handle,
+    vec::Vec,
+    NtResult, NtResultEx, NtStatusError,
+};
+
+//Not exported by windows-sys
+const FILE_RENAME_INFORMATION_CLASS: FILE_INFORMATION_CLASS = 10;
+const FILE_DISPOSITION_INFORMATION_CLASS: FILE_INFORMATION_CLASS = 13;
+const FILE_POSITION_INFORMATION_CLASS: FILE_INFORMATION_CLASS = 14;
+const FILE_STANDARD_INFORMATION_CLASS: FILE_INFORMATION_CLASS = 5;
+const FILE_END_OF_FILE_INFORMATION_CLASS: FILE_INFORMATION_CLASS = 20;
+const FILE_NETWORK_OPEN_INFORMATION_CLASS: FILE_INFORMATION_CLASS = 34;
+
+///
+/// An open file, closed when dropped
+///
+pub struct File {
+    handle: Handle,
+}
+
+impl File {
+    pub(crate) fn from_handle(handle: Handle) -> Self {
+        Self { handle }
+    }
+
+    pub fn options() -> OpenOptions {
+        OpenOptions::new()
+    }
+
+    ///
+    /// Opens an existing file for reading
+    ///
+    pub fn open(path: &NtUnicodeStr<'_>) -> NtResult<Self> {
+        OpenOptions::new().read(true).open(path)
+    }
+
+    ///
+    /// Creates the file for writing, truncating it if it exists
+    ///
+    pub fn create(path: &NtUnicodeStr<'_>) -> NtResult<Self> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+    }
+
+    #[inline]
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn read_buf(&mut self, buf: &mut [u8]) -> NtResult<usize> {
+        let length = core::cmp::min(buf.len(), u32::MAX as usize) as u32;
+
+        unsafe {
+            let mut iosb: IO_STATUS_BLOCK = core::mem::zeroed();
+            let status = ZwReadFile(
+                self.handle.raw_handle(),
+                0,
+                None,
+                core::ptr::null(),
+                &mut iosb,
+                buf.as_mut_ptr().cast(),
+                length,
+                core::ptr::null(),
+                core::ptr::null(),
+            );
+
+            if status == STATUS_END_OF_FILE {
+                Ok(0)
+            } else {
+                NtResult::from_status(status, || iosb.Information)
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn write_buf(&mut self, buf: &[u8]) -> NtResult<usize> {
+        let length = core::cmp::min(buf.len(), u32::MAX as usize) as u32;
+
+        unsafe {
+            let mut iosb: IO_STATUS_BLOCK = core::mem::zeroed();
+            let status = ZwWriteFile(
+                self.handle.raw_handle(),
+                0,
+                None,
+                core::ptr::null(),
+                &mut iosb,
+                buf.as_ptr().cast(),
+                length,
+                core::ptr::null(),
+                core::ptr::null(),
+            );
+
+            NtResult::from_status(status, || iosb.Information)
+        }
+    }
+
+    ///
+    /// Flushes the cached data of the file to disk
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn sync_all(&self) -> NtResult<()> {
+        unsafe {
+            let mut iosb: IO_STATUS_BLOCK = core::mem::zeroed();
+            let status = ZwFlushBuffersFile(self.handle.raw_handle(), &mut iosb);
+
+            NtResult::from_status(status, || ())
+        }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn metadata(&self) -> NtResult<Metadata> {
+        let info: FILE_NETWORK_OPEN_INFORMATION =
+            self.query_information(FILE_NETWORK_OPEN_INFORMATION_CLASS)?;
+
+        Ok(Metadata::from_info(&info))
+    }
+
+    ///
+    /// Truncates or extends the file, extended bytes read as zero
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn set_len(&self, len: u64) -> NtResult<()> {
+        let info = FILE_END_OF_FILE_INFORMATION {
+            EndOfFile: to_offset(len)?,
+        };
+
+        self.set_information(&info, FILE_END_OF_FILE_INFORMATION_CLASS)
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn stream_position(&self) -> NtResult<u64> {
+        let info: FILE_POSITION_INFORMATION =
+            self.query_information(FILE_POSITION_INFORMATION_CLASS)?;
+
+        Ok(info.CurrentByteOffset as u64)
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn seek_to(&self, pos: SeekFrom) -> NtResult<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => return self.set_position(offset),
+            SeekFrom::Current(delta) => (self.stream_position()?, delta),
+            SeekFrom::End(delta) => {
+                let info: FILE_STANDARD_INFORMATION =
+                    self.query_information(FILE_STANDARD_INFORMATION_CLASS)?;
+                (info.EndOfFile as u64, delta)
+            }
+        };
+
+        let offset = base
+            .checked_add_signed(delta)
+            .ok_or(NtStatusError::Status(STATUS_INVALID_PARAMETER))?;
+
+        self.set_position(offset)
+    }
+
+    ///
+    /// The file is deleted once the last handle to it is closed
+    ///
+    /// Requires the file to be opened with `OpenOptions::delete`.
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn mark_for_delete(&self, delete: bool) -> NtResult<()> {
+        let info = FILE_DISPOSITION_INFORMATION {
+            DeleteFile: delete as _,
+        };
+
+        self.set_information(&info, FILE_DISPOSITION_INFORMATION_CLASS)
+    }
+
+    ///
+    /// Renames the open file to the full NT path `new_path`
+    ///
+    /// Requires the file to be opened with `OpenOptions::delete`.
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn rename(&self, new_path: &NtUnicodeStr<'_>, replace_if_exists: bool) -> NtResult<()> {
+        let name_offset = core::mem::offset_of!(FILE_RENAME_INFORMATION, FileName);
+        let name = new_path.as_slice();
+        let name_length = core::mem::size_of_val(name);
+        let size = core::cmp::max(
+            name_offset + name_length,
+            size_of::<FILE_RENAME_INFORMATION>(),
+        );
+
+        //u64 storage keeps the structure aligned
+        let mut buffer: Vec<u64> = Vec::new_in(rename_allocator());
+        let words = size.div_ceil(size_of::<u64>());
+        buffer
+            .try_reserve_exact(words)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        buffer.resize(words, 0);
+
+        unsafe {
+            let info: *mut FILE_RENAME_INFORMATION = buffer.as_mut_ptr().cast();
+            (*info).Anonymous.ReplaceIfExists = replace_if_exists as _;
+            (*info).RootDirectory = 0;
+            (*info).FileNameLength = name_length as u32;
+            core::ptr::copy_nonoverlapping(
+                name.as_ptr().cast::<u8>(),
+                info.cast::<u8>().add(name_offset),
+                name_length,
+            );
+
+            let mut iosb: IO_STATUS_BLOCK = core::mem::zeroed();
+            let status = ZwSetInformationFile(
+                self.handle.raw_handle(),
+                &mut iosb,
+                info.cast(),
+                size as u32,
+                FILE_RENAME_INFORMATION_CLASS,
+            );
+
+            NtResult::from_status(status, || ())
+        }
+    }
+
+    fn set_position(&self, offset: u64) -> NtResult<u64> {
+        let info = FILE_POSITION_INFORMATION {
+            CurrentByteOffset: to_offset(offset)?,
+        };
+
+        self.set_information(&info, FILE_POSITION_INFORMATION_CLASS)?;
+        Ok(offset)
+    }
+
+    fn query_information<I>(&self, class: FILE_INFORMATION_CLASS) -> NtResult<I> {
+        unsafe {
+            let mut info: I = core::mem::zeroed();
+            let mut iosb: IO_STATUS_BLOCK = core::mem::zeroed();
+
+            let status = ZwQueryInformationFile(
+                self.handle.raw_handle(),
+                &mut iosb,
+                (&mut info as *mut I).cast(),
+                size_of::<I>() as u32,
+                class,
+            );
+
+            NtResult::from_status(status, || info)
+        }
+    }
+
+    fn set_information<I>(&self, info: &I, class: FILE_INFORMATION_CLASS) -> NtResult<()> {
+        unsafe {
+            let mut iosb: IO_STATUS_BLOCK = core::mem::zeroed();
+
+            let status = ZwSetInformationFile(
+                self.handle.raw_handle(),
+                &mut iosb,
+                (info as *const I).cast(),
+                size_of::<I>() as u32,
+                class,
+            );
+
+            NtResult::from_status(status, || ())
+        }
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.read_buf(buf)?)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(self.write_buf(buf)?)
+    }
+
+    ///
+    /// Writes go straight to the file system, there is nothing to flush.
+    /// `sync_all` forces the cached data to disk
+    ///
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        Ok(self.seek_to(pos)?)
+    }
+}
+
+fn to_offset(offset: u64) -> NtResult<i64> {
+    i64::try_from(offset).map_err(|_| NtStatusError::Status(STATUS_INVALID_PARAMETER))
+}
+
+fn rename_allocator() -> GlobalKernelAllocator {
+    GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"fsrn"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    )
+}
+
+///
+/// Deletes the file at `path`
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+pub fn remove_file(path: &NtUnicodeStr<'_>) -> NtResult<()> {
+    let file = OpenOptions::new().delete(true).open(path)?;
+    file.mark_for_delete(true)
+}
+
+///
+/// Renames the file at `from` to `to`, both full NT paths
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+pub fn rename(
+    from: &NtUnicodeStr<'_>,
+    to: &NtUnicodeStr<'_>,
+    replace_if_exists: bool,
+) -> NtResult<()> {
+    let file = OpenOptions::new().delete(true).open(from)?;
+    file.rename(to, replace_if_exists)
+}
@@ -0,0 +1,239 @@
+use nt_string::unicode_string::NtUnicodeStr;
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+use windows_sys::{
+    Wdk::{
+        Storage::FileSystem::{
+            FILE_CREATE, FILE_NON_DIRECTORY_FILE, FILE_OPEN, FILE_OPEN_IF, FILE_OVERWRITE,
+            FILE_OVERWRITE_IF, FILE_SYNCHRONOUS_IO_NONALERT,
+        },
+        System::SystemServices::ZwCreateFile,
+    },
+    Win32::{
+        Foundation::{HANDLE, STATUS_INVALID_PARAMETER},
+        Storage::FileSystem::{
+            DELETE, FILE_APPEND_DATA, FILE_ATTRIBUTE_NORMAL, FILE_GENERIC_READ, FILE_GENERIC_WRITE,
+            FILE_READ_ATTRIBUTES, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+            FILE_WRITE_DATA, SYNCHRONIZE,
+        },
+        System::{
+            Kernel::{OBJ_CASE_INSENSITIVE, OBJ_KERNEL_HANDLE},
+            IO::IO_STATUS_BLOCK,
+        },
+    },
+};
+
+use crate::{
+    object::{attribute::ObjectAttributes, handle::Handle, KernelObjectType},
+    NtResult, NtResultEx, NtStatusError,
+};
+
+use super::File;
+
+///
+/// Options for opening a `File`, mirrors `std::fs::OpenOptions`
+///
+/// The handle is always a kernel handle opened for synchronous I/O, the
+/// I/O manager keeps track of the file position.
+///
+#[derive(Clone, Debug)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+    delete: bool,
+    share_access: u32,
+    attributes: u32,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenOptions {
+    pub const fn new() -> Self {
+        Self {
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+            create_new: false,
+            delete: false,
+            share_access: FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            attributes: FILE_ATTRIBUTE_NORMAL,
+        }
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    ///
+    /// Every write goes to the end of the file
+    ///
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    ///
+    /// Fails with STATUS_OBJECT_NAME_COLLISION if the file exists
+    ///
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    ///
+    /// Needed for `File::mark_for_delete` and `File::rename`
+    ///
+    pub fn delete(&mut self, delete: bool) -> &mut Self {
+        self.delete = delete;
+        self
+    }
+
+    ///
+    /// `FILE_SHARE_*` flags, defaults to sharing everything
+    ///
+    pub fn share_access(&mut self, share_access: u32) -> &mut Self {
+        self.share_access = share_access;
+        self
+    }
+
+    ///
+    /// `FILE_ATTRIBUTE_*` flags used when the file gets created
+    ///
+    pub fn attributes(&mut self, attributes: u32) -> &mut Self {
+        self.attributes = attributes;
+        self
+    }
+
+    fn desired_access(&self) -> u32 {
+        let mut access = SYNCHRONIZE | FILE_READ_ATTRIBUTES;
+
+        if self.read {
+            access |= FILE_GENERIC_READ;
+        }
+        if self.write {
+            access |= FILE_GENERIC_WRITE;
+        }
+        if self.append {
+            //FILE_APPEND_DATA without FILE_WRITE_DATA makes every write land at the end,
+            //so it is also cleared when `write` added it
+            access |= FILE_GENERIC_WRITE | FILE_APPEND_DATA;
+            access &= !FILE_WRITE_DATA;
+        }
+        if self.delete {
+            access |= DELETE;
+        }
+
+        access
+    }
+
+    fn create_disposition(&self) -> NtResult<u32> {
+        let writable = self.write || self.append;
+
+        if (self.truncate || self.create || self.create_new) && !writable {
+            return Err(NtStatusError::Status(STATUS_INVALID_PARAMETER));
+        }
+        if self.truncate && self.append {
+            return Err(NtStatusError::Status(STATUS_INVALID_PARAMETER));
+        }
+
+        let disposition = match (self.create, self.truncate, self.create_new) {
+            (_, _, true) => FILE_CREATE,
+            (true, true, false) => FILE_OVERWRITE_IF,
+            (true, false, false) => FILE_OPEN_IF,
+            (false, true, false) => FILE_OVERWRITE,
+            (false, false, false) => FILE_OPEN,
+        };
+
+        Ok(disposition)
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn open(&self, path: &NtUnicodeStr<'_>) -> NtResult<File> {
+        let disposition = self.create_disposition()?;
+
+        let attributes =
+            ObjectAttributes::new_named(path, OBJ_KERNEL_HANDLE | OBJ_CASE_INSENSITIVE);
+
+        unsafe {
+            let mut handle: HANDLE = 0;
+            let mut iosb: IO_STATUS_BLOCK = core::mem::zeroed();
+
+            let status = ZwCreateFile(
+                &mut handle,
+                self.desired_access(),
+                attributes.as_ref_mut(),
+                &mut iosb,
+                core::ptr::null(),
+                self.attributes,
+                self.share_access,
+                disposition,
+                FILE_SYNCHRONOUS_IO_NONALERT | FILE_NON_DIRECTORY_FILE,
+                core::ptr::null(),
+                0,
+            );
+
+            NtResult::from_status(status, || {
+                File::from_handle(Handle::new(KernelObjectType::File, handle))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_APPEND_DATA, FILE_GENERIC_READ, FILE_WRITE_ATTRIBUTES, FILE_WRITE_DATA,
+    };
+
+    use super::OpenOptions;
+
+    extern crate std;
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let access = OpenOptions::new().read(true).desired_access();
+        assert_eq!(access & FILE_GENERIC_READ, FILE_GENERIC_READ);
+        assert_eq!(access & (FILE_WRITE_DATA | FILE_APPEND_DATA), 0);
+
+        let access = OpenOptions::new().write(true).desired_access();
+        assert_eq!(access & FILE_WRITE_DATA, FILE_WRITE_DATA);
+
+        let access = OpenOptions::new().append(true).desired_access();
+        assert_eq!(access & FILE_WRITE_DATA, 0);
+        assert_eq!(access & FILE_APPEND_DATA, FILE_APPEND_DATA);
+        assert_eq!(access & FILE_WRITE_ATTRIBUTES, FILE_WRITE_ATTRIBUTES);
+
+        //Same as std, append wins over write
+        let access = OpenOptions::new().write(true).append(true).desired_access();
+        assert_eq!(access & FILE_WRITE_DATA, 0);
+        assert_eq!(access & FILE_APPEND_DATA, FILE_APPEND_DATA);
+
+        Ok(())
+    }
+}
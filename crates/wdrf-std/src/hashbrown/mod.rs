@@ -1,6 +1,6 @@
 use allocator_api2::alloc::Allocator;
 
-pub use hashbrown::hash_map::OccupiedError;
+pub use hashbrown::hash_map::{Entry, OccupiedError};
 
 use crate::{kmalloc::GlobalKernelAllocator, traits::DispatchSafe};
 
@@ -0,0 +1,306 @@
+use crate::{
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    vec::Vec,
+};
+
+use super::{Error, Read, Result, Seek, SeekFrom, Write};
+
+pub const DEFAULT_BUF_SIZE: usize = 4096;
+
+fn try_alloc_buffer(capacity: usize) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new_in(GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"iobf"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    ));
+    buffer
+        .try_reserve_exact(capacity)
+        .map_err(|_| Error::OutOfMemory)?;
+    buffer.resize(capacity, 0);
+
+    Ok(buffer)
+}
+
+///
+/// Reads from `R` in large chunks
+///
+/// Useful in front of `fs::File` where every `read` is a system call.
+///
+pub struct BufReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    pos: usize,
+    filled: usize,
+}
+
+impl<R: Read> BufReader<R> {
+    pub fn try_new(inner: R) -> Result<Self> {
+        Self::try_with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn try_with_capacity(capacity: usize, inner: R) -> Result<Self> {
+        Ok(Self {
+            inner,
+            buffer: try_alloc_buffer(capacity)?,
+            pos: 0,
+            filled: 0,
+        })
+    }
+
+    ///
+    /// Returns the buffered data, reading more if it is empty
+    ///
+    pub fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.pos >= self.filled {
+            self.filled = self.inner.read(&mut self.buffer)?;
+            self.pos = 0;
+        }
+
+        Ok(&self.buffer[self.pos..self.filled])
+    }
+
+    ///
+    /// Marks `amount` bytes returned by `fill_buf` as read
+    ///
+    pub fn consume(&mut self, amount: usize) {
+        self.pos = core::cmp::min(self.pos + amount, self.filled);
+    }
+}
+
+impl<R> BufReader<R> {
+    #[inline]
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer[self.pos..self.filled]
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    ///
+    /// Reading from the inner reader directly skips the buffered data
+    ///
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    ///
+    /// The buffered data is lost
+    ///
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn discard_buffer(&mut self) {
+        self.pos = 0;
+        self.filled = 0;
+    }
+}
+
+impl<R: Read> Read for BufReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        //Large reads skip the buffer when it is empty
+        if self.pos == self.filled && buf.len() >= self.capacity() {
+            self.discard_buffer();
+            return self.inner.read(buf);
+        }
+
+        let read = self.fill_buf()?.read(buf)?;
+        self.consume(read);
+
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for BufReader<R> {
+    ///
+    /// Always discards the buffer, `SeekFrom::Current` is relative to the
+    /// data returned so far rather than to the inner reader
+    ///
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let result = match pos {
+            SeekFrom::Current(delta) => {
+                let remaining = (self.filled - self.pos) as i64;
+                let delta = delta.checked_sub(remaining).ok_or(Error::InvalidInput)?;
+                self.inner.seek(SeekFrom::Current(delta))?
+            }
+            pos => self.inner.seek(pos)?,
+        };
+
+        self.discard_buffer();
+        Ok(result)
+    }
+}
+
+///
+/// Collects small writes and hands them to `W` in large chunks
+///
+/// Dropping the writer flushes it and ignores any error, call `flush`
+/// or `into_inner` to observe them.
+///
+pub struct BufWriter<W: Write> {
+    inner: Option<W>,
+    buffer: Vec<u8>,
+    len: usize,
+}
+
+impl<W: Write> BufWriter<W> {
+    pub fn try_new(inner: W) -> Result<Self> {
+        Self::try_with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn try_with_capacity(capacity: usize, inner: W) -> Result<Self> {
+        Ok(Self {
+            inner: Some(inner),
+            buffer: try_alloc_buffer(capacity)?,
+            len: 0,
+        })
+    }
+
+    #[inline]
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    ///
+    /// Writing to the inner writer directly bypasses the buffered data
+    ///
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    ///
+    /// Flushes the buffer, the writer is given back with the error on failure
+    ///
+    pub fn into_inner(mut self) -> core::result::Result<W, (Error, Self)> {
+        match self.flush_buf() {
+            Ok(()) => Ok(self.inner.take().unwrap()),
+            Err(e) => Err((e, self)),
+        }
+    }
+
+    fn flush_buf(&mut self) -> Result<()> {
+        let inner = self.inner.as_mut().unwrap();
+
+        let mut written = 0;
+        let result = loop {
+            if written >= self.len {
+                break Ok(());
+            }
+
+            match inner.write(&self.buffer[written..self.len]) {
+                Ok(0) => break Err(Error::WriteZero),
+                Ok(count) => written += count,
+                Err(e) => break Err(e),
+            }
+        };
+
+        //Keep what was not written for the next attempt
+        self.buffer.copy_within(written..self.len, 0);
+        self.len -= written;
+
+        result
+    }
+}
+
+impl<W: Write> Write for BufWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.len + buf.len() > self.capacity() {
+            self.flush_buf()?;
+        }
+
+        if buf.len() >= self.capacity() {
+            self.get_mut().write(buf)
+        } else {
+            self.buffer[self.len..self.len + buf.len()].copy_from_slice(buf);
+            self.len += buf.len();
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.flush_buf()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write + Seek> Seek for BufWriter<W> {
+    ///
+    /// Flushes the buffer before seeking
+    ///
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.flush_buf()?;
+        self.get_mut().seek(pos)
+    }
+}
+
+impl<W: Write> Drop for BufWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.flush_buf();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        io::{BufReader, BufWriter, Cursor, Error, Read, Seek, SeekFrom, Write},
+        vec::{Vec, VecCreate},
+    };
+
+    extern crate std;
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let data: &[u8] = b"0123456789abcdef";
+
+        let mut reader = BufReader::try_with_capacity(4, Cursor::new(data))?;
+        let mut head = [0u8; 3];
+        reader.read_exact(&mut head)?;
+        assert_eq!(&head, b"012");
+        assert_eq!(reader.buffer(), b"3");
+
+        //Relative to what the caller has seen, not to the inner cursor
+        assert_eq!(reader.seek(SeekFrom::Current(2))?, 5);
+        let mut rest = [0u8; 11];
+        reader.read_exact(&mut rest)?;
+        assert_eq!(&rest, b"56789abcdef");
+        assert_eq!(reader.read_exact(&mut head), Err(Error::UnexpectedEof));
+
+        let mut writer = BufWriter::try_with_capacity(4, Cursor::new(Vec::create_any()))?;
+        writer.write_all(b"ab")?;
+        assert!(writer.get_ref().get_ref().is_empty());
+        writer.write_all(b"cdefgh")?;
+        writer.seek(SeekFrom::Start(1))?;
+        writer.write_all(b"X")?;
+        let cursor = writer.into_inner().map_err(|(e, _)| e)?;
+        assert_eq!(cursor.get_ref().as_slice(), b"aXcdefgh");
+
+        let mut storage = [0u8; 4];
+        let mut slice = Cursor::new(&mut storage[..]);
+        assert_eq!(slice.write_all(b"12345"), Err(Error::WriteZero));
+        assert_eq!(&storage, b"1234");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use core::alloc::Allocator;
+
+use crate::vec::Vec;
+
+use super::{seek_position, Error, Read, Result, Seek, SeekFrom, Write};
+
+///
+/// Adds a position to an in memory buffer
+///
+/// Writing to `Cursor<&mut [u8]>` stops at the end of the slice,
+/// writing to `Cursor<Vec<u8>>` grows the vector fallibly.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Cursor<T> {
+    inner: T,
+    pos: u64,
+}
+
+impl<T> Cursor<T> {
+    pub const fn new(inner: T) -> Self {
+        Self { inner, pos: 0 }
+    }
+
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    ///
+    /// The position may go past the end of the data, reads then return 0
+    ///
+    #[inline]
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: AsRef<[u8]>> Cursor<T> {
+    ///
+    /// The data between the position and the end
+    ///
+    pub fn remaining_slice(&self) -> &[u8] {
+        let data = self.inner.as_ref();
+        let start = core::cmp::min(self.pos, data.len() as u64) as usize;
+
+        &data[start..]
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for Cursor<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.remaining_slice().read(buf)?;
+        self.pos += read as u64;
+
+        Ok(read)
+    }
+}
+
+impl<T: AsRef<[u8]>> Seek for Cursor<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let len = self.inner.as_ref().len() as u64;
+        self.pos = seek_position(pos, self.pos, len)?;
+
+        Ok(self.pos)
+    }
+}
+
+impl Write for Cursor<&mut [u8]> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let start = core::cmp::min(self.pos, self.inner.len() as u64) as usize;
+        let len = core::cmp::min(buf.len(), self.inner.len() - start);
+
+        self.inner[start..start + len].copy_from_slice(&buf[..len]);
+        self.pos = (start + len) as u64;
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<A: Allocator> Write for Cursor<Vec<u8, A>> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let start = usize::try_from(self.pos).map_err(|_| Error::InvalidInput)?;
+        let end = start.checked_add(buf.len()).ok_or(Error::InvalidInput)?;
+
+        if end > self.inner.len() {
+            self.inner
+                .try_reserve(end - self.inner.len())
+                .map_err(|_| Error::OutOfMemory)?;
+            //Writing past the end fills the gap with zeroes
+            self.inner.resize(end, 0);
+        }
+
+        self.inner[start..end].copy_from_slice(buf);
+        self.pos = end as u64;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
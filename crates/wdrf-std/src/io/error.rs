@@ -0,0 +1,58 @@
+use thiserror::Error;
+use windows_sys::Win32::Foundation::{
+    NTSTATUS, STATUS_BUFFER_TOO_SMALL, STATUS_DATA_ERROR, STATUS_END_OF_FILE,
+    STATUS_INVALID_PARAMETER, STATUS_NO_MEMORY,
+};
+
+use crate::NtStatusError;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+///
+/// Error returned by the io traits
+///
+/// Every variant maps to an NTSTATUS so it can be handed back to the
+/// I/O manager or a filter manager callback as is.
+///
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    #[error("NtStatus code: {0:X}")]
+    Status(NTSTATUS),
+    #[error("Unexpected end of data")]
+    UnexpectedEof,
+    #[error("Failed to write the whole buffer")]
+    WriteZero,
+    #[error("Out of memory")]
+    OutOfMemory,
+    #[error("Invalid input")]
+    InvalidInput,
+    #[error("Invalid data")]
+    InvalidData,
+}
+
+impl Error {
+    pub fn status(&self) -> NTSTATUS {
+        match self {
+            Error::Status(status) => *status,
+            Error::UnexpectedEof => STATUS_END_OF_FILE,
+            Error::WriteZero => STATUS_BUFFER_TOO_SMALL,
+            Error::OutOfMemory => STATUS_NO_MEMORY,
+            Error::InvalidInput => STATUS_INVALID_PARAMETER,
+            Error::InvalidData => STATUS_DATA_ERROR,
+        }
+    }
+}
+
+impl From<NtStatusError> for Error {
+    fn from(value: NtStatusError) -> Self {
+        match value {
+            NtStatusError::Status(status) => Error::Status(status),
+        }
+    }
+}
+
+impl From<Error> for NtStatusError {
+    fn from(value: Error) -> Self {
+        NtStatusError::Status(value.status())
+    }
+}
@@ -1,4 +1,127 @@
+//!
+//! A `no_std` subset of `std::io`
+//!
+//! Buffers are allocated fallibly from non paged pool and every error
+//! carries an NTSTATUS through `Error::status`.
+//!
+
+mod buffered;
+mod cursor;
+mod error;
+
+pub use buffered::{BufReader, BufWriter};
+pub use cursor::Cursor;
+pub use error::{Error, Result};
+
 pub trait Write {
-    fn write(&mut self, buf: &[u8]) -> anyhow::Result<usize>;
-    fn flush(&mut self) -> anyhow::Result<()>;
+    fn write(&mut self, buf: &[u8]) -> Result<usize>;
+    fn flush(&mut self) -> Result<()>;
+
+    ///
+    /// Fails with `Error::WriteZero` if the writer stops accepting data
+    ///
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(Error::WriteZero),
+                written => buf = &buf[written..],
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub trait Read {
+    ///
+    /// Returns 0 once the end of the source has been reached
+    ///
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    ///
+    /// Fails with `Error::UnexpectedEof` if the source ends early, the
+    /// content of `buf` is unspecified in that case
+    ///
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf)? {
+                0 => return Err(Error::UnexpectedEof),
+                read => buf = &mut buf[read..],
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+pub trait Seek {
+    ///
+    /// Returns the new position from the start
+    ///
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64>;
+
+    fn stream_position(&mut self) -> Result<u64> {
+        self.seek(SeekFrom::Current(0))
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.seek(SeekFrom::Start(0)).map(|_| ())
+    }
+}
+
+impl<R: Read + ?Sized> Read for &mut R {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+}
+
+impl<W: Write + ?Sized> Write for &mut W {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        (**self).write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+impl<S: Seek + ?Sized> Seek for &mut S {
+    #[inline]
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        (**self).seek(pos)
+    }
+}
+
+impl Read for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = core::cmp::min(buf.len(), self.len());
+        let (head, tail) = self.split_at(len);
+
+        buf[..len].copy_from_slice(head);
+        *self = tail;
+        Ok(len)
+    }
+}
+
+///
+/// Applies `pos` to a stream of `len` bytes currently at `current`
+///
+pub(crate) fn seek_position(pos: SeekFrom, current: u64, len: u64) -> Result<u64> {
+    let new_pos = match pos {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::End(delta) => len.checked_add_signed(delta),
+        SeekFrom::Current(delta) => current.checked_add_signed(delta),
+    };
+
+    new_pos.ok_or(Error::InvalidInput)
 }
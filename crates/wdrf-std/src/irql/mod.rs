@@ -0,0 +1,235 @@
+//!
+//! Zero sized IRQL tokens
+//!
+//! Holding a `Passive<'a>` proves the current thread runs at PASSIVE_LEVEL.
+//! Operations that raise the IRQL mutably borrow the lower token and hand out
+//! the higher one, so the lower token can not be used until the raise ends.
+//! APIs that must not run at DISPATCH_LEVEL take a `&impl BelowDispatch`,
+//! calling them while holding a spin lock becomes a compile error.
+//! Spin locks taken while already at DISPATCH_LEVEL use the `*_at_dpc_level`
+//! functions which reborrow the `Dispatch` token instead of raising.
+//!
+//! The lock and wait functions that take no token are deprecated, they can
+//! not prove anything about the IRQL they run at.
+//!
+//! Tokens are neither `Send` nor `Sync` and can not be cloned.
+//!
+
+use core::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use sealed::sealed;
+use windows_sys::Wdk::System::SystemServices::{
+    KeGetCurrentIrql, APC_LEVEL, DISPATCH_LEVEL, PASSIVE_LEVEL,
+};
+
+#[sealed]
+pub trait IrqlToken {
+    const LEVEL: u32;
+}
+
+///
+/// Implemented by the tokens that allow waiting and touching paged memory
+///
+#[sealed]
+pub trait BelowDispatch: IrqlToken {}
+
+macro_rules! define_irql_token {
+    ($name:ident, $level:expr) => {
+        pub struct $name<'a> {
+            _scope: PhantomData<&'a mut ()>,
+            _not_send: PhantomData<*mut ()>,
+        }
+
+        impl<'a> $name<'a> {
+            ///
+            /// # Safety
+            ///
+            /// The caller must be running at the token IRQL and must not
+            /// change it while the token is alive
+            ///
+            #[inline(always)]
+            pub unsafe fn new_unchecked() -> Self {
+                Self {
+                    _scope: PhantomData,
+                    _not_send: PhantomData,
+                }
+            }
+
+            ///
+            /// Creates a token if the current IRQL matches
+            ///
+            #[inline]
+            pub fn try_current() -> Option<Self> {
+                if unsafe { KeGetCurrentIrql() } as u32 == $level {
+                    Some(unsafe { Self::new_unchecked() })
+                } else {
+                    None
+                }
+            }
+
+            #[inline(always)]
+            pub fn reborrow(&mut self) -> $name<'_> {
+                unsafe { $name::new_unchecked() }
+            }
+        }
+
+        #[sealed]
+        impl<'a> IrqlToken for $name<'a> {
+            const LEVEL: u32 = $level;
+        }
+    };
+}
+
+define_irql_token!(Passive, PASSIVE_LEVEL);
+define_irql_token!(Apc, APC_LEVEL);
+define_irql_token!(Dispatch, DISPATCH_LEVEL);
+
+#[sealed]
+impl<'a> BelowDispatch for Passive<'a> {}
+#[sealed]
+impl<'a> BelowDispatch for Apc<'a> {}
+
+///
+/// Hands out the token for the IRQL a raising operation moved to
+/// The lower token stays borrowed for `'b`
+///
+#[inline(always)]
+pub(crate) fn raise_to_dispatch<'b, I: BelowDispatch>(_lower: &'b mut I) -> Dispatch<'b> {
+    unsafe { Dispatch::new_unchecked() }
+}
+
+#[inline(always)]
+pub(crate) fn raise_to_apc<'b, I: BelowDispatch>(_lower: &'b mut I) -> Apc<'b> {
+    unsafe { Apc::new_unchecked() }
+}
+
+//x64 drivers get KfRaiseIrql and KeLowerIrql as inlines accessing CR8,
+//ntoskrnl does not export them
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    #[inline(always)]
+    pub(super) unsafe fn raise_irql(new_irql: u8) -> u8 {
+        let old_irql: u64;
+        core::arch::asm!("mov {}, cr8", out(reg) old_irql, options(nostack, preserves_flags));
+        core::arch::asm!("mov cr8, {}", in(reg) new_irql as u64, options(nostack, preserves_flags));
+
+        old_irql as u8
+    }
+
+    #[inline(always)]
+    pub(super) unsafe fn lower_irql(new_irql: u8) {
+        core::arch::asm!("mov cr8, {}", in(reg) new_irql as u64, options(nostack, preserves_flags));
+    }
+}
+
+//Both are fastcall hal exports on x86
+#[cfg(target_arch = "x86")]
+mod arch {
+    #[link(name = "hal")]
+    extern "fastcall" {
+        #[link_name = "KfRaiseIrql"]
+        pub(super) fn raise_irql(new_irql: u8) -> u8;
+        #[link_name = "KfLowerIrql"]
+        pub(super) fn lower_irql(new_irql: u8);
+    }
+}
+
+//ARM64 has no CR8, ntoskrnl exports real functions
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    #[link(name = "ntoskrnl")]
+    extern "system" {
+        #[link_name = "KfRaiseIrql"]
+        pub(super) fn raise_irql(new_irql: u8) -> u8;
+        #[link_name = "KeLowerIrql"]
+        pub(super) fn lower_irql(new_irql: u8);
+    }
+}
+
+///
+/// Keeps the IRQL at DISPATCH_LEVEL until dropped
+///
+/// The thread can not be preempted or moved to another processor
+/// while the guard is alive. Must not be held across waits.
+///
+pub struct DispatchGuard {
+    old_irql: u8,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl DispatchGuard {
+    ///
+    /// # Panics
+    ///
+    /// If the current IRQL is above DISPATCH_LEVEL
+    ///
+    pub fn raise() -> Self {
+        let current = unsafe { KeGetCurrentIrql() };
+        if current as u32 > DISPATCH_LEVEL {
+            panic!("Can not raise to DISPATCH_LEVEL from IRQL {}", current);
+        }
+
+        let old_irql = unsafe { arch::raise_irql(DISPATCH_LEVEL as u8) };
+
+        Self {
+            old_irql,
+            _not_send: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn old_irql(&self) -> u8 {
+        self.old_irql
+    }
+
+    #[inline(always)]
+    pub fn irql(&mut self) -> Dispatch<'_> {
+        unsafe { Dispatch::new_unchecked() }
+    }
+}
+
+impl Drop for DispatchGuard {
+    fn drop(&mut self) {
+        unsafe { arch::lower_irql(self.old_irql) };
+    }
+}
+
+///
+/// Lock guard returned by the token based lock functions
+///
+/// Keeps the lower token borrowed for as long as the lock is held
+/// and gives access to the token of the raised IRQL.
+///
+pub struct IrqlGuard<G, T: IrqlToken> {
+    guard: G,
+    irql: T,
+}
+
+impl<G, T: IrqlToken> IrqlGuard<G, T> {
+    #[inline(always)]
+    pub(crate) fn new(guard: G, irql: T) -> Self {
+        Self { guard, irql }
+    }
+
+    #[inline(always)]
+    pub fn irql(&mut self) -> &mut T {
+        &mut self.irql
+    }
+}
+
+impl<G: Deref, T: IrqlToken> Deref for IrqlGuard<G, T> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &Self::Target {
+        self.guard.deref()
+    }
+}
+
+impl<G: DerefMut, T: IrqlToken> DerefMut for IrqlGuard<G, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.guard.deref_mut()
+    }
+}
@@ -6,7 +6,7 @@ use core::{
 use thiserror::Error;
 use windows_sys::Wdk::System::SystemServices::{ExAllocatePool2, ExFreePoolWithTag};
 
-use crate::constants::PoolFlags;
+use crate::{constants::PoolFlags, irql::BelowDispatch};
 
 #[derive(Clone, Copy)]
 pub struct MemoryTag {
@@ -98,7 +98,7 @@ pub struct GlobalKernelAllocator {
     tag: MemoryTag,
     flags: PoolFlags,
 
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-alloc"))]
     fail_alloc: bool,
 }
 
@@ -110,22 +110,29 @@ impl GlobalKernelAllocator {
         Self {
             tag,
             flags,
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-alloc"))]
             fail_alloc: false,
         }
     }
 
+    ///
+    /// Paged pool can only be touched below DISPATCH_LEVEL
+    ///
+    pub fn new_paged<I: BelowDispatch>(tag: MemoryTag, _irql: &I) -> Self {
+        Self::new(tag, PoolFlags::POOL_FLAG_PAGED)
+    }
+
     pub fn new_for_tagged<T: TaggedObject>() -> Self {
         Self {
             tag: T::tag(),
             flags: T::flags(),
-            #[cfg(test)]
+            #[cfg(any(test, feature = "test-alloc"))]
             fail_alloc: false,
         }
     }
 }
 
-#[cfg(not(test))]
+#[cfg(not(any(test, feature = "test-alloc")))]
 impl GlobalKernelAllocator {
     #[inline]
     fn allocate_internal(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
@@ -147,7 +154,7 @@ impl GlobalKernelAllocator {
     }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-alloc"))]
 impl GlobalKernelAllocator {
     #[inline]
     fn allocate_internal(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
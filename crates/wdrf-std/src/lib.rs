@@ -14,11 +14,17 @@ pub mod aligned;
 pub mod boxed;
 pub mod collections;
 pub mod constants;
+pub mod dpc;
+pub mod executor;
 pub mod fmt;
+pub mod fs;
 pub mod hashbrown;
 pub mod io;
+pub mod irql;
 pub mod kmalloc;
 pub mod object;
+pub mod registry;
+pub mod security;
 pub mod slice;
 pub mod structs;
 pub mod sync;
@@ -26,6 +32,7 @@ pub mod thread;
 pub mod time;
 pub mod traits;
 pub mod vec;
+pub mod workqueue;
 
 pub mod sys;
 
@@ -42,6 +49,12 @@ pub enum NtStatusError {
 
 pub type NtResult<T> = anyhow::Result<T, NtStatusError>;
 
+impl From<core::convert::Infallible> for NtStatusError {
+    fn from(value: core::convert::Infallible) -> Self {
+        match value {}
+    }
+}
+
 #[sealed]
 pub trait NtResultEx<T> {
     fn from_status<F: FnOnce() -> T>(status: NTSTATUS, f: F) -> NtResult<T>;
@@ -1,7 +1,10 @@
 use core::cell::UnsafeCell;
 
 use nt_string::unicode_string::NtUnicodeStr;
-use windows_sys::{Wdk::Foundation::OBJECT_ATTRIBUTES, Win32::Security::PSECURITY_DESCRIPTOR};
+use windows_sys::{
+    Wdk::Foundation::OBJECT_ATTRIBUTES,
+    Win32::{Foundation::HANDLE, Security::PSECURITY_DESCRIPTOR},
+};
 
 #[allow(dead_code)]
 pub struct ObjectAttributes<'a> {
@@ -51,6 +54,14 @@ impl<'a> ObjectAttributes<'a> {
         }
     }
 
+    ///
+    /// Makes the name relative to an already open object such as a key or directory
+    ///
+    pub fn with_root_directory(mut self, root: HANDLE) -> Self {
+        self.inner.get_mut().RootDirectory = root;
+        self
+    }
+
     #[allow(clippy::mut_from_ref)]
     pub fn as_ref_mut(&'a self) -> &'a mut OBJECT_ATTRIBUTES {
         unsafe { &mut *self.inner.get() }
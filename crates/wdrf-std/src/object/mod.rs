@@ -1,5 +1,6 @@
 pub mod attribute;
 pub mod handle;
+pub mod named;
 
 use handle::Handle;
 use windows_sys::Wdk::Foundation::POBJECT_TYPE;
@@ -11,8 +12,8 @@ use windows_sys::Win32::Foundation::{HANDLE, STATUS_OBJECT_TYPE_MISMATCH};
 use core::ffi::c_void;
 
 use crate::constants::{
-    ExEventObjectType, ExSemaphoreObjectType, IoFileObjectType, PsProcessType, PsThreadType,
-    SeTokenObjectType, TmEnlistmentObjectType, TmResourceManagerObjectType,
+    CmKeyObjectType, ExEventObjectType, ExSemaphoreObjectType, IoFileObjectType, PsProcessType,
+    PsThreadType, SeTokenObjectType, TmEnlistmentObjectType, TmResourceManagerObjectType,
     TmTransactionManagerObjectType, TmTransactionObjectType,
 };
 use crate::structs::PKENLISTMENT;
@@ -40,6 +41,7 @@ pub enum KernelObjectType {
     ResourceManager,
     TranscationManager,
     Transcation,
+    Key,
 }
 
 impl KernelObjectType {
@@ -56,6 +58,7 @@ impl KernelObjectType {
                 KernelObjectType::ResourceManager => *TmResourceManagerObjectType,
                 KernelObjectType::TranscationManager => *TmTransactionManagerObjectType,
                 KernelObjectType::Transcation => *TmTransactionObjectType,
+                KernelObjectType::Key => *CmKeyObjectType,
             }
         }
     }
@@ -252,6 +255,12 @@ impl_kernel_resource!(PRKRESOURCEMANAGER, KernelObjectType::ResourceManager);
 impl_kernel_resource!(PKTM, KernelObjectType::TranscationManager);
 impl_kernel_resource!(PKTRANSACTION, KernelObjectType::Transcation);
 
+unsafe impl WaitableObject for ArcKernelObj<PKEVENT> {
+    fn kernel_object(&self) -> &crate::sys::WaitableKernelObject {
+        unsafe { &*(self.obj.as_mut_ptr() as *const crate::sys::WaitableKernelObject) }
+    }
+}
+
 unsafe impl WaitableObject for ArcKernelObj<PKTHREAD> {
     fn kernel_object(&self) -> &crate::sys::WaitableKernelObject {
         unsafe { &*(self.obj.as_mut_ptr() as *const crate::sys::WaitableKernelObject) }
@@ -0,0 +1,330 @@
+//!
+//! Named objects in the object manager namespace
+//!
+//! Events, directories and symbolic links created here are visible to user
+//! mode (e.g. `\BaseNamedObjects\MyEvent` is `Global\MyEvent`), so the
+//! attributes should carry a security descriptor. Handles are always created
+//! with `OBJ_KERNEL_HANDLE` and the objects are temporary, they go away once
+//! the last handle is closed.
+//!
+
+use core::ffi::c_void;
+
+use nt_string::unicode_string::NtUnicodeStr;
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+use windows_sys::{
+    Wdk::{
+        Foundation::{OBJECT_ATTRIBUTES, POBJECT_TYPE},
+        Storage::FileSystem::{ZwCreateEvent, ZwOpenDirectoryObject, IO_NO_INCREMENT},
+        System::SystemServices::{
+            IoCreateNotificationEvent, KeClearEvent, KeSetEvent, KernelMode,
+            ZwCreateDirectoryObject, ZwOpenEvent, ZwOpenSymbolicLinkObject,
+        },
+    },
+    Win32::{
+        Foundation::{HANDLE, NTSTATUS, STATUS_UNSUCCESSFUL, UNICODE_STRING},
+        System::{
+            Kernel::{OBJ_CASE_INSENSITIVE, OBJ_KERNEL_HANDLE},
+            Threading::EVENT_ALL_ACCESS,
+        },
+    },
+};
+
+use crate::{
+    structs::PKEVENT,
+    sys::{event::EventType, WaitableKernelObject, WaitableObject},
+    traits::DispatchSafe,
+    NtResult, NtResultEx, NtStatusError,
+};
+
+use super::{
+    attribute::ObjectAttributes, handle::Handle, ArcKernelObj, KernelObjectType, KernelResource,
+    NonNullKrnlResource,
+};
+
+pub const DIRECTORY_QUERY: u32 = 0x0001;
+pub const DIRECTORY_TRAVERSE: u32 = 0x0002;
+pub const DIRECTORY_CREATE_OBJECT: u32 = 0x0004;
+pub const DIRECTORY_CREATE_SUBDIRECTORY: u32 = 0x0008;
+pub const DIRECTORY_ALL_ACCESS: u32 = 0x000F_000F;
+
+pub const SYMBOLIC_LINK_QUERY: u32 = 0x0001;
+pub const SYMBOLIC_LINK_ALL_ACCESS: u32 = 0x000F_0001;
+
+#[link(name = "ntoskrnl")]
+extern "system" {
+    fn ZwCreateSymbolicLinkObject(
+        link_handle: *mut HANDLE,
+        desired_access: u32,
+        object_attributes: *const OBJECT_ATTRIBUTES,
+        link_target: *const UNICODE_STRING,
+    ) -> NTSTATUS;
+
+    fn ObReferenceObjectByName(
+        object_name: *const UNICODE_STRING,
+        attributes: u32,
+        access_state: *mut c_void,
+        desired_access: u32,
+        object_type: POBJECT_TYPE,
+        access_mode: i8,
+        parse_context: *mut c_void,
+        object: *mut *mut c_void,
+    ) -> NTSTATUS;
+}
+
+fn kernel_attributes<'a>(attributes: &'a ObjectAttributes<'a>) -> &'a OBJECT_ATTRIBUTES {
+    let raw = attributes.as_ref_mut();
+    raw.Attributes |= OBJ_KERNEL_HANDLE as u32;
+    raw
+}
+
+impl<T> ArcKernelObj<T>
+where
+    T: KernelResource,
+{
+    ///
+    /// References an object by its full name, e.g. `\BaseNamedObjects\MyEvent`
+    ///
+    /// The lookup is case insensitive and fails with `STATUS_OBJECT_TYPE_MISMATCH`
+    /// if the name points to an object of another type.
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn from_name(name: &NtUnicodeStr, access: u32) -> NtResult<Self> {
+        unsafe {
+            let mut obj_ptr = core::ptr::null_mut();
+
+            let status = ObReferenceObjectByName(
+                name.as_ptr() as _,
+                OBJ_CASE_INSENSITIVE as _,
+                core::ptr::null_mut(),
+                access,
+                T::object_type().into_kernel_object_type(),
+                KernelMode as _,
+                core::ptr::null_mut(),
+                &mut obj_ptr,
+            );
+            NtResult::from_status(status, || ())?;
+
+            NonNullKrnlResource::new(T::from_raw_ptr(obj_ptr))
+                .map(|obj| Self { obj })
+                .ok_or(NtStatusError::Status(STATUS_UNSUCCESSFUL))
+        }
+    }
+}
+
+///
+/// Named event shared with other drivers or user mode
+///
+/// Keeps both the handle, which keeps the name alive, and a reference to the
+/// `KEVENT` so it can be signaled and waited on without going through the handle.
+///
+pub struct NamedEvent {
+    handle: Handle,
+    event: ArcKernelObj<PKEVENT>,
+}
+
+unsafe impl Send for NamedEvent {}
+unsafe impl Sync for NamedEvent {}
+unsafe impl DispatchSafe for NamedEvent {}
+
+impl NamedEvent {
+    ///
+    /// Creates the event with `ZwCreateEvent`, fails with `STATUS_OBJECT_NAME_COLLISION`
+    /// if the name is taken unless the attributes have `OBJ_OPENIF`
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn create(
+        attributes: &ObjectAttributes,
+        ev_type: EventType,
+        signaled: bool,
+    ) -> NtResult<Self> {
+        unsafe {
+            let mut handle: HANDLE = 0;
+
+            let status = ZwCreateEvent(
+                &mut handle,
+                EVENT_ALL_ACCESS,
+                kernel_attributes(attributes),
+                ev_type.as_wdm_value(),
+                signaled as _,
+            );
+            NtResult::from_status(status, || ())?;
+
+            Self::from_owned_handle(Handle::new(KernelObjectType::Event, handle))
+        }
+    }
+
+    ///
+    /// Creates or opens a notification event with `IoCreateNotificationEvent`
+    ///
+    /// The event gets the default security of `\BaseNamedObjects`, use `create`
+    /// to control who can open it.
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn create_notification(name: &NtUnicodeStr) -> NtResult<Self> {
+        unsafe {
+            let mut handle: HANDLE = 0;
+
+            let event = IoCreateNotificationEvent(name.as_ptr() as _, &mut handle);
+            let event = NonNullKrnlResource::new(event as PKEVENT)
+                .ok_or(NtStatusError::Status(STATUS_UNSUCCESSFUL))?;
+
+            Ok(Self {
+                handle: Handle::new(KernelObjectType::Event, handle),
+                event: ArcKernelObj::new(event, true),
+            })
+        }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn open(attributes: &ObjectAttributes, access: u32) -> NtResult<Self> {
+        unsafe {
+            let mut handle: HANDLE = 0;
+
+            let status = ZwOpenEvent(&mut handle, access, kernel_attributes(attributes));
+            NtResult::from_status(status, || ())?;
+
+            Self::from_owned_handle(Handle::new(KernelObjectType::Event, handle))
+        }
+    }
+
+    fn from_owned_handle(handle: Handle) -> NtResult<Self> {
+        //The handle has the rights we asked for, the kernel reference needs none
+        let event = ArcKernelObj::from_handle(&handle, 0)?;
+
+        Ok(Self { handle, event })
+    }
+
+    #[inline]
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    #[inline]
+    pub fn event(&self) -> &ArcKernelObj<PKEVENT> {
+        &self.event
+    }
+
+    pub fn signal(&self) {
+        unsafe {
+            KeSetEvent(
+                self.event.as_raw_obj() as _,
+                IO_NO_INCREMENT as _,
+                false as _,
+            );
+        }
+    }
+
+    pub fn clear(&self) {
+        unsafe {
+            KeClearEvent(self.event.as_raw_obj() as _);
+        }
+    }
+}
+
+unsafe impl WaitableObject for NamedEvent {
+    #[inline]
+    fn kernel_object(&self) -> &WaitableKernelObject {
+        self.event.kernel_object()
+    }
+}
+
+///
+/// Object manager directory, e.g. a private `\MyDriver` namespace
+///
+/// Pass `handle()` to `ObjectAttributes::with_root_directory` to create
+/// objects inside it.
+///
+pub struct ObjectDirectory {
+    handle: Handle,
+}
+
+impl ObjectDirectory {
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn create(attributes: &ObjectAttributes) -> NtResult<Self> {
+        //The directory and symbolic link object types are not exported,
+        //so these handles can not be typed
+        unsafe {
+            let mut handle: HANDLE = 0;
+
+            let status = ZwCreateDirectoryObject(
+                &mut handle,
+                DIRECTORY_ALL_ACCESS,
+                kernel_attributes(attributes),
+            );
+
+            NtResult::from_status(status, || Self {
+                handle: Handle::new_unknown(handle),
+            })
+        }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn open(attributes: &ObjectAttributes, access: u32) -> NtResult<Self> {
+        unsafe {
+            let mut handle: HANDLE = 0;
+
+            let status = ZwOpenDirectoryObject(&mut handle, access, kernel_attributes(attributes));
+
+            NtResult::from_status(status, || Self {
+                handle: Handle::new_unknown(handle),
+            })
+        }
+    }
+
+    #[inline]
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+}
+
+///
+/// Symbolic link such as `\??\MyDevice` -> `\Device\MyDevice`
+///
+/// The link is removed when dropped, unlike `IoCreateSymbolicLink` which
+/// needs a matching `IoDeleteSymbolicLink`.
+///
+pub struct SymbolicLink {
+    handle: Handle,
+}
+
+impl SymbolicLink {
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn create(attributes: &ObjectAttributes, target: &NtUnicodeStr) -> NtResult<Self> {
+        unsafe {
+            let mut handle: HANDLE = 0;
+
+            let status = ZwCreateSymbolicLinkObject(
+                &mut handle,
+                SYMBOLIC_LINK_ALL_ACCESS,
+                kernel_attributes(attributes),
+                target.as_ptr() as _,
+            );
+
+            NtResult::from_status(status, || Self {
+                handle: Handle::new_unknown(handle),
+            })
+        }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn open(attributes: &ObjectAttributes, access: u32) -> NtResult<Self> {
+        unsafe {
+            let mut handle: HANDLE = 0;
+
+            let status =
+                ZwOpenSymbolicLinkObject(&mut handle, access, kernel_attributes(attributes));
+
+            NtResult::from_status(status, || Self {
+                handle: Handle::new_unknown(handle),
+            })
+        }
+    }
+
+    #[inline]
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+}
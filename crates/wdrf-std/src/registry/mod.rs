@@ -0,0 +1,638 @@
+//!
+//! Typed access to registry keys through the `Zw*Key` routines
+//!
+//! All calls must be made at PASSIVE_LEVEL. Paths are full registry paths
+//! such as `\Registry\Machine\System\CurrentControlSet\Services\MyDriver`,
+//! which is the form of the `registry_path` handed to `DriverEntry`.
+//!
+
+mod store;
+mod value;
+
+pub use store::{FromRegValue, MemoryStore, ValueStore};
+pub use value::{RegString, RegValue};
+
+use core::{
+    ffi::c_void,
+    mem::{offset_of, ManuallyDrop},
+};
+
+use nt_string::unicode_string::NtUnicodeStr;
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+use windows_sys::{
+    Wdk::{
+        Foundation::WORK_QUEUE_ITEM,
+        Storage::FileSystem::ZwNotifyChangeKey,
+        System::SystemServices::{
+            DelayedWorkQueue, KeyBasicInformation, KeyValueFullInformation,
+            KeyValuePartialInformation, ZwCreateKey, ZwDeleteKey, ZwDeleteValueKey, ZwEnumerateKey,
+            ZwEnumerateValueKey, ZwFlushKey, ZwOpenKey, ZwQueryValueKey, ZwSetValueKey,
+            KEY_BASIC_INFORMATION, KEY_VALUE_FULL_INFORMATION, KEY_VALUE_PARTIAL_INFORMATION,
+        },
+    },
+    Win32::{
+        Foundation::{
+            HANDLE, NTSTATUS, STATUS_BUFFER_OVERFLOW, STATUS_BUFFER_TOO_SMALL,
+            STATUS_INVALID_BUFFER_SIZE, STATUS_NO_MEMORY, STATUS_NO_MORE_ENTRIES,
+            STATUS_OBJECT_TYPE_MISMATCH,
+        },
+        System::{
+            Kernel::{OBJ_CASE_INSENSITIVE, OBJ_KERNEL_HANDLE},
+            Registry::{
+                REG_BINARY, REG_CREATED_NEW_KEY, REG_NOTIFY_CHANGE_ATTRIBUTES,
+                REG_NOTIFY_CHANGE_LAST_SET, REG_NOTIFY_CHANGE_NAME, REG_NOTIFY_CHANGE_SECURITY,
+                REG_OPTION_NON_VOLATILE,
+            },
+            IO::IO_STATUS_BLOCK,
+        },
+    },
+};
+
+pub use windows_sys::Win32::System::Registry::{
+    KEY_ALL_ACCESS, KEY_CREATE_SUB_KEY, KEY_NOTIFY, KEY_READ, KEY_WRITE,
+};
+
+use crate::{
+    boxed::{Box, BoxExt},
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+    nt_success,
+    object::{attribute::ObjectAttributes, handle::Handle, KernelObjectType},
+    sync::{arc::Arc, event::Event, in_flight::InFlightCounter, once::OnceLock},
+    vec::Vec,
+    workqueue::{PreparedWork, WorkQueue, WorkQueueType},
+    NtResult, NtResultEx, NtStatusError,
+};
+
+use value::{parse_string, value_allocator};
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct NotifyFilter : u32 {
+        const NAME = REG_NOTIFY_CHANGE_NAME;
+        const ATTRIBUTES = REG_NOTIFY_CHANGE_ATTRIBUTES;
+        const LAST_SET = REG_NOTIFY_CHANGE_LAST_SET;
+        const SECURITY = REG_NOTIFY_CHANGE_SECURITY;
+    }
+}
+
+///
+/// Whether `Key::create` made a new key
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Disposition {
+    Created,
+    Opened,
+}
+
+///
+/// An open registry key, closed when dropped
+///
+pub struct Key {
+    //Closed by hand in `drop`, before waiting for the notifications
+    handle: ManuallyDrop<Handle>,
+    notify_requests: OnceLock<Arc<InFlightCounter>>,
+}
+
+impl Key {
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn open(path: &NtUnicodeStr<'_>, access: u32) -> NtResult<Self> {
+        Self::open_relative(0, path, access)
+    }
+
+    ///
+    /// Opens a key relative to this one
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn open_subkey(&self, name: &NtUnicodeStr<'_>, access: u32) -> NtResult<Self> {
+        Self::open_relative(self.raw_handle(), name, access)
+    }
+
+    ///
+    /// Opens the key or creates it as a non volatile key
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn create(path: &NtUnicodeStr<'_>, access: u32) -> NtResult<(Self, Disposition)> {
+        Self::create_relative(0, path, access)
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn create_subkey(
+        &self,
+        name: &NtUnicodeStr<'_>,
+        access: u32,
+    ) -> NtResult<(Self, Disposition)> {
+        Self::create_relative(self.raw_handle(), name, access)
+    }
+
+    fn open_relative(root: HANDLE, path: &NtUnicodeStr<'_>, access: u32) -> NtResult<Self> {
+        let attributes =
+            ObjectAttributes::new_named(path, OBJ_KERNEL_HANDLE | OBJ_CASE_INSENSITIVE)
+                .with_root_directory(root);
+
+        unsafe {
+            let mut handle: HANDLE = 0;
+            let status = ZwOpenKey(&mut handle, access, attributes.as_ref_mut());
+
+            NtResult::from_status(status, || Self::from_raw_handle(handle))
+        }
+    }
+
+    fn create_relative(
+        root: HANDLE,
+        path: &NtUnicodeStr<'_>,
+        access: u32,
+    ) -> NtResult<(Self, Disposition)> {
+        let attributes =
+            ObjectAttributes::new_named(path, OBJ_KERNEL_HANDLE | OBJ_CASE_INSENSITIVE)
+                .with_root_directory(root);
+
+        unsafe {
+            let mut handle: HANDLE = 0;
+            let mut disposition: u32 = 0;
+            let status = ZwCreateKey(
+                &mut handle,
+                access,
+                attributes.as_ref_mut(),
+                0,
+                core::ptr::null(),
+                REG_OPTION_NON_VOLATILE,
+                &mut disposition,
+            );
+
+            NtResult::from_status(status, || {
+                let disposition = if disposition == REG_CREATED_NEW_KEY {
+                    Disposition::Created
+                } else {
+                    Disposition::Opened
+                };
+
+                (Self::from_raw_handle(handle), disposition)
+            })
+        }
+    }
+
+    fn from_raw_handle(handle: HANDLE) -> Self {
+        Self {
+            handle: ManuallyDrop::new(Handle::new(KernelObjectType::Key, handle)),
+            notify_requests: OnceLock::new(),
+        }
+    }
+
+    #[inline]
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    #[inline]
+    fn raw_handle(&self) -> HANDLE {
+        unsafe { self.handle.raw_handle() }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn read_value(&self, name: &NtUnicodeStr<'_>) -> NtResult<RegValue> {
+        let buffer = query_buffer(|info, length, result_length| unsafe {
+            ZwQueryValueKey(
+                self.raw_handle(),
+                name.as_ptr().cast(),
+                KeyValuePartialInformation,
+                info,
+                length,
+                result_length,
+            )
+        })?;
+
+        let bytes = as_bytes(&buffer);
+        let info: &KEY_VALUE_PARTIAL_INFORMATION = unsafe { &*buffer.as_ptr().cast() };
+        let data = sub_slice(
+            bytes,
+            offset_of!(KEY_VALUE_PARTIAL_INFORMATION, Data),
+            info.DataLength,
+        )?;
+
+        RegValue::parse(info.Type, data)
+    }
+
+    ///
+    /// Fails with STATUS_OBJECT_TYPE_MISMATCH unless the value is a REG_DWORD
+    ///
+    pub fn read_dword(&self, name: &NtUnicodeStr<'_>) -> NtResult<u32> {
+        match self.read_value(name)? {
+            RegValue::Dword(value) => Ok(value),
+            _ => Err(NtStatusError::Status(STATUS_OBJECT_TYPE_MISMATCH)),
+        }
+    }
+
+    pub fn read_qword(&self, name: &NtUnicodeStr<'_>) -> NtResult<u64> {
+        match self.read_value(name)? {
+            RegValue::Qword(value) => Ok(value),
+            _ => Err(NtStatusError::Status(STATUS_OBJECT_TYPE_MISMATCH)),
+        }
+    }
+
+    ///
+    /// Accepts REG_SZ and REG_EXPAND_SZ, environment variables are not expanded
+    ///
+    pub fn read_string(&self, name: &NtUnicodeStr<'_>) -> NtResult<RegString> {
+        match self.read_value(name)? {
+            RegValue::String(s) | RegValue::ExpandString(s) => Ok(s),
+            _ => Err(NtStatusError::Status(STATUS_OBJECT_TYPE_MISMATCH)),
+        }
+    }
+
+    pub fn read_multi_string(&self, name: &NtUnicodeStr<'_>) -> NtResult<Vec<RegString>> {
+        match self.read_value(name)? {
+            RegValue::MultiString(strings) => Ok(strings),
+            _ => Err(NtStatusError::Status(STATUS_OBJECT_TYPE_MISMATCH)),
+        }
+    }
+
+    pub fn read_binary(&self, name: &NtUnicodeStr<'_>) -> NtResult<Vec<u8>> {
+        match self.read_value(name)? {
+            RegValue::Binary(data) => Ok(data),
+            _ => Err(NtStatusError::Status(STATUS_OBJECT_TYPE_MISMATCH)),
+        }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn write_value(&self, name: &NtUnicodeStr<'_>, value: &RegValue) -> NtResult<()> {
+        let data = value.encode()?;
+        self.write_raw(name, value.value_type(), &data)
+    }
+
+    pub fn write_dword(&self, name: &NtUnicodeStr<'_>, value: u32) -> NtResult<()> {
+        self.write_value(name, &RegValue::Dword(value))
+    }
+
+    pub fn write_qword(&self, name: &NtUnicodeStr<'_>, value: u64) -> NtResult<()> {
+        self.write_value(name, &RegValue::Qword(value))
+    }
+
+    pub fn write_string(&self, name: &NtUnicodeStr<'_>, value: &str) -> NtResult<()> {
+        self.write_value(name, &RegValue::String(RegString::try_from_str(value)?))
+    }
+
+    pub fn write_multi_string(&self, name: &NtUnicodeStr<'_>, values: &[&str]) -> NtResult<()> {
+        let mut strings = Vec::new_in(value_allocator());
+        strings
+            .try_reserve_exact(values.len())
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        for value in values {
+            strings.push(RegString::try_from_str(value)?);
+        }
+
+        self.write_value(name, &RegValue::MultiString(strings))
+    }
+
+    pub fn write_binary(&self, name: &NtUnicodeStr<'_>, data: &[u8]) -> NtResult<()> {
+        self.write_raw(name, REG_BINARY, data)
+    }
+
+    fn write_raw(&self, name: &NtUnicodeStr<'_>, value_type: u32, data: &[u8]) -> NtResult<()> {
+        let size = u32::try_from(data.len())
+            .map_err(|_| NtStatusError::Status(STATUS_INVALID_BUFFER_SIZE))?;
+
+        unsafe {
+            let status = ZwSetValueKey(
+                self.raw_handle(),
+                name.as_ptr().cast(),
+                0,
+                value_type,
+                data.as_ptr().cast(),
+                size,
+            );
+
+            NtResult::from_status(status, || ())
+        }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn delete_value(&self, name: &NtUnicodeStr<'_>) -> NtResult<()> {
+        unsafe {
+            let status = ZwDeleteValueKey(self.raw_handle(), name.as_ptr().cast());
+            NtResult::from_status(status, || ())
+        }
+    }
+
+    ///
+    /// Deletes the key, it must not have subkeys
+    ///
+    /// The key has to be opened with DELETE access, `KEY_ALL_ACCESS` has it.
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn delete(self) -> NtResult<()> {
+        unsafe {
+            let status = ZwDeleteKey(self.raw_handle());
+            NtResult::from_status(status, || ())
+        }
+    }
+
+    ///
+    /// Writes the key data to disk, only needed when it must survive a crash
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn flush(&self) -> NtResult<()> {
+        unsafe {
+            let status = ZwFlushKey(self.raw_handle());
+            NtResult::from_status(status, || ())
+        }
+    }
+
+    ///
+    /// Names of the direct subkeys
+    ///
+    pub fn subkeys(&self) -> SubKeys<'_> {
+        SubKeys {
+            key: self,
+            index: 0,
+            done: false,
+        }
+    }
+
+    ///
+    /// Names and data of the values of this key
+    ///
+    pub fn values(&self) -> Values<'_> {
+        Values {
+            key: self,
+            index: 0,
+            done: false,
+        }
+    }
+
+    ///
+    /// Signals `event` once when the key changes
+    ///
+    /// The notification is one shot, call it again after the event fires
+    /// to keep watching. Closing the key also signals the event.
+    /// The event is signaled from an `IO_WORKITEM` of `queue`, the I/O
+    /// manager keeps the driver loaded until it returns. Dropping the key
+    /// waits for its pending notifications, the key must be dropped before
+    /// `queue` because the queue waits for them as well.
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn notify_change(
+        &self,
+        queue: &WorkQueue,
+        filter: NotifyFilter,
+        watch_subtree: bool,
+        event: Arc<Event>,
+    ) -> NtResult<()> {
+        let pending = self
+            .notify_requests
+            .get_or_try_init(InFlightCounter::try_create_arc)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?
+            .enter_owned()
+            .expect("Key notifications only drain when the key is dropped");
+
+        //Released at the end of the IO_WORKITEM routine, not by the
+        //executive work item that ZwNotifyChangeKey queues
+        let work = queue
+            .prepare(move || {
+                event.signal();
+                drop(pending);
+            })
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        let request = Box::try_create(NotifyRequest {
+            item: unsafe { core::mem::zeroed() },
+            iosb: unsafe { core::mem::zeroed() },
+            work,
+        })
+        .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        unsafe {
+            let request = Box::into_raw(request);
+
+            //ExInitializeWorkItem
+            let worker: unsafe extern "system" fn(*mut c_void) = notify_worker;
+            (*request).item.WorkerRoutine = core::mem::transmute(worker);
+            (*request).item.Parameter = request.cast();
+
+            //Kernel callers pass a work item instead of an APC routine
+            let item: *mut WORK_QUEUE_ITEM = &mut (*request).item;
+            let status = ZwNotifyChangeKey(
+                self.raw_handle(),
+                0,
+                core::mem::transmute(item),
+                DelayedWorkQueue as usize as *const c_void,
+                &mut (*request).iosb,
+                filter.bits(),
+                watch_subtree as _,
+                core::ptr::null_mut(),
+                0,
+                true as _,
+            );
+
+            if !nt_success(status) {
+                drop(Box::from_raw_in(
+                    request,
+                    GlobalKernelAllocator::new_for_tagged::<NotifyRequest>(),
+                ));
+                return Err(NtStatusError::Status(status));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn enumerate(
+        &self,
+        index: u32,
+        f: impl Fn(HANDLE, u32, *mut c_void, u32, *mut u32) -> NTSTATUS,
+    ) -> Option<NtResult<Vec<u64>>> {
+        let handle = self.raw_handle();
+        let result = query_buffer(|info, length, result_length| {
+            f(handle, index, info, length, result_length)
+        });
+
+        match result {
+            Err(NtStatusError::Status(STATUS_NO_MORE_ENTRIES)) => None,
+            result => Some(result),
+        }
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        //Closing the key completes its pending notifications with STATUS_NOTIFY_CLEANUP,
+        //each one still queues its work item
+        unsafe { ManuallyDrop::drop(&mut self.handle) };
+
+        if let Some(requests) = self.notify_requests.get() {
+            requests.drain(None);
+        }
+    }
+}
+
+struct NotifyRequest {
+    item: WORK_QUEUE_ITEM,
+    iosb: IO_STATUS_BLOCK,
+    //Signals the event and releases the token `Key::drop` waits for
+    work: PreparedWork,
+}
+
+impl TaggedObject for NotifyRequest {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"rgnt")
+    }
+}
+
+unsafe extern "system" fn notify_worker(parameter: *mut c_void) {
+    let request = Box::from_raw_in(
+        parameter.cast::<NotifyRequest>(),
+        GlobalKernelAllocator::new_for_tagged::<NotifyRequest>(),
+    );
+
+    //Nothing runs here after the hand off, the IO_WORKITEM holds the
+    //driver reference from now on
+    let NotifyRequest { work, .. } = Box::into_inner(request);
+    work.queue(WorkQueueType::Delayed);
+}
+
+pub struct SubKeys<'a> {
+    key: &'a Key,
+    index: u32,
+    done: bool,
+}
+
+impl<'a> Iterator for SubKeys<'a> {
+    type Item = NtResult<RegString>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let buffer = self.key.enumerate(
+            self.index,
+            |handle, index, info, length, result_length| unsafe {
+                ZwEnumerateKey(
+                    handle,
+                    index,
+                    KeyBasicInformation,
+                    info,
+                    length,
+                    result_length,
+                )
+            },
+        );
+        self.index += 1;
+
+        let result = buffer.map(|buffer| {
+            let buffer = buffer?;
+            let info: &KEY_BASIC_INFORMATION = unsafe { &*buffer.as_ptr().cast() };
+            let name = sub_slice(
+                as_bytes(&buffer),
+                offset_of!(KEY_BASIC_INFORMATION, Name),
+                info.NameLength,
+            )?;
+
+            parse_string(name)
+        });
+
+        self.done = !matches!(result, Some(Ok(_)));
+        result
+    }
+}
+
+pub struct Values<'a> {
+    key: &'a Key,
+    index: u32,
+    done: bool,
+}
+
+impl<'a> Iterator for Values<'a> {
+    type Item = NtResult<(RegString, RegValue)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let buffer = self.key.enumerate(
+            self.index,
+            |handle, index, info, length, result_length| unsafe {
+                ZwEnumerateValueKey(
+                    handle,
+                    index,
+                    KeyValueFullInformation,
+                    info,
+                    length,
+                    result_length,
+                )
+            },
+        );
+        self.index += 1;
+
+        let result = buffer.map(|buffer| {
+            let buffer = buffer?;
+            let bytes = as_bytes(&buffer);
+            let info: &KEY_VALUE_FULL_INFORMATION = unsafe { &*buffer.as_ptr().cast() };
+
+            let name = sub_slice(
+                bytes,
+                offset_of!(KEY_VALUE_FULL_INFORMATION, Name),
+                info.NameLength,
+            )?;
+            let data = sub_slice(bytes, info.DataOffset as usize, info.DataLength)?;
+
+            Ok((parse_string(name)?, RegValue::parse(info.Type, data)?))
+        });
+
+        self.done = !matches!(result, Some(Ok(_)));
+        result
+    }
+}
+
+///
+/// Calls `f` with a growing buffer until the information fits
+///
+/// The buffer is made of `u64` so the structures placed at its start
+/// are aligned.
+///
+fn query_buffer(mut f: impl FnMut(*mut c_void, u32, *mut u32) -> NTSTATUS) -> NtResult<Vec<u64>> {
+    let mut buffer: Vec<u64> = Vec::new_in(GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"regq"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    ));
+    let mut size: u32 = 256;
+
+    loop {
+        let words = (size as usize).div_ceil(core::mem::size_of::<u64>());
+        buffer.clear();
+        buffer
+            .try_reserve_exact(words)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        buffer.resize(words, 0);
+
+        let mut result_length: u32 = 0;
+        let status = f(
+            buffer.as_mut_ptr().cast(),
+            (words * core::mem::size_of::<u64>()) as u32,
+            &mut result_length,
+        );
+
+        match status {
+            STATUS_BUFFER_OVERFLOW | STATUS_BUFFER_TOO_SMALL => {
+                //The value can grow between the two calls
+                size = core::cmp::max(result_length, size.saturating_mul(2));
+            }
+            status if nt_success(status) => return Ok(buffer),
+            status => return Err(NtStatusError::Status(status)),
+        }
+    }
+}
+
+fn as_bytes(buffer: &[u64]) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(buffer.as_ptr().cast(), core::mem::size_of_val(buffer)) }
+}
+
+fn sub_slice(bytes: &[u8], offset: usize, length: u32) -> NtResult<&[u8]> {
+    offset
+        .checked_add(length as usize)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or(NtStatusError::Status(STATUS_INVALID_BUFFER_SIZE))
+}
@@ -0,0 +1,242 @@
+use windows_sys::Win32::Foundation::{STATUS_NO_MEMORY, STATUS_OBJECT_NAME_NOT_FOUND};
+
+use crate::{vec::Vec, NtResult, NtStatusError};
+
+use super::{value::value_allocator, Key, RegString, RegValue};
+
+///
+/// Anything values can be read from by name
+///
+/// Implemented by `Key` and by `MemoryStore` so code reading settings can
+/// be exercised without a registry.
+///
+pub trait ValueStore {
+    ///
+    /// A missing value is `Ok(None)`, not an error
+    ///
+    fn read(&self, name: &str) -> NtResult<Option<RegValue>>;
+
+    fn read_as<T: FromRegValue>(&self, name: &str) -> NtResult<Option<Result<T, RegValue>>> {
+        Ok(self.read(name)?.map(T::from_reg_value))
+    }
+}
+
+impl ValueStore for Key {
+    fn read(&self, name: &str) -> NtResult<Option<RegValue>> {
+        let name = RegString::try_from_str(name)?;
+
+        match self.read_value(&name.as_unicode_str()?) {
+            Ok(value) => Ok(Some(value)),
+            Err(NtStatusError::Status(STATUS_OBJECT_NAME_NOT_FOUND)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+///
+/// Values kept in memory, names are compared ignoring ASCII case like the
+/// registry does
+///
+pub struct MemoryStore {
+    values: Vec<(RegString, RegValue)>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            values: Vec::new_in(value_allocator()),
+        }
+    }
+
+    ///
+    /// Adds the value or replaces the one with the same name
+    ///
+    pub fn try_insert(&mut self, name: &str, value: RegValue) -> NtResult<()> {
+        if let Some(index) = self.position(name) {
+            self.values[index].1 = value;
+            return Ok(());
+        }
+
+        let name = RegString::try_from_str(name)?;
+        self.values
+            .try_reserve(1)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        self.values.push((name, value));
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<RegValue> {
+        self.position(name)
+            .map(|index| self.values.swap_remove(index).1)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegValue> {
+        self.position(name).map(|index| &self.values[index].1)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.values
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValueStore for MemoryStore {
+    fn read(&self, name: &str) -> NtResult<Option<RegValue>> {
+        match self.get(name) {
+            Some(value) => value.try_clone().map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+///
+/// Conversion from a registry value, the value is handed back when its
+/// type does not match
+///
+pub trait FromRegValue: Sized {
+    fn from_reg_value(value: RegValue) -> Result<Self, RegValue>;
+}
+
+macro_rules! impl_from_reg_value_int {
+    ($($ty:ty),*) => {
+        $(
+            ///
+            /// Accepts REG_DWORD and REG_QWORD when the number fits
+            ///
+            impl FromRegValue for $ty {
+                fn from_reg_value(value: RegValue) -> Result<Self, RegValue> {
+                    value
+                        .as_u64()
+                        .and_then(|v| <$ty>::try_from(v).ok())
+                        .ok_or(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_reg_value_int!(u8, u16, u32, u64, usize);
+
+///
+/// Reinterprets the REG_DWORD bits, the way `reg add /t REG_DWORD /d -1` stores it
+///
+impl FromRegValue for i32 {
+    fn from_reg_value(value: RegValue) -> Result<Self, RegValue> {
+        match value {
+            RegValue::Dword(v) => Ok(v as i32),
+            value => Err(value),
+        }
+    }
+}
+
+impl FromRegValue for i64 {
+    fn from_reg_value(value: RegValue) -> Result<Self, RegValue> {
+        match value {
+            RegValue::Dword(v) => Ok(v as i32 as i64),
+            RegValue::Qword(v) => Ok(v as i64),
+            value => Err(value),
+        }
+    }
+}
+
+///
+/// Any non zero DWORD is `true`
+///
+impl FromRegValue for bool {
+    fn from_reg_value(value: RegValue) -> Result<Self, RegValue> {
+        match value {
+            RegValue::Dword(v) => Ok(v != 0),
+            value => Err(value),
+        }
+    }
+}
+
+impl FromRegValue for RegString {
+    fn from_reg_value(value: RegValue) -> Result<Self, RegValue> {
+        match value {
+            RegValue::String(s) | RegValue::ExpandString(s) => Ok(s),
+            value => Err(value),
+        }
+    }
+}
+
+impl FromRegValue for Vec<RegString> {
+    fn from_reg_value(value: RegValue) -> Result<Self, RegValue> {
+        match value {
+            RegValue::MultiString(strings) => Ok(strings),
+            value => Err(value),
+        }
+    }
+}
+
+impl FromRegValue for Vec<u8> {
+    fn from_reg_value(value: RegValue) -> Result<Self, RegValue> {
+        match value {
+            RegValue::Binary(data) => Ok(data),
+            value => Err(value),
+        }
+    }
+}
+
+impl FromRegValue for RegValue {
+    fn from_reg_value(value: RegValue) -> Result<Self, RegValue> {
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::{MemoryStore, RegString, RegValue, ValueStore};
+
+    extern crate std;
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let mut store = MemoryStore::new();
+        store.try_insert("LogLevel", RegValue::Dword(3))?;
+        store.try_insert("Name", RegValue::String(RegString::try_from_str("wdrf")?))?;
+        store.try_insert("loglevel", RegValue::Dword(300))?;
+        assert_eq!(store.len(), 2);
+
+        assert_eq!(store.read_as::<u32>("LOGLEVEL")?, Some(Ok(300)));
+        assert_eq!(
+            store.read_as::<u8>("LogLevel")?,
+            Some(Err(RegValue::Dword(300)))
+        );
+        assert!(store.read_as::<u32>("Name")?.unwrap().is_err());
+        assert!(store
+            .read_as::<RegString>("Name")?
+            .unwrap()
+            .unwrap()
+            .eq_str("wdrf"));
+        assert_eq!(store.read("Missing")?, None);
+
+        assert_eq!(
+            store
+                .remove("name")
+                .and_then(|v| v.as_string().map(|s| s.len())),
+            Some(4)
+        );
+        assert!(store.get("Name").is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,390 @@
+use core::fmt::{Display, Write};
+
+use nt_string::unicode_string::NtUnicodeStr;
+use windows_sys::Win32::{
+    Foundation::{STATUS_INVALID_BUFFER_SIZE, STATUS_NAME_TOO_LONG, STATUS_NO_MEMORY},
+    System::Registry::{REG_BINARY, REG_DWORD, REG_EXPAND_SZ, REG_MULTI_SZ, REG_QWORD, REG_SZ},
+};
+
+use crate::{
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    vec::Vec,
+    NtResult, NtStatusError,
+};
+
+pub(super) fn value_allocator() -> GlobalKernelAllocator {
+    GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"regv"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    )
+}
+
+pub(super) fn try_vec_from<T: Clone>(data: &[T]) -> NtResult<Vec<T>> {
+    let mut vec = Vec::new_in(value_allocator());
+    vec.try_reserve_exact(data.len())
+        .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+    vec.extend_from_slice(data);
+
+    Ok(vec)
+}
+
+///
+/// Owned UTF-16 string without the terminating nul
+///
+#[derive(Clone, PartialEq, Eq)]
+pub struct RegString {
+    buffer: Vec<u16>,
+}
+
+impl RegString {
+    pub fn try_from_u16(s: &[u16]) -> NtResult<Self> {
+        Ok(Self {
+            buffer: try_vec_from(s)?,
+        })
+    }
+
+    pub fn try_from_str(s: &str) -> NtResult<Self> {
+        let mut buffer = Vec::new_in(value_allocator());
+        buffer
+            .try_reserve_exact(s.encode_utf16().count())
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        buffer.extend(s.encode_utf16());
+
+        Ok(Self { buffer })
+    }
+
+    ///
+    /// Copies the string, the registry strings read back from the kernel
+    /// are never longer than a `UNICODE_STRING` can hold
+    ///
+    pub fn try_from_unicode_str(s: &NtUnicodeStr<'_>) -> NtResult<Self> {
+        Self::try_from_u16(s.as_slice())
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[u16] {
+        &self.buffer
+    }
+
+    ///
+    /// Borrows the string as a `UNICODE_STRING`, fails above 32767 characters
+    ///
+    pub fn as_unicode_str(&self) -> NtResult<NtUnicodeStr<'_>> {
+        NtUnicodeStr::try_from_u16(&self.buffer)
+            .map_err(|_| NtStatusError::Status(STATUS_NAME_TOO_LONG))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    ///
+    /// Invalid UTF-16 is replaced with U+FFFD
+    ///
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        char::decode_utf16(self.buffer.iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    pub fn eq_str(&self, other: &str) -> bool {
+        self.buffer.iter().copied().eq(other.encode_utf16())
+    }
+
+    pub fn eq_ignore_ascii_case(&self, other: &str) -> bool {
+        let lower = |c: u16| {
+            if (b'A' as u16..=b'Z' as u16).contains(&c) {
+                c + 32
+            } else {
+                c
+            }
+        };
+
+        self.buffer
+            .iter()
+            .copied()
+            .map(lower)
+            .eq(other.encode_utf16().map(lower))
+    }
+}
+
+impl Display for RegString {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for c in self.chars() {
+            f.write_char(c)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for RegString {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+///
+/// A registry value with its data copied out of the key
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegValue {
+    Dword(u32),
+    Qword(u64),
+    String(RegString),
+    ExpandString(RegString),
+    MultiString(Vec<RegString>),
+    Binary(Vec<u8>),
+    Other { value_type: u32, data: Vec<u8> },
+}
+
+impl RegValue {
+    ///
+    /// Builds the value from the raw data stored for `value_type`
+    ///
+    pub fn parse(value_type: u32, data: &[u8]) -> NtResult<Self> {
+        let value = match value_type {
+            REG_DWORD => RegValue::Dword(u32::from_le_bytes(fixed_size(data)?)),
+            REG_QWORD => RegValue::Qword(u64::from_le_bytes(fixed_size(data)?)),
+            REG_SZ => RegValue::String(parse_string(data)?),
+            REG_EXPAND_SZ => RegValue::ExpandString(parse_string(data)?),
+            REG_MULTI_SZ => RegValue::MultiString(parse_multi_string(data)?),
+            REG_BINARY => RegValue::Binary(try_vec_from(data)?),
+            value_type => RegValue::Other {
+                value_type,
+                data: try_vec_from(data)?,
+            },
+        };
+
+        Ok(value)
+    }
+
+    ///
+    /// `Clone` without aborting when the pool is exhausted
+    ///
+    pub fn try_clone(&self) -> NtResult<Self> {
+        let value = match self {
+            RegValue::Dword(value) => RegValue::Dword(*value),
+            RegValue::Qword(value) => RegValue::Qword(*value),
+            RegValue::String(s) => RegValue::String(RegString::try_from_u16(s.as_slice())?),
+            RegValue::ExpandString(s) => {
+                RegValue::ExpandString(RegString::try_from_u16(s.as_slice())?)
+            }
+            RegValue::MultiString(strings) => {
+                let mut copy = Vec::new_in(value_allocator());
+                copy.try_reserve_exact(strings.len())
+                    .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+                for s in strings {
+                    copy.push(RegString::try_from_u16(s.as_slice())?);
+                }
+
+                RegValue::MultiString(copy)
+            }
+            RegValue::Binary(data) => RegValue::Binary(try_vec_from(data)?),
+            RegValue::Other { value_type, data } => RegValue::Other {
+                value_type: *value_type,
+                data: try_vec_from(data)?,
+            },
+        };
+
+        Ok(value)
+    }
+
+    pub fn value_type(&self) -> u32 {
+        match self {
+            RegValue::Dword(_) => REG_DWORD,
+            RegValue::Qword(_) => REG_QWORD,
+            RegValue::String(_) => REG_SZ,
+            RegValue::ExpandString(_) => REG_EXPAND_SZ,
+            RegValue::MultiString(_) => REG_MULTI_SZ,
+            RegValue::Binary(_) => REG_BINARY,
+            RegValue::Other { value_type, .. } => *value_type,
+        }
+    }
+
+    ///
+    /// The data as it is stored in the registry, strings are nul terminated
+    ///
+    pub fn encode(&self) -> NtResult<Vec<u8>> {
+        match self {
+            RegValue::Dword(value) => try_vec_from(&value.to_le_bytes()),
+            RegValue::Qword(value) => try_vec_from(&value.to_le_bytes()),
+            RegValue::String(s) | RegValue::ExpandString(s) => {
+                encode_strings(core::iter::once(s.as_slice()), false)
+            }
+            RegValue::MultiString(strings) => {
+                encode_strings(strings.iter().map(RegString::as_slice), true)
+            }
+            RegValue::Binary(data) | RegValue::Other { data, .. } => try_vec_from(data),
+        }
+    }
+
+    ///
+    /// DWORD and QWORD values, QWORD only if it fits
+    ///
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            RegValue::Dword(value) => Some(*value),
+            RegValue::Qword(value) => u32::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            RegValue::Dword(value) => Some(*value as u64),
+            RegValue::Qword(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    ///
+    /// REG_SZ and REG_EXPAND_SZ, the latter is not expanded
+    ///
+    pub fn as_string(&self) -> Option<&RegString> {
+        match self {
+            RegValue::String(s) | RegValue::ExpandString(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_multi_string(&self) -> Option<&[RegString]> {
+        match self {
+            RegValue::MultiString(strings) => Some(strings),
+            _ => None,
+        }
+    }
+
+    pub fn as_binary(&self) -> Option<&[u8]> {
+        match self {
+            RegValue::Binary(data) => Some(data),
+            _ => None,
+        }
+    }
+}
+
+fn fixed_size<const N: usize>(data: &[u8]) -> NtResult<[u8; N]> {
+    data.try_into()
+        .map_err(|_| NtStatusError::Status(STATUS_INVALID_BUFFER_SIZE))
+}
+
+fn to_u16(data: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    //An odd trailing byte can not be part of a character
+    data.chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+}
+
+fn string_from_iter(chars: impl Iterator<Item = u16> + Clone) -> NtResult<RegString> {
+    let mut buffer = Vec::new_in(value_allocator());
+    buffer
+        .try_reserve_exact(chars.clone().count())
+        .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+    buffer.extend(chars);
+
+    Ok(RegString { buffer })
+}
+
+///
+/// The terminating nul is optional, writers do not always store it
+///
+pub(super) fn parse_string(data: &[u8]) -> NtResult<RegString> {
+    string_from_iter(to_u16(data).take_while(|c| *c != 0))
+}
+
+///
+/// Strings are separated by a nul, an empty string ends the list
+///
+fn parse_multi_string(data: &[u8]) -> NtResult<Vec<RegString>> {
+    let mut strings = Vec::new_in(value_allocator());
+
+    let chars: Vec<u16> = {
+        let mut chars = Vec::new_in(value_allocator());
+        chars
+            .try_reserve_exact(data.len() / 2)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        chars.extend(to_u16(data));
+        chars
+    };
+
+    for part in chars.split(|c| *c == 0) {
+        if part.is_empty() {
+            break;
+        }
+
+        strings
+            .try_reserve(1)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        strings.push(RegString::try_from_u16(part)?);
+    }
+
+    Ok(strings)
+}
+
+fn encode_strings<'a>(
+    strings: impl Iterator<Item = &'a [u16]> + Clone,
+    multi: bool,
+) -> NtResult<Vec<u8>> {
+    let chars: usize = strings.clone().map(|s| s.len() + 1).sum();
+    let total = (chars + multi as usize) * 2;
+
+    let mut data = Vec::new_in(value_allocator());
+    data.try_reserve_exact(total)
+        .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+    for s in strings {
+        for c in s.iter().chain(core::iter::once(&0)) {
+            data.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    if multi {
+        data.extend_from_slice(&[0, 0]);
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use windows_sys::Win32::System::Registry::{REG_DWORD, REG_MULTI_SZ, REG_SZ};
+
+    use super::{RegString, RegValue};
+
+    extern crate std;
+
+    fn utf16_bytes(s: &str) -> std::vec::Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let value = RegValue::parse(REG_DWORD, &42u32.to_le_bytes())?;
+        assert_eq!(value.as_u32(), Some(42));
+        assert!(RegValue::parse(REG_DWORD, &[1, 2]).is_err());
+
+        //With and without the terminating nul
+        for raw in ["C:\\log\0", "C:\\log"] {
+            let value = RegValue::parse(REG_SZ, &utf16_bytes(raw))?;
+            assert!(value.as_string().unwrap().eq_str("C:\\log"));
+        }
+
+        let raw = utf16_bytes("one\0two\0\0");
+        let value = RegValue::parse(REG_MULTI_SZ, &raw)?;
+        let strings = value.as_multi_string().unwrap();
+        assert_eq!(strings.len(), 2);
+        assert!(strings[1].eq_str("two"));
+        assert_eq!(value.encode()?.as_slice(), raw.as_slice());
+
+        let value = RegValue::String(RegString::try_from_str("Info")?);
+        assert_eq!(value.encode()?.as_slice(), utf16_bytes("Info\0").as_slice());
+        assert!(RegString::try_from_str("INFO")?.eq_ignore_ascii_case("info"));
+        assert_eq!(std::format!("{}", RegString::try_from_str("Info")?), "Info");
+
+        Ok(())
+    }
+}
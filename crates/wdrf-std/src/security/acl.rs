@@ -0,0 +1,155 @@
+use windows_sys::Win32::Foundation::{STATUS_INVALID_ACL, STATUS_NO_MEMORY};
+
+use crate::{vec::Vec, NtResult, NtStatusError};
+
+use super::sid::{security_allocator, Sid};
+
+const ACL_REVISION: u8 = 2;
+const ACL_HEADER_LEN: usize = 8;
+const ACE_HEADER_LEN: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum AceType {
+    AccessAllowed = 0,
+    AccessDenied = 1,
+    SystemAudit = 2,
+    SystemAlarm = 3,
+    MandatoryLabel = 0x11,
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct AceFlags : u8 {
+        const OBJECT_INHERIT = 0x01;
+        const CONTAINER_INHERIT = 0x02;
+        const NO_PROPAGATE_INHERIT = 0x04;
+        const INHERIT_ONLY = 0x08;
+        const INHERITED = 0x10;
+        const SUCCESSFUL_ACCESS = 0x40;
+        const FAILED_ACCESS = 0x80;
+    }
+}
+
+bitflags::bitflags! {
+    ///
+    /// The `P`, `AI` and `AR` flags of an SDDL ACL
+    ///
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct AclControl : u8 {
+        const PROTECTED = 0x01;
+        const AUTO_INHERITED = 0x02;
+        const AUTO_INHERIT_REQ = 0x04;
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Ace {
+    pub ace_type: AceType,
+    pub flags: AceFlags,
+    pub mask: u32,
+    pub sid: Sid,
+}
+
+impl Ace {
+    pub fn new(ace_type: AceType, flags: AceFlags, mask: u32, sid: Sid) -> Self {
+        Self {
+            ace_type,
+            flags,
+            mask,
+            sid,
+        }
+    }
+
+    #[inline]
+    pub fn binary_len(&self) -> usize {
+        ACE_HEADER_LEN + self.sid.binary_len()
+    }
+}
+
+///
+/// Access control list, the ACEs are kept in the order they were added
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Acl {
+    control: AclControl,
+    aces: Vec<Ace>,
+}
+
+impl Acl {
+    pub fn new() -> Self {
+        Self::with_control(AclControl::empty())
+    }
+
+    pub fn with_control(control: AclControl) -> Self {
+        Self {
+            control,
+            aces: Vec::new_in(security_allocator()),
+        }
+    }
+
+    pub fn try_push(&mut self, ace: Ace) -> NtResult<()> {
+        self.aces
+            .try_reserve(1)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        self.aces.push(ace);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn control(&self) -> AclControl {
+        self.control
+    }
+
+    #[inline]
+    pub fn set_control(&mut self, control: AclControl) {
+        self.control = control;
+    }
+
+    #[inline]
+    pub fn aces(&self) -> &[Ace] {
+        &self.aces
+    }
+
+    ///
+    /// Size of the binary `ACL`, which is limited to 64K
+    ///
+    pub fn binary_len(&self) -> NtResult<usize> {
+        let len = ACL_HEADER_LEN + self.aces.iter().map(Ace::binary_len).sum::<usize>();
+        if len > u16::MAX as usize || self.aces.len() > u16::MAX as usize {
+            return Err(NtStatusError::Status(STATUS_INVALID_ACL));
+        }
+
+        Ok(len)
+    }
+
+    ///
+    /// Appends the binary `ACL`, `out` must have room for `binary_len` bytes
+    ///
+    pub(super) fn write_to(&self, out: &mut Vec<u8>) -> NtResult<()> {
+        let len = self.binary_len()?;
+
+        out.push(ACL_REVISION);
+        out.push(0);
+        out.extend_from_slice(&(len as u16).to_le_bytes());
+        out.extend_from_slice(&(self.aces.len() as u16).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+
+        for ace in self.aces.iter() {
+            out.push(ace.ace_type as u8);
+            out.push(ace.flags.bits());
+            out.extend_from_slice(&(ace.binary_len() as u16).to_le_bytes());
+            out.extend_from_slice(&ace.mask.to_le_bytes());
+            ace.sid.write_to(out);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
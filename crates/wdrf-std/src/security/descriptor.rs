@@ -0,0 +1,262 @@
+use windows_sys::Win32::{
+    Foundation::{STATUS_INVALID_SECURITY_DESCR, STATUS_NO_MEMORY},
+    Security::PSECURITY_DESCRIPTOR,
+};
+
+use crate::{vec::Vec, NtResult, NtStatusError};
+
+use super::{
+    acl::{Ace, AceFlags, AceType, Acl, AclControl},
+    sid::{security_allocator, Sid},
+};
+
+const SECURITY_DESCRIPTOR_REVISION: u8 = 1;
+const HEADER_LEN: usize = 20;
+
+const SE_DACL_PRESENT: u16 = 0x0004;
+const SE_SACL_PRESENT: u16 = 0x0010;
+const SE_DACL_AUTO_INHERIT_REQ: u16 = 0x0100;
+const SE_SACL_AUTO_INHERIT_REQ: u16 = 0x0200;
+const SE_DACL_AUTO_INHERITED: u16 = 0x0400;
+const SE_SACL_AUTO_INHERITED: u16 = 0x0800;
+const SE_DACL_PROTECTED: u16 = 0x1000;
+const SE_SACL_PROTECTED: u16 = 0x2000;
+const SE_SELF_RELATIVE: u16 = 0x8000;
+
+///
+/// Self-relative `SECURITY_DESCRIPTOR` in a single allocation
+///
+/// Can be handed to `ObjectAttributes::new_named_security` or to any
+/// routine taking a `PSECURITY_DESCRIPTOR`.
+///
+pub struct SecurityDescriptor {
+    buffer: Vec<u8>,
+    raw: PSECURITY_DESCRIPTOR,
+}
+
+unsafe impl Send for SecurityDescriptor {}
+unsafe impl Sync for SecurityDescriptor {}
+
+impl SecurityDescriptor {
+    #[inline]
+    pub fn as_ptr(&self) -> PSECURITY_DESCRIPTOR {
+        self.raw
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+impl AsRef<PSECURITY_DESCRIPTOR> for SecurityDescriptor {
+    fn as_ref(&self) -> &PSECURITY_DESCRIPTOR {
+        &self.raw
+    }
+}
+
+///
+/// Owner, group and ACLs of a security descriptor before it is serialized
+///
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SecurityDescriptorBuilder {
+    owner: Option<Sid>,
+    group: Option<Sid>,
+    dacl: Option<Acl>,
+    sacl: Option<Acl>,
+}
+
+impl SecurityDescriptorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn owner(mut self, owner: Sid) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    pub fn group(mut self, group: Sid) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    ///
+    /// An empty DACL denies every access, no DACL at all grants every access
+    ///
+    pub fn dacl(mut self, dacl: Acl) -> Self {
+        self.dacl = Some(dacl);
+        self
+    }
+
+    pub fn sacl(mut self, sacl: Acl) -> Self {
+        self.sacl = Some(sacl);
+        self
+    }
+
+    pub fn allow(self, sid: Sid, mask: u32) -> NtResult<Self> {
+        self.push_dacl(Ace::new(
+            AceType::AccessAllowed,
+            AceFlags::empty(),
+            mask,
+            sid,
+        ))
+    }
+
+    ///
+    /// Deny ACEs are only checked first if they are added before the allow ones
+    ///
+    pub fn deny(self, sid: Sid, mask: u32) -> NtResult<Self> {
+        self.push_dacl(Ace::new(
+            AceType::AccessDenied,
+            AceFlags::empty(),
+            mask,
+            sid,
+        ))
+    }
+
+    ///
+    /// Adds a mandatory label, `policy` is a mix of the `NW`, `NR` and `NX` bits
+    ///
+    pub fn mandatory_label(mut self, integrity: Sid, policy: u32) -> NtResult<Self> {
+        self.sacl.get_or_insert_with(Acl::new).try_push(Ace::new(
+            AceType::MandatoryLabel,
+            AceFlags::empty(),
+            policy,
+            integrity,
+        ))?;
+
+        Ok(self)
+    }
+
+    fn push_dacl(mut self, ace: Ace) -> NtResult<Self> {
+        self.dacl.get_or_insert_with(Acl::new).try_push(ace)?;
+
+        Ok(self)
+    }
+
+    #[inline]
+    pub fn get_owner(&self) -> Option<&Sid> {
+        self.owner.as_ref()
+    }
+
+    #[inline]
+    pub fn get_group(&self) -> Option<&Sid> {
+        self.group.as_ref()
+    }
+
+    #[inline]
+    pub fn get_dacl(&self) -> Option<&Acl> {
+        self.dacl.as_ref()
+    }
+
+    #[inline]
+    pub fn get_sacl(&self) -> Option<&Acl> {
+        self.sacl.as_ref()
+    }
+
+    ///
+    /// Serializes the descriptor in the same layout as `RtlMakeSelfRelativeSD`,
+    /// SACL, DACL, owner and group after the header
+    ///
+    pub fn build(&self) -> NtResult<SecurityDescriptor> {
+        let sacl_len = self.sacl.as_ref().map(Acl::binary_len).transpose()?;
+        let dacl_len = self.dacl.as_ref().map(Acl::binary_len).transpose()?;
+        let owner_len = self.owner.as_ref().map(Sid::binary_len);
+        let group_len = self.group.as_ref().map(Sid::binary_len);
+
+        let mut offset = HEADER_LEN;
+        let mut place = |len: Option<usize>| match len {
+            Some(len) => {
+                let at = offset;
+                offset += len;
+                at as u32
+            }
+            None => 0,
+        };
+        let sacl_offset = place(sacl_len);
+        let dacl_offset = place(dacl_len);
+        let owner_offset = place(owner_len);
+        let group_offset = place(group_len);
+        let total = offset;
+
+        if total > u32::MAX as usize {
+            return Err(NtStatusError::Status(STATUS_INVALID_SECURITY_DESCR));
+        }
+
+        let mut control = SE_SELF_RELATIVE;
+        if let Some(dacl) = &self.dacl {
+            control |= SE_DACL_PRESENT
+                | acl_control(
+                    dacl.control(),
+                    SE_DACL_PROTECTED,
+                    SE_DACL_AUTO_INHERITED,
+                    SE_DACL_AUTO_INHERIT_REQ,
+                );
+        }
+        if let Some(sacl) = &self.sacl {
+            control |= SE_SACL_PRESENT
+                | acl_control(
+                    sacl.control(),
+                    SE_SACL_PROTECTED,
+                    SE_SACL_AUTO_INHERITED,
+                    SE_SACL_AUTO_INHERIT_REQ,
+                );
+        }
+
+        let mut buffer = Vec::new_in(security_allocator());
+        buffer
+            .try_reserve_exact(total)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        buffer.push(SECURITY_DESCRIPTOR_REVISION);
+        buffer.push(0);
+        buffer.extend_from_slice(&control.to_le_bytes());
+        buffer.extend_from_slice(&owner_offset.to_le_bytes());
+        buffer.extend_from_slice(&group_offset.to_le_bytes());
+        buffer.extend_from_slice(&sacl_offset.to_le_bytes());
+        buffer.extend_from_slice(&dacl_offset.to_le_bytes());
+
+        if let Some(sacl) = &self.sacl {
+            sacl.write_to(&mut buffer)?;
+        }
+        if let Some(dacl) = &self.dacl {
+            dacl.write_to(&mut buffer)?;
+        }
+        if let Some(owner) = &self.owner {
+            owner.write_to(&mut buffer);
+        }
+        if let Some(group) = &self.group {
+            group.write_to(&mut buffer);
+        }
+        debug_assert_eq!(buffer.len(), total);
+
+        let raw = buffer.as_mut_ptr().cast();
+        Ok(SecurityDescriptor { buffer, raw })
+    }
+}
+
+fn acl_control(control: AclControl, protected: u16, inherited: u16, inherit_req: u16) -> u16 {
+    let mut bits = 0;
+    if control.contains(AclControl::PROTECTED) {
+        bits |= protected;
+    }
+    if control.contains(AclControl::AUTO_INHERITED) {
+        bits |= inherited;
+    }
+    if control.contains(AclControl::AUTO_INHERIT_REQ) {
+        bits |= inherit_req;
+    }
+
+    bits
+}
@@ -0,0 +1,19 @@
+//!
+//! Security identifiers, ACLs and self-relative security descriptors
+//!
+//! Descriptors are built in memory, either with `SecurityDescriptorBuilder`
+//! or from an SDDL string, and do not need any kernel routine to be
+//! serialized.
+//!
+
+mod acl;
+mod descriptor;
+mod sddl;
+mod sid;
+mod token;
+
+pub use acl::{Ace, AceFlags, AceType, Acl, AclControl};
+pub use descriptor::{SecurityDescriptor, SecurityDescriptorBuilder};
+pub use sddl::SddlError;
+pub use sid::{Sid, WellKnownSid};
+pub use token::{ElevationType, Group, ImpersonationLevel, IntegrityLevel, Privilege, Token};
@@ -0,0 +1,572 @@
+use core::fmt::{Display, Write};
+
+use thiserror::Error;
+use windows_sys::Win32::Foundation::{NTSTATUS, STATUS_INVALID_PARAMETER};
+
+use crate::NtStatusError;
+
+use super::{
+    acl::{Ace, AceFlags, AceType, Acl, AclControl},
+    descriptor::{SecurityDescriptor, SecurityDescriptorBuilder},
+    sid::{Sid, WellKnownSid},
+};
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SddlError {
+    #[error("Invalid SDDL at offset {0}")]
+    Syntax(usize),
+    #[error("Unknown SID alias at offset {0}")]
+    UnknownAlias(usize),
+    #[error("Unsupported ACE at offset {0}")]
+    UnsupportedAce(usize),
+    #[error("Component given twice at offset {0}")]
+    Duplicate(usize),
+    #[error("NtStatus code: {0:X}")]
+    Status(NTSTATUS),
+}
+
+impl SddlError {
+    pub fn status(&self) -> NTSTATUS {
+        match self {
+            SddlError::Status(status) => *status,
+            _ => STATUS_INVALID_PARAMETER,
+        }
+    }
+}
+
+impl From<NtStatusError> for SddlError {
+    fn from(value: NtStatusError) -> Self {
+        match value {
+            NtStatusError::Status(status) => SddlError::Status(status),
+        }
+    }
+}
+
+impl From<SddlError> for NtStatusError {
+    fn from(value: SddlError) -> Self {
+        NtStatusError::Status(value.status())
+    }
+}
+
+///
+/// SID strings that do not depend on the machine or domain
+///
+const SID_ALIASES: &[(&str, WellKnownSid)] = &[
+    ("WD", WellKnownSid::World),
+    ("CO", WellKnownSid::CreatorOwner),
+    ("CG", WellKnownSid::CreatorGroup),
+    ("OW", WellKnownSid::OwnerRights),
+    ("NU", WellKnownSid::Network),
+    ("IU", WellKnownSid::Interactive),
+    ("SU", WellKnownSid::Service),
+    ("AN", WellKnownSid::Anonymous),
+    ("PS", WellKnownSid::PrincipalSelf),
+    ("AU", WellKnownSid::AuthenticatedUsers),
+    ("RC", WellKnownSid::Restricted),
+    ("SY", WellKnownSid::LocalSystem),
+    ("LS", WellKnownSid::LocalService),
+    ("NS", WellKnownSid::NetworkService),
+    ("BA", WellKnownSid::Administrators),
+    ("BU", WellKnownSid::Users),
+    ("BG", WellKnownSid::Guests),
+    ("PU", WellKnownSid::PowerUsers),
+    ("AO", WellKnownSid::AccountOperators),
+    ("SO", WellKnownSid::SystemOperators),
+    ("BO", WellKnownSid::BackupOperators),
+    ("RE", WellKnownSid::Replicator),
+    ("RU", WellKnownSid::PreWindows2000CompatibleAccess),
+    ("RD", WellKnownSid::RemoteDesktopUsers),
+    ("NO", WellKnownSid::NetworkConfigurationOperators),
+    ("LU", WellKnownSid::PerformanceLogUsers),
+    ("ER", WellKnownSid::EventLogReaders),
+    ("AC", WellKnownSid::AllAppPackages),
+    ("LW", WellKnownSid::LowIntegrity),
+    ("ME", WellKnownSid::MediumIntegrity),
+    ("MP", WellKnownSid::MediumPlusIntegrity),
+    ("HI", WellKnownSid::HighIntegrity),
+    ("SI", WellKnownSid::SystemIntegrity),
+];
+
+const ACE_TYPES: &[(&str, AceType)] = &[
+    ("A", AceType::AccessAllowed),
+    ("D", AceType::AccessDenied),
+    ("AU", AceType::SystemAudit),
+    ("AL", AceType::SystemAlarm),
+    ("ML", AceType::MandatoryLabel),
+];
+
+const ACE_FLAGS: &[(&str, AceFlags)] = &[
+    ("OI", AceFlags::OBJECT_INHERIT),
+    ("CI", AceFlags::CONTAINER_INHERIT),
+    ("NP", AceFlags::NO_PROPAGATE_INHERIT),
+    ("IO", AceFlags::INHERIT_ONLY),
+    ("ID", AceFlags::INHERITED),
+    ("SA", AceFlags::SUCCESSFUL_ACCESS),
+    ("FA", AceFlags::FAILED_ACCESS),
+];
+
+const ACL_FLAGS: &[(&str, AclControl)] = &[
+    ("P", AclControl::PROTECTED),
+    ("AI", AclControl::AUTO_INHERITED),
+    ("AR", AclControl::AUTO_INHERIT_REQ),
+];
+
+///
+/// Rights made of several bits, tried first when formatting
+///
+const COMPOSITE_RIGHTS: &[(&str, u32)] = &[
+    ("FA", 0x001F_01FF),
+    ("FR", 0x0012_0089),
+    ("FW", 0x0012_0116),
+    ("FX", 0x0012_00A0),
+    ("KA", 0x000F_003F),
+    ("KR", 0x0002_0019),
+    ("KW", 0x0002_0006),
+    ("KX", 0x0002_0019),
+];
+
+const RIGHTS: &[(&str, u32)] = &[
+    ("GA", 0x1000_0000),
+    ("GR", 0x8000_0000),
+    ("GW", 0x4000_0000),
+    ("GX", 0x2000_0000),
+    ("RC", 0x0002_0000),
+    ("SD", 0x0001_0000),
+    ("WD", 0x0004_0000),
+    ("WO", 0x0008_0000),
+    ("CC", 0x0000_0001),
+    ("DC", 0x0000_0002),
+    ("LC", 0x0000_0004),
+    ("SW", 0x0000_0008),
+    ("RP", 0x0000_0010),
+    ("WP", 0x0000_0020),
+    ("DT", 0x0000_0040),
+    ("LO", 0x0000_0080),
+    ("CR", 0x0000_0100),
+];
+
+///
+/// Policy bits of a mandatory label ACE
+///
+const LABEL_RIGHTS: &[(&str, u32)] = &[("NW", 0x1), ("NR", 0x2), ("NX", 0x4)];
+
+impl SecurityDescriptorBuilder {
+    ///
+    /// Parses an SDDL string such as `O:BAG:SYD:P(A;;GA;;;SY)(A;;GR;;;AU)`
+    ///
+    /// Object ACEs, conditional ACEs, resource attributes and the domain
+    /// relative aliases (`DA`, `DU`, ...) are not supported.
+    ///
+    pub fn from_sddl(sddl: &str) -> Result<Self, SddlError> {
+        Parser { sddl, pos: 0 }.descriptor()
+    }
+}
+
+impl SecurityDescriptor {
+    pub fn from_sddl(sddl: &str) -> Result<Self, SddlError> {
+        Ok(SecurityDescriptorBuilder::from_sddl(sddl)?.build()?)
+    }
+}
+
+fn sid_from_alias(alias: &str) -> Option<Result<Sid, SddlError>> {
+    SID_ALIASES
+        .iter()
+        .find(|(name, _)| *name == alias)
+        .map(|(_, known)| known.to_sid().map_err(SddlError::from))
+}
+
+fn alias_of(sid: &Sid) -> Option<&'static str> {
+    let known = sid.well_known()?;
+    SID_ALIASES
+        .iter()
+        .find(|(_, alias)| *alias == known)
+        .map(|(name, _)| *name)
+}
+
+struct Parser<'a> {
+    sddl: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.sddl[self.pos..]
+    }
+
+    ///
+    /// Length of the text before the next `O:`, `G:`, `D:` or `S:`
+    ///
+    fn component_len(&self) -> usize {
+        let rest = self.rest().as_bytes();
+        (0..rest.len())
+            .find(|i| {
+                matches!(rest[*i], b'O' | b'G' | b'D' | b'S') && rest.get(i + 1) == Some(&b':')
+            })
+            .unwrap_or(rest.len())
+    }
+
+    fn descriptor(mut self) -> Result<SecurityDescriptorBuilder, SddlError> {
+        let mut builder = SecurityDescriptorBuilder::new();
+
+        while !self.rest().is_empty() {
+            let start = self.pos;
+            let rest = self.rest().as_bytes();
+            if rest.len() < 2 || rest[1] != b':' {
+                return Err(SddlError::Syntax(start));
+            }
+            self.pos += 2;
+
+            match rest[0] {
+                b'O' if builder.get_owner().is_none() => builder = builder.owner(self.sid()?),
+                b'G' if builder.get_group().is_none() => builder = builder.group(self.sid()?),
+                b'D' if builder.get_dacl().is_none() => builder = builder.dacl(self.acl()?),
+                b'S' if builder.get_sacl().is_none() => builder = builder.sacl(self.acl()?),
+                b'O' | b'G' | b'D' | b'S' => return Err(SddlError::Duplicate(start)),
+                _ => return Err(SddlError::Syntax(start)),
+            }
+        }
+
+        Ok(builder)
+    }
+
+    fn sid(&mut self) -> Result<Sid, SddlError> {
+        let len = self.component_len();
+        let sid = parse_sid(&self.rest()[..len], self.pos)?;
+        self.pos += len;
+
+        Ok(sid)
+    }
+
+    fn acl(&mut self) -> Result<Acl, SddlError> {
+        let flags_len = self.rest().find('(').unwrap_or(self.component_len());
+        let flags_len = core::cmp::min(flags_len, self.component_len());
+        let control = parse_flags(&self.rest()[..flags_len], ACL_FLAGS, self.pos)?
+            .iter()
+            .fold(AclControl::empty(), |acc, flag| acc | *flag);
+        self.pos += flags_len;
+
+        let mut acl = Acl::with_control(control);
+        while self.rest().starts_with('(') {
+            let start = self.pos;
+            let end = self.rest().find(')').ok_or(SddlError::Syntax(start))?;
+            let ace = parse_ace(&self.rest()[1..end], start + 1)?;
+            acl.try_push(ace)?;
+            self.pos += end + 1;
+        }
+
+        Ok(acl)
+    }
+}
+
+///
+/// Splits `text` into the codes of `table`, every code must be known
+///
+fn parse_flags<T: Copy>(
+    text: &str,
+    table: &[(&str, T)],
+    offset: usize,
+) -> Result<FlagIter<T>, SddlError> {
+    let mut values = FlagIter::default();
+    let mut pos = 0;
+
+    while pos < text.len() {
+        let (name, value) = table
+            .iter()
+            //Longest match first, `A` and `AI` share a prefix
+            .filter(|(name, _)| text[pos..].starts_with(name))
+            .max_by_key(|(name, _)| name.len())
+            .ok_or(SddlError::Syntax(offset + pos))?;
+
+        values.push(*value).ok_or(SddlError::Syntax(offset + pos))?;
+        pos += name.len();
+    }
+
+    Ok(values)
+}
+
+///
+/// Fixed capacity list so the parser does not allocate for flags
+///
+struct FlagIter<T> {
+    values: [Option<T>; 32],
+    len: usize,
+}
+
+impl<T: Copy> Default for FlagIter<T> {
+    fn default() -> Self {
+        Self {
+            values: [None; 32],
+            len: 0,
+        }
+    }
+}
+
+impl<T: Copy> FlagIter<T> {
+    fn push(&mut self, value: T) -> Option<()> {
+        *self.values.get_mut(self.len)? = Some(value);
+        self.len += 1;
+        Some(())
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        self.values[..self.len].iter().flatten()
+    }
+}
+
+fn parse_sid(text: &str, offset: usize) -> Result<Sid, SddlError> {
+    if text.starts_with("S-") || text.starts_with("s-") {
+        return Sid::parse(text).map_err(|_| SddlError::Syntax(offset));
+    }
+
+    sid_from_alias(text).unwrap_or(Err(SddlError::UnknownAlias(offset)))
+}
+
+fn parse_rights(text: &str, ace_type: AceType, offset: usize) -> Result<u32, SddlError> {
+    let number = if let Some(hex) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        Some(u32::from_str_radix(hex, 16))
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        Some(text.parse::<u32>())
+    } else {
+        None
+    };
+
+    if let Some(number) = number {
+        return number.map_err(|_| SddlError::Syntax(offset));
+    }
+
+    let table = match ace_type {
+        AceType::MandatoryLabel => LABEL_RIGHTS,
+        _ => RIGHTS,
+    };
+
+    let mut mask = 0;
+    let mut pos = 0;
+    while pos < text.len() {
+        let code = text
+            .get(pos..pos + 2)
+            .ok_or(SddlError::Syntax(offset + pos))?;
+        let (_, value) = table
+            .iter()
+            .chain(COMPOSITE_RIGHTS.iter())
+            .find(|(name, _)| *name == code)
+            .ok_or(SddlError::Syntax(offset + pos))?;
+
+        mask |= value;
+        pos += 2;
+    }
+
+    Ok(mask)
+}
+
+///
+/// `type;flags;rights;object_guid;inherit_object_guid;sid`
+///
+fn parse_ace(text: &str, offset: usize) -> Result<Ace, SddlError> {
+    let mut fields = [""; 6];
+    let mut count = 0;
+    for field in text.split(';') {
+        if count == fields.len() {
+            //Resource attribute ACEs have a seventh field
+            return Err(SddlError::UnsupportedAce(offset));
+        }
+
+        fields[count] = field;
+        count += 1;
+    }
+    if count != fields.len() {
+        return Err(SddlError::Syntax(offset));
+    }
+
+    let [ace_type, flags, rights, object, inherit_object, sid] = fields;
+    let field_offset = |field: &str| offset + (field.as_ptr() as usize - text.as_ptr() as usize);
+
+    let ace_type = ACE_TYPES
+        .iter()
+        .find(|(name, _)| *name == ace_type)
+        .map(|(_, ace_type)| *ace_type)
+        .ok_or(SddlError::UnsupportedAce(offset))?;
+
+    if !object.is_empty() || !inherit_object.is_empty() {
+        return Err(SddlError::UnsupportedAce(field_offset(object)));
+    }
+
+    let flags = parse_flags(flags, ACE_FLAGS, field_offset(flags))?
+        .iter()
+        .fold(AceFlags::empty(), |acc, flag| acc | *flag);
+    let mask = parse_rights(rights, ace_type, field_offset(rights))?;
+    let sid = parse_sid(sid, field_offset(sid))?;
+
+    Ok(Ace::new(ace_type, flags, mask, sid))
+}
+
+fn write_sid(f: &mut core::fmt::Formatter<'_>, sid: &Sid) -> core::fmt::Result {
+    match alias_of(sid) {
+        Some(alias) => f.write_str(alias),
+        None => write!(f, "{}", sid),
+    }
+}
+
+fn write_rights(f: &mut core::fmt::Formatter<'_>, ace: &Ace) -> core::fmt::Result {
+    let table = match ace.ace_type {
+        AceType::MandatoryLabel => LABEL_RIGHTS,
+        _ => {
+            if let Some((name, _)) = COMPOSITE_RIGHTS.iter().find(|(_, mask)| *mask == ace.mask) {
+                return f.write_str(name);
+            }
+
+            RIGHTS
+        }
+    };
+
+    let known = table.iter().fold(0, |acc, (_, mask)| acc | mask);
+    if ace.mask == 0 || ace.mask & !known != 0 {
+        return write!(f, "0x{:x}", ace.mask);
+    }
+
+    for (name, mask) in table {
+        if ace.mask & mask != 0 {
+            f.write_str(name)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_acl(f: &mut core::fmt::Formatter<'_>, acl: &Acl) -> core::fmt::Result {
+    for (name, flag) in ACL_FLAGS {
+        if acl.control().contains(*flag) {
+            f.write_str(name)?;
+        }
+    }
+
+    for ace in acl.aces() {
+        let (ace_type, _) = ACE_TYPES
+            .iter()
+            .find(|(_, ace_type)| *ace_type == ace.ace_type)
+            .unwrap();
+
+        write!(f, "({};", ace_type)?;
+        for (name, flag) in ACE_FLAGS {
+            if ace.flags.contains(*flag) {
+                f.write_str(name)?;
+            }
+        }
+        f.write_char(';')?;
+        write_rights(f, ace)?;
+        f.write_str(";;;")?;
+        write_sid(f, &ace.sid)?;
+        f.write_char(')')?;
+    }
+
+    Ok(())
+}
+
+///
+/// Formats the descriptor as SDDL, well-known SIDs use their alias
+///
+impl Display for SecurityDescriptorBuilder {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(owner) = self.get_owner() {
+            f.write_str("O:")?;
+            write_sid(f, owner)?;
+        }
+        if let Some(group) = self.get_group() {
+            f.write_str("G:")?;
+            write_sid(f, group)?;
+        }
+        if let Some(dacl) = self.get_dacl() {
+            f.write_str("D:")?;
+            write_acl(f, dacl)?;
+        }
+        if let Some(sacl) = self.get_sacl() {
+            f.write_str("S:")?;
+            write_acl(f, sacl)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::security::{
+        AceFlags, AceType, AclControl, SddlError, SecurityDescriptor, SecurityDescriptorBuilder,
+        Sid,
+    };
+
+    use super::SID_ALIASES;
+
+    extern crate std;
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let sddl = "O:BAG:SYD:P(A;;GA;;;SY)(A;;GA;;;BA)(A;OICI;0x1200a9;;;AU)(D;;FA;;;S-1-5-21-1-2-3-500)S:(ML;;NW;;;LW)";
+        let builder = SecurityDescriptorBuilder::from_sddl(sddl)?;
+
+        assert_eq!(builder.get_owner(), Some(&Sid::try_new(5, &[32, 544])?));
+        let dacl = builder.get_dacl().unwrap();
+        assert_eq!(dacl.control(), AclControl::PROTECTED);
+        assert_eq!(dacl.aces().len(), 4);
+        assert_eq!(
+            dacl.aces()[2].flags,
+            AceFlags::OBJECT_INHERIT | AceFlags::CONTAINER_INHERIT
+        );
+        assert_eq!(dacl.aces()[2].mask, 0x1200a9);
+        assert_eq!(dacl.aces()[3].ace_type, AceType::AccessDenied);
+        assert_eq!(dacl.aces()[3].sid.rid(), Some(500));
+        assert_eq!(builder.get_sacl().unwrap().aces()[0].mask, 1);
+
+        //Formatting gives back the input
+        assert_eq!(std::format!("{}", builder), sddl);
+
+        //Same bytes as ConvertStringSecurityDescriptorToSecurityDescriptor
+        let descriptor = SecurityDescriptor::from_sddl("O:SYD:(A;;GA;;;WD)")?;
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            1, 0, 0x04, 0x80, 48, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 20, 0, 0, 0,
+            2, 0, 28, 0, 1, 0, 0, 0,
+            0, 0, 20, 0, 0, 0, 0, 0x10,
+            1, 1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0,
+            1, 1, 0, 0, 0, 0, 0, 5, 18, 0, 0, 0,
+        ];
+        assert_eq!(descriptor.as_bytes(), expected);
+
+        let built = SecurityDescriptorBuilder::new()
+            .owner(Sid::parse("S-1-5-18")?)
+            .allow(Sid::parse("S-1-1-0")?, 0x1000_0000)?
+            .build()?;
+        assert_eq!(built.as_bytes(), expected);
+
+        assert_eq!(
+            SecurityDescriptorBuilder::from_sddl("O:DA").unwrap_err(),
+            SddlError::UnknownAlias(2)
+        );
+        assert_eq!(
+            SecurityDescriptorBuilder::from_sddl("D:(A;;GA;;;SY").unwrap_err(),
+            SddlError::Syntax(2)
+        );
+        assert_eq!(
+            SecurityDescriptorBuilder::from_sddl("D:(OA;;GA;;;SY)").unwrap_err(),
+            SddlError::UnsupportedAce(3)
+        );
+        assert_eq!(
+            SecurityDescriptorBuilder::from_sddl("O:SYO:BA").unwrap_err(),
+            SddlError::Duplicate(4)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn aliases() -> anyhow::Result<()> {
+        for (alias, known) in SID_ALIASES {
+            let sddl = std::format!("O:{alias}");
+            let builder = SecurityDescriptorBuilder::from_sddl(&sddl)?;
+
+            assert_eq!(builder.get_owner(), Some(&known.to_sid()?), "{alias}");
+            assert_eq!(std::format!("{}", builder), sddl);
+        }
+
+        Ok(())
+    }
+}
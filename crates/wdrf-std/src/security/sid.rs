@@ -0,0 +1,368 @@
+use core::fmt::Display;
+
+use windows_sys::Win32::Foundation::{PSID, STATUS_INVALID_SID, STATUS_NO_MEMORY};
+
+use crate::{
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    vec::Vec,
+    NtResult, NtStatusError,
+};
+
+pub(super) fn security_allocator() -> GlobalKernelAllocator {
+    GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"secd"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    )
+}
+
+const SID_REVISION: u8 = 1;
+
+///
+/// SIDs that are the same on every machine
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WellKnownSid {
+    World,
+    CreatorOwner,
+    CreatorGroup,
+    OwnerRights,
+    Network,
+    Interactive,
+    Service,
+    Anonymous,
+    PrincipalSelf,
+    AuthenticatedUsers,
+    Restricted,
+    LocalSystem,
+    LocalService,
+    NetworkService,
+    Administrators,
+    Users,
+    Guests,
+    PowerUsers,
+    AccountOperators,
+    SystemOperators,
+    BackupOperators,
+    Replicator,
+    PreWindows2000CompatibleAccess,
+    RemoteDesktopUsers,
+    NetworkConfigurationOperators,
+    PerformanceLogUsers,
+    EventLogReaders,
+    AllAppPackages,
+    UntrustedIntegrity,
+    LowIntegrity,
+    MediumIntegrity,
+    MediumPlusIntegrity,
+    HighIntegrity,
+    SystemIntegrity,
+    ProtectedProcessIntegrity,
+}
+
+impl WellKnownSid {
+    pub const ALL: &'static [WellKnownSid] = &[
+        WellKnownSid::World,
+        WellKnownSid::CreatorOwner,
+        WellKnownSid::CreatorGroup,
+        WellKnownSid::OwnerRights,
+        WellKnownSid::Network,
+        WellKnownSid::Interactive,
+        WellKnownSid::Service,
+        WellKnownSid::Anonymous,
+        WellKnownSid::PrincipalSelf,
+        WellKnownSid::AuthenticatedUsers,
+        WellKnownSid::Restricted,
+        WellKnownSid::LocalSystem,
+        WellKnownSid::LocalService,
+        WellKnownSid::NetworkService,
+        WellKnownSid::Administrators,
+        WellKnownSid::Users,
+        WellKnownSid::Guests,
+        WellKnownSid::PowerUsers,
+        WellKnownSid::AccountOperators,
+        WellKnownSid::SystemOperators,
+        WellKnownSid::BackupOperators,
+        WellKnownSid::Replicator,
+        WellKnownSid::PreWindows2000CompatibleAccess,
+        WellKnownSid::RemoteDesktopUsers,
+        WellKnownSid::NetworkConfigurationOperators,
+        WellKnownSid::PerformanceLogUsers,
+        WellKnownSid::EventLogReaders,
+        WellKnownSid::AllAppPackages,
+        WellKnownSid::UntrustedIntegrity,
+        WellKnownSid::LowIntegrity,
+        WellKnownSid::MediumIntegrity,
+        WellKnownSid::MediumPlusIntegrity,
+        WellKnownSid::HighIntegrity,
+        WellKnownSid::SystemIntegrity,
+        WellKnownSid::ProtectedProcessIntegrity,
+    ];
+
+    ///
+    /// Identifier authority and sub authorities
+    ///
+    pub const fn parts(self) -> (u64, &'static [u32]) {
+        match self {
+            WellKnownSid::World => (1, &[0]),
+            WellKnownSid::CreatorOwner => (3, &[0]),
+            WellKnownSid::CreatorGroup => (3, &[1]),
+            WellKnownSid::OwnerRights => (3, &[4]),
+            WellKnownSid::Network => (5, &[2]),
+            WellKnownSid::Interactive => (5, &[4]),
+            WellKnownSid::Service => (5, &[6]),
+            WellKnownSid::Anonymous => (5, &[7]),
+            WellKnownSid::PrincipalSelf => (5, &[10]),
+            WellKnownSid::AuthenticatedUsers => (5, &[11]),
+            WellKnownSid::Restricted => (5, &[12]),
+            WellKnownSid::LocalSystem => (5, &[18]),
+            WellKnownSid::LocalService => (5, &[19]),
+            WellKnownSid::NetworkService => (5, &[20]),
+            WellKnownSid::Administrators => (5, &[32, 544]),
+            WellKnownSid::Users => (5, &[32, 545]),
+            WellKnownSid::Guests => (5, &[32, 546]),
+            WellKnownSid::PowerUsers => (5, &[32, 547]),
+            WellKnownSid::AccountOperators => (5, &[32, 548]),
+            WellKnownSid::SystemOperators => (5, &[32, 549]),
+            WellKnownSid::BackupOperators => (5, &[32, 551]),
+            WellKnownSid::Replicator => (5, &[32, 552]),
+            WellKnownSid::PreWindows2000CompatibleAccess => (5, &[32, 554]),
+            WellKnownSid::RemoteDesktopUsers => (5, &[32, 555]),
+            WellKnownSid::NetworkConfigurationOperators => (5, &[32, 556]),
+            WellKnownSid::PerformanceLogUsers => (5, &[32, 559]),
+            WellKnownSid::EventLogReaders => (5, &[32, 573]),
+            WellKnownSid::AllAppPackages => (15, &[2, 1]),
+            WellKnownSid::UntrustedIntegrity => (16, &[0]),
+            WellKnownSid::LowIntegrity => (16, &[0x1000]),
+            WellKnownSid::MediumIntegrity => (16, &[0x2000]),
+            WellKnownSid::MediumPlusIntegrity => (16, &[0x2100]),
+            WellKnownSid::HighIntegrity => (16, &[0x3000]),
+            WellKnownSid::SystemIntegrity => (16, &[0x4000]),
+            WellKnownSid::ProtectedProcessIntegrity => (16, &[0x5000]),
+        }
+    }
+
+    pub fn to_sid(self) -> NtResult<Sid> {
+        let (authority, sub_authorities) = self.parts();
+        Sid::try_new(authority, sub_authorities)
+    }
+}
+
+///
+/// Owned security identifier
+///
+#[derive(Clone, PartialEq, Eq)]
+pub struct Sid {
+    authority: u64,
+    sub_authorities: Vec<u32>,
+}
+
+impl Sid {
+    pub const MAX_SUB_AUTHORITIES: usize = 15;
+    const MAX_AUTHORITY: u64 = (1 << 48) - 1;
+
+    ///
+    /// `authority` is the 48 bit identifier authority, 5 for `NT AUTHORITY`
+    ///
+    pub fn try_new(authority: u64, sub_authorities: &[u32]) -> NtResult<Self> {
+        if authority > Self::MAX_AUTHORITY || sub_authorities.len() > Self::MAX_SUB_AUTHORITIES {
+            return Err(NtStatusError::Status(STATUS_INVALID_SID));
+        }
+
+        let mut vec = Vec::new_in(security_allocator());
+        vec.try_reserve_exact(sub_authorities.len())
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        vec.extend_from_slice(sub_authorities);
+
+        Ok(Self {
+            authority,
+            sub_authorities: vec,
+        })
+    }
+
+    ///
+    /// Parses the `S-1-5-32-544` form, the authority can be written in hex
+    ///
+    pub fn parse(s: &str) -> NtResult<Self> {
+        let invalid = || NtStatusError::Status(STATUS_INVALID_SID);
+
+        let mut parts = s.split('-');
+        if !matches!(parts.next(), Some("S" | "s")) || parts.next() != Some("1") {
+            return Err(invalid());
+        }
+
+        let authority = match parts.next() {
+            Some(hex) if hex.starts_with("0x") || hex.starts_with("0X") => {
+                u64::from_str_radix(&hex[2..], 16).map_err(|_| invalid())?
+            }
+            Some(decimal) => decimal.parse::<u64>().map_err(|_| invalid())?,
+            None => return Err(invalid()),
+        };
+
+        let mut sub_authorities = [0u32; Self::MAX_SUB_AUTHORITIES];
+        let mut count = 0;
+        for part in parts {
+            if count == Self::MAX_SUB_AUTHORITIES {
+                return Err(invalid());
+            }
+
+            sub_authorities[count] = part.parse::<u32>().map_err(|_| invalid())?;
+            count += 1;
+        }
+
+        Self::try_new(authority, &sub_authorities[..count])
+    }
+
+    ///
+    /// Copies a binary `SID`
+    ///
+    /// # Safety
+    ///
+    /// `sid` must point to a valid SID
+    ///
+    pub unsafe fn try_from_raw(sid: PSID) -> NtResult<Self> {
+        let header = core::slice::from_raw_parts(sid.cast::<u8>(), 8);
+        if header[0] != SID_REVISION || header[1] as usize > Self::MAX_SUB_AUTHORITIES {
+            return Err(NtStatusError::Status(STATUS_INVALID_SID));
+        }
+
+        let mut authority = [0u8; 8];
+        authority[2..].copy_from_slice(&header[2..8]);
+
+        //Sub authorities are not aligned when the SID sits inside a buffer
+        let sub_authorities = sid.cast::<u8>().add(8).cast::<u32>();
+        let mut subs = [0u32; Self::MAX_SUB_AUTHORITIES];
+        for (i, sub) in subs.iter_mut().take(header[1] as usize).enumerate() {
+            *sub = sub_authorities.add(i).read_unaligned();
+        }
+
+        Self::try_new(u64::from_be_bytes(authority), &subs[..header[1] as usize])
+    }
+
+    pub fn try_clone(&self) -> NtResult<Self> {
+        Self::try_new(self.authority, &self.sub_authorities)
+    }
+
+    #[inline]
+    pub fn identifier_authority(&self) -> u64 {
+        self.authority
+    }
+
+    #[inline]
+    pub fn sub_authorities(&self) -> &[u32] {
+        &self.sub_authorities
+    }
+
+    ///
+    /// The last sub authority, the relative id of accounts and groups
+    ///
+    #[inline]
+    pub fn rid(&self) -> Option<u32> {
+        self.sub_authorities.last().copied()
+    }
+
+    pub fn well_known(&self) -> Option<WellKnownSid> {
+        WellKnownSid::ALL
+            .iter()
+            .copied()
+            .find(|known| self.is_well_known(*known))
+    }
+
+    pub fn is_well_known(&self, known: WellKnownSid) -> bool {
+        let (authority, sub_authorities) = known.parts();
+        self.authority == authority && *self.sub_authorities == *sub_authorities
+    }
+
+    ///
+    /// Size of the binary `SID`
+    ///
+    #[inline]
+    pub fn binary_len(&self) -> usize {
+        8 + 4 * self.sub_authorities.len()
+    }
+
+    ///
+    /// Appends the binary `SID`, `out` must have room for `binary_len` bytes
+    ///
+    pub(super) fn write_to(&self, out: &mut Vec<u8>) {
+        out.push(SID_REVISION);
+        out.push(self.sub_authorities.len() as u8);
+        out.extend_from_slice(&self.authority.to_be_bytes()[2..]);
+        for sub_authority in self.sub_authorities.iter() {
+            out.extend_from_slice(&sub_authority.to_le_bytes());
+        }
+    }
+}
+
+impl Display for Sid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        //Same rule as RtlConvertSidToUnicodeString
+        if self.authority >= 1 << 32 {
+            write!(f, "S-{}-0x{:012X}", SID_REVISION, self.authority)?;
+        } else {
+            write!(f, "S-{}-{}", SID_REVISION, self.authority)?;
+        }
+
+        for sub_authority in self.sub_authorities.iter() {
+            write!(f, "-{}", sub_authority)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for Sid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        security::{Sid, WellKnownSid},
+        vec::Vec,
+    };
+
+    extern crate std;
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let sid = Sid::parse("S-1-5-21-3623811015-3361044348-30300820-1013")?;
+        assert_eq!(sid.identifier_authority(), 5);
+        assert_eq!(sid.rid(), Some(1013));
+        assert_eq!(
+            std::format!("{}", sid),
+            "S-1-5-21-3623811015-3361044348-30300820-1013"
+        );
+        assert_eq!(sid.well_known(), None);
+
+        let large = Sid::parse("S-1-0x100000000-7")?;
+        assert_eq!(std::format!("{}", large), "S-1-0x000100000000-7");
+
+        assert!(Sid::parse("S-1-5-32-544")?.is_well_known(WellKnownSid::Administrators));
+        assert_eq!(
+            WellKnownSid::HighIntegrity.to_sid()?.well_known(),
+            Some(WellKnownSid::HighIntegrity)
+        );
+
+        for invalid in [
+            "S-2-5",
+            "S-1",
+            "S-1-5-x",
+            "S-1-1-1-2-3-4-5-6-7-8-9-10-11-12-13-14-15-16",
+        ] {
+            assert!(Sid::parse(invalid).is_err(), "{invalid}");
+        }
+
+        //Unaligned on purpose
+        let mut raw = Vec::new_in(super::security_allocator());
+        raw.push(0u8);
+        sid.write_to(&mut raw);
+        assert_eq!(raw.len(), sid.binary_len() + 1);
+        let copy = unsafe { Sid::try_from_raw(raw.as_mut_ptr().add(1).cast()) }?;
+        assert_eq!(copy, sid);
+
+        Ok(())
+    }
+}
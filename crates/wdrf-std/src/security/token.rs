@@ -0,0 +1,392 @@
+use core::ffi::c_void;
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+use windows_sys::{
+    Wdk::{
+        Storage::FileSystem::{
+            PsReferenceImpersonationToken, PsReferencePrimaryToken, SeQueryInformationToken,
+            SeTokenIsAdmin,
+        },
+        System::SystemServices::ExFreePool,
+    },
+    Win32::{
+        Foundation::{STATUS_NO_MEMORY, STATUS_UNSUCCESSFUL},
+        Security::{
+            SecurityAnonymous, SecurityDelegation, SecurityIdentification, SecurityImpersonation,
+            TokenElevation, TokenElevationType, TokenElevationTypeDefault, TokenElevationTypeFull,
+            TokenElevationTypeLimited, TokenGroups, TokenImpersonationLevel, TokenIntegrityLevel,
+            TokenPrivileges, TokenSessionId, TokenType, TokenUser, SECURITY_IMPERSONATION_LEVEL,
+            SE_PRIVILEGE_ENABLED, SE_PRIVILEGE_ENABLED_BY_DEFAULT, TOKEN_ELEVATION, TOKEN_GROUPS,
+            TOKEN_INFORMATION_CLASS, TOKEN_MANDATORY_LABEL, TOKEN_PRIVILEGES, TOKEN_USER,
+        },
+    },
+};
+
+use crate::{
+    nt_success,
+    object::{ArcKernelObj, NonNullKrnlResource},
+    structs::{PKPROCESS, PKTHREAD, PPROCESS_ACCESS_TOKEN},
+    vec::Vec,
+    NtResult, NtStatusError,
+};
+
+use super::sid::{security_allocator, Sid, WellKnownSid};
+
+const TOKEN_PRIMARY: u32 = 1;
+
+const SE_GROUP_MANDATORY: u32 = 0x0000_0001;
+const SE_GROUP_ENABLED: u32 = 0x0000_0004;
+const SE_GROUP_OWNER: u32 = 0x0000_0008;
+const SE_GROUP_USE_FOR_DENY_ONLY: u32 = 0x0000_0010;
+const SE_GROUP_INTEGRITY: u32 = 0x0000_0020;
+const SE_GROUP_LOGON_ID: u32 = 0xC000_0000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ElevationType {
+    ///
+    /// UAC is off or the user is not an administrator
+    ///
+    Default,
+    Full,
+    Limited,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ImpersonationLevel {
+    Anonymous,
+    Identification,
+    Impersonation,
+    Delegation,
+}
+
+impl ImpersonationLevel {
+    fn from_raw(level: SECURITY_IMPERSONATION_LEVEL) -> Option<Self> {
+        match level {
+            SecurityAnonymous => Some(ImpersonationLevel::Anonymous),
+            SecurityIdentification => Some(ImpersonationLevel::Identification),
+            SecurityImpersonation => Some(ImpersonationLevel::Impersonation),
+            SecurityDelegation => Some(ImpersonationLevel::Delegation),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Mandatory integrity level, ordered from least to most trusted
+///
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum IntegrityLevel {
+    Untrusted,
+    Low,
+    Medium,
+    MediumPlus,
+    High,
+    System,
+    ProtectedProcess,
+}
+
+impl IntegrityLevel {
+    ///
+    /// Levels between the named ones round down
+    ///
+    pub fn from_rid(rid: u32) -> Self {
+        match rid {
+            0..=0x0FFF => IntegrityLevel::Untrusted,
+            0x1000..=0x1FFF => IntegrityLevel::Low,
+            0x2000..=0x20FF => IntegrityLevel::Medium,
+            0x2100..=0x2FFF => IntegrityLevel::MediumPlus,
+            0x3000..=0x3FFF => IntegrityLevel::High,
+            0x4000..=0x4FFF => IntegrityLevel::System,
+            _ => IntegrityLevel::ProtectedProcess,
+        }
+    }
+
+    pub fn well_known(self) -> WellKnownSid {
+        match self {
+            IntegrityLevel::Untrusted => WellKnownSid::UntrustedIntegrity,
+            IntegrityLevel::Low => WellKnownSid::LowIntegrity,
+            IntegrityLevel::Medium => WellKnownSid::MediumIntegrity,
+            IntegrityLevel::MediumPlus => WellKnownSid::MediumPlusIntegrity,
+            IntegrityLevel::High => WellKnownSid::HighIntegrity,
+            IntegrityLevel::System => WellKnownSid::SystemIntegrity,
+            IntegrityLevel::ProtectedProcess => WellKnownSid::ProtectedProcessIntegrity,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Group {
+    pub sid: Sid,
+    pub attributes: u32,
+}
+
+impl Group {
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.attributes & SE_GROUP_ENABLED != 0
+    }
+
+    #[inline]
+    pub fn is_mandatory(&self) -> bool {
+        self.attributes & SE_GROUP_MANDATORY != 0
+    }
+
+    #[inline]
+    pub fn is_owner(&self) -> bool {
+        self.attributes & SE_GROUP_OWNER != 0
+    }
+
+    ///
+    /// Only matched by deny ACEs, as in the filtered token of a UAC admin
+    ///
+    #[inline]
+    pub fn is_deny_only(&self) -> bool {
+        self.attributes & SE_GROUP_USE_FOR_DENY_ONLY != 0
+    }
+
+    #[inline]
+    pub fn is_integrity(&self) -> bool {
+        self.attributes & SE_GROUP_INTEGRITY != 0
+    }
+
+    #[inline]
+    pub fn is_logon_id(&self) -> bool {
+        self.attributes & SE_GROUP_LOGON_ID == SE_GROUP_LOGON_ID
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Privilege {
+    ///
+    /// The privilege LUID, `SeDebugPrivilege` is 20
+    ///
+    pub luid: u64,
+    pub attributes: u32,
+}
+
+impl Privilege {
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.attributes & SE_PRIVILEGE_ENABLED != 0
+    }
+
+    #[inline]
+    pub fn is_enabled_by_default(&self) -> bool {
+        self.attributes & SE_PRIVILEGE_ENABLED_BY_DEFAULT != 0
+    }
+}
+
+///
+/// Buffer allocated by `SeQueryInformationToken`
+///
+struct TokenInformation<T> {
+    info: *mut T,
+}
+
+impl<T> Drop for TokenInformation<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ExFreePool(self.info.cast());
+        }
+    }
+}
+
+///
+/// A referenced access token
+///
+/// All the queries copy the information out so nothing borrows the token.
+///
+#[derive(Clone)]
+pub struct Token {
+    token: ArcKernelObj<PPROCESS_ACCESS_TOKEN>,
+}
+
+impl Token {
+    pub fn new(token: ArcKernelObj<PPROCESS_ACCESS_TOKEN>) -> Self {
+        Self { token }
+    }
+
+    ///
+    /// The primary token of `process`
+    ///
+    pub fn primary(process: &ArcKernelObj<PKPROCESS>) -> Option<Self> {
+        unsafe {
+            let token: PPROCESS_ACCESS_TOKEN =
+                PsReferencePrimaryToken(process.as_raw_obj() as _).cast();
+
+            NonNullKrnlResource::new(token).map(|token| Self::new(ArcKernelObj::new(token, false)))
+        }
+    }
+
+    ///
+    /// The token `thread` is impersonating, `None` when it is not
+    ///
+    pub fn impersonation(thread: &ArcKernelObj<PKTHREAD>) -> Option<(Self, ImpersonationLevel)> {
+        unsafe {
+            let mut copy_on_open = 0;
+            let mut effective_only = 0;
+            let mut level: SECURITY_IMPERSONATION_LEVEL = SecurityAnonymous;
+
+            let token: PPROCESS_ACCESS_TOKEN = PsReferenceImpersonationToken(
+                thread.as_raw_obj() as _,
+                &mut copy_on_open,
+                &mut effective_only,
+                &mut level,
+            )
+            .cast();
+
+            let token = Self::new(ArcKernelObj::new(NonNullKrnlResource::new(token)?, false));
+            Some((token, ImpersonationLevel::from_raw(level)?))
+        }
+    }
+
+    #[inline]
+    pub fn as_kernel_obj(&self) -> &ArcKernelObj<PPROCESS_ACCESS_TOKEN> {
+        &self.token
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn user(&self) -> NtResult<Sid> {
+        let info = self.query::<TOKEN_USER>(TokenUser)?;
+        unsafe { Sid::try_from_raw((*info.info).User.Sid) }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn groups(&self) -> NtResult<Vec<Group>> {
+        let info = self.query::<TOKEN_GROUPS>(TokenGroups)?;
+
+        unsafe {
+            let count = (*info.info).GroupCount as usize;
+            let groups = core::slice::from_raw_parts((*info.info).Groups.as_ptr(), count);
+
+            let mut result = Vec::new_in(security_allocator());
+            result
+                .try_reserve_exact(count)
+                .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+            for group in groups {
+                result.push(Group {
+                    sid: Sid::try_from_raw(group.Sid)?,
+                    attributes: group.Attributes,
+                });
+            }
+
+            Ok(result)
+        }
+    }
+
+    ///
+    /// True if the token has `sid` as an enabled group
+    ///
+    pub fn is_member_of(&self, sid: &Sid) -> NtResult<bool> {
+        Ok(self
+            .groups()?
+            .iter()
+            .any(|group| group.is_enabled() && group.sid == *sid))
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn integrity_level(&self) -> NtResult<IntegrityLevel> {
+        let info = self.query::<TOKEN_MANDATORY_LABEL>(TokenIntegrityLevel)?;
+        let label = unsafe { Sid::try_from_raw((*info.info).Label.Sid)? };
+
+        label
+            .rid()
+            .map(IntegrityLevel::from_rid)
+            .ok_or(NtStatusError::Status(STATUS_UNSUCCESSFUL))
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn elevation_type(&self) -> NtResult<ElevationType> {
+        let info = self.query::<i32>(TokenElevationType)?;
+
+        match unsafe { *info.info } {
+            TokenElevationTypeDefault => Ok(ElevationType::Default),
+            TokenElevationTypeFull => Ok(ElevationType::Full),
+            TokenElevationTypeLimited => Ok(ElevationType::Limited),
+            _ => Err(NtStatusError::Status(STATUS_UNSUCCESSFUL)),
+        }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn is_elevated(&self) -> NtResult<bool> {
+        let info = self.query::<TOKEN_ELEVATION>(TokenElevation)?;
+        Ok(unsafe { (*info.info).TokenIsElevated } != 0)
+    }
+
+    ///
+    /// Whether the token has the administrators group enabled
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn is_admin(&self) -> bool {
+        unsafe { SeTokenIsAdmin(self.token.as_raw_obj().cast()) != 0 }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn session_id(&self) -> NtResult<u32> {
+        let info = self.query::<u32>(TokenSessionId)?;
+        Ok(unsafe { *info.info })
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn privileges(&self) -> NtResult<Vec<Privilege>> {
+        let info = self.query::<TOKEN_PRIVILEGES>(TokenPrivileges)?;
+
+        unsafe {
+            let count = (*info.info).PrivilegeCount as usize;
+            let privileges = core::slice::from_raw_parts((*info.info).Privileges.as_ptr(), count);
+
+            let mut result = Vec::new_in(security_allocator());
+            result
+                .try_reserve_exact(count)
+                .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+            result.extend(privileges.iter().map(|privilege| Privilege {
+                luid: ((privilege.Luid.HighPart as u32 as u64) << 32)
+                    | privilege.Luid.LowPart as u64,
+                attributes: privilege.Attributes,
+            }));
+
+            Ok(result)
+        }
+    }
+
+    pub fn has_privilege_enabled(&self, luid: u64) -> NtResult<bool> {
+        Ok(self
+            .privileges()?
+            .iter()
+            .any(|privilege| privilege.luid == luid && privilege.is_enabled()))
+    }
+
+    ///
+    /// `None` for a primary token
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn impersonation_level(&self) -> NtResult<Option<ImpersonationLevel>> {
+        let token_type = self.query::<u32>(TokenType)?;
+        if unsafe { *token_type.info } == TOKEN_PRIMARY {
+            return Ok(None);
+        }
+
+        let info = self.query::<SECURITY_IMPERSONATION_LEVEL>(TokenImpersonationLevel)?;
+        ImpersonationLevel::from_raw(unsafe { *info.info })
+            .map(Some)
+            .ok_or(NtStatusError::Status(STATUS_UNSUCCESSFUL))
+    }
+
+    fn query<T>(&self, class: TOKEN_INFORMATION_CLASS) -> NtResult<TokenInformation<T>> {
+        unsafe {
+            let mut info: *mut c_void = core::ptr::null_mut();
+            let status = SeQueryInformationToken(self.token.as_raw_obj().cast(), class, &mut info);
+
+            if !nt_success(status) {
+                return Err(NtStatusError::Status(status));
+            }
+            if info.is_null() {
+                return Err(NtStatusError::Status(STATUS_UNSUCCESSFUL));
+            }
+
+            Ok(TokenInformation { info: info.cast() })
+        }
+    }
+}
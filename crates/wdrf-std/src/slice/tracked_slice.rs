@@ -1,10 +1,4 @@
-use crate::io::Write;
-
-pub enum SeekFrom {
-    Start(usize),
-    End(isize),
-    Current(isize),
-}
+use crate::io::{self, seek_position, Error, Seek, SeekFrom, Write};
 
 pub struct TrackedSlice<'a> {
     buffer: &'a mut [u8],
@@ -54,43 +48,10 @@ impl<'a> TrackedSlice<'a> {
     pub fn as_slice_mut(&mut self) -> &mut [u8] {
         &mut self.buffer
     }
-
-    #[inline]
-    pub fn seek(&mut self, seek: SeekFrom) -> bool {
-        match seek {
-            SeekFrom::Start(offset) => {
-                if offset > self.buffer.len() {
-                    false
-                } else {
-                    self.bytes_written = offset;
-                    true
-                }
-            }
-            SeekFrom::End(offset) => {
-                let new_offset = ((self.buffer.len() as isize) + offset) as usize;
-                if new_offset > self.buffer.len() {
-                    false
-                } else {
-                    self.bytes_written = new_offset;
-                    true
-                }
-            }
-            SeekFrom::Current(offset) => {
-                let new_offset = ((self.bytes_written as isize) + offset) as usize;
-
-                if new_offset > self.buffer.len() {
-                    false
-                } else {
-                    self.bytes_written = new_offset;
-                    true
-                }
-            }
-        }
-    }
 }
 
 impl<'a> Write for TrackedSlice<'a> {
-    fn write(&mut self, buf: &[u8]) -> anyhow::Result<usize> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let write_size = core::cmp::min(buf.len(), self.remaining());
 
         unsafe {
@@ -101,7 +62,24 @@ impl<'a> Write for TrackedSlice<'a> {
         Ok(write_size)
     }
 
-    fn flush(&mut self) -> anyhow::Result<()> {
+    fn flush(&mut self) -> io::Result<()> {
         Ok(())
     }
 }
+
+impl<'a> Seek for TrackedSlice<'a> {
+    ///
+    /// Moves the write position, it can not go past the end of the buffer
+    ///
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = self.buffer.len() as u64;
+        let new_pos = seek_position(pos, self.bytes_written as u64, len)?;
+
+        if new_pos > len {
+            return Err(Error::InvalidInput);
+        }
+
+        self.bytes_written = new_pos as usize;
+        Ok(new_pos)
+    }
+}
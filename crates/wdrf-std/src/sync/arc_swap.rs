@@ -0,0 +1,234 @@
+use core::{
+    alloc::Allocator,
+    cell::UnsafeCell,
+    ops::Deref,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+
+use crate::{kmalloc::GlobalKernelAllocator, traits::DispatchSafe};
+
+use super::arc::Arc;
+
+///
+/// An `Arc<T>` that can be replaced while other threads read it
+///
+/// Readers never take a lock and never wait for a writer, `load` is usable
+/// up to DISPATCH_LEVEL. Writers are serialized between themselves and spin
+/// until the readers that are in the middle of a `load` finish cloning the
+/// previous version, they never wait for the snapshots to be released.
+///
+/// `store` and `swap` must be called at PASSIVE_LEVEL. A reader running below
+/// DISPATCH_LEVEL can be preempted between registering and cloning, a writer
+/// spinning at DISPATCH_LEVEL on the same processor would never let it finish.
+/// At PASSIVE_LEVEL the writer is preemptible and the spin ends once the
+/// reader is scheduled again.
+///
+/// The previous version is freed when its last snapshot is dropped,
+/// if snapshots are dropped at DISPATCH_LEVEL `T` must be safe to drop there.
+///
+pub struct ArcSwap<T, A: Allocator = GlobalKernelAllocator> {
+    epoch: AtomicUsize,
+    readers: [AtomicUsize; 2],
+    slots: [UnsafeCell<Option<Arc<T, A>>>; 2],
+    writer: AtomicBool,
+}
+
+unsafe impl<T: Send + Sync, A: Allocator + Send + Sync> Send for ArcSwap<T, A> {}
+unsafe impl<T: Send + Sync, A: Allocator + Send + Sync> Sync for ArcSwap<T, A> {}
+unsafe impl<T: DispatchSafe, A: Allocator> DispatchSafe for ArcSwap<T, A> {}
+
+///
+/// A snapshot of the value that was current when `load` was called
+///
+pub struct ArcSwapGuard<T, A: Allocator = GlobalKernelAllocator> {
+    arc: Arc<T, A>,
+}
+
+impl<T, A: Allocator> ArcSwapGuard<T, A> {
+    #[inline]
+    pub fn into_arc(self) -> Arc<T, A> {
+        self.arc
+    }
+}
+
+impl<T, A: Allocator> Deref for ArcSwapGuard<T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.arc
+    }
+}
+
+impl<T, A: Allocator + Clone> ArcSwap<T, A> {
+    pub fn new(value: Arc<T, A>) -> Self {
+        Self {
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            slots: [UnsafeCell::new(Some(value)), UnsafeCell::new(None)],
+            writer: AtomicBool::new(false),
+        }
+    }
+
+    #[inline]
+    pub fn load(&self) -> ArcSwapGuard<T, A> {
+        ArcSwapGuard {
+            arc: self.load_full(),
+        }
+    }
+
+    pub fn load_full(&self) -> Arc<T, A> {
+        loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            self.readers[epoch].fetch_add(1, Ordering::SeqCst);
+
+            //A writer flipped the epoch before we registered, the slot
+            //we registered for may be rewritten
+            if self.epoch.load(Ordering::SeqCst) != epoch {
+                self.readers[epoch].fetch_sub(1, Ordering::SeqCst);
+                core::hint::spin_loop();
+                continue;
+            }
+
+            let arc = unsafe { (*self.slots[epoch].get()).clone() };
+            self.readers[epoch].fetch_sub(1, Ordering::SeqCst);
+
+            match arc {
+                Some(arc) => return arc,
+                None => unreachable!("ArcSwap current slot is empty"),
+            }
+        }
+    }
+
+    #[inline]
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn store(&self, value: Arc<T, A>) {
+        drop(self.swap(value));
+    }
+
+    ///
+    /// Publishes `value` and returns the previous version
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn swap(&self, value: Arc<T, A>) -> Arc<T, A> {
+        self.lock_writer();
+
+        let current = self.epoch.load(Ordering::SeqCst);
+        let next = current ^ 1;
+
+        //Readers that registered with a stale epoch back off without
+        //touching the slot, wait for them before rewriting it
+        self.wait_readers(next);
+        unsafe {
+            *self.slots[next].get() = Some(value);
+        }
+        self.epoch.store(next, Ordering::SeqCst);
+
+        self.wait_readers(current);
+        let previous = unsafe { (*self.slots[current].get()).take() };
+
+        self.unlock_writer();
+
+        match previous {
+            Some(previous) => previous,
+            None => unreachable!("ArcSwap previous slot is empty"),
+        }
+    }
+
+    pub fn into_inner(mut self) -> Arc<T, A> {
+        let epoch = *self.epoch.get_mut();
+        match self.slots[epoch].get_mut().take() {
+            Some(arc) => arc,
+            None => unreachable!("ArcSwap current slot is empty"),
+        }
+    }
+
+    fn wait_readers(&self, epoch: usize) {
+        while self.readers[epoch].load(Ordering::SeqCst) != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn lock_writer(&self) {
+        while self
+            .writer
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn unlock_writer(&self) {
+        self.writer.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use super::ArcSwap;
+    use crate::sync::arc::{Arc, ArcExt};
+
+    extern crate std;
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let swap = ArcSwap::new(Arc::try_create(10)?);
+
+        let snapshot = swap.load();
+        assert_eq!(*snapshot, 10);
+
+        let previous = swap.swap(Arc::try_create(20)?);
+        assert_eq!(*previous, 10);
+        assert_eq!(*snapshot, 10);
+        assert_eq!(*swap.load(), 20);
+
+        swap.store(Arc::try_create(30)?);
+        assert_eq!(*swap.load_full(), 30);
+        assert_eq!(*swap.into_inner(), 30);
+
+        Ok(())
+    }
+
+    #[test]
+    fn threads() -> anyhow::Result<()> {
+        let swap = ArcSwap::new(Arc::try_create(0u64)?);
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|s| -> anyhow::Result<()> {
+            let readers: std::vec::Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        //Writers only publish increasing values
+                        let mut last = 0;
+                        while !done.load(Ordering::SeqCst) {
+                            let value = *swap.load();
+                            assert!(value >= last);
+                            last = value;
+                        }
+                    })
+                })
+                .collect();
+
+            for value in 1..=1000u64 {
+                let previous = swap.swap(Arc::try_create(value)?);
+                assert_eq!(*previous, value - 1);
+            }
+            done.store(true, Ordering::SeqCst);
+
+            for reader in readers {
+                reader.join().unwrap();
+            }
+
+            Ok(())
+        })?;
+
+        assert_eq!(*swap.into_inner(), 1000);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::Waker,
+};
+
+use crate::{
+    constants::PoolFlags,
+    executor::atomic_waker::AtomicWaker,
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+    sys::{
+        event::{EventType, KeEvent},
+        WaitableKernelObject, WaitableObject,
+    },
+    traits::DispatchSafe,
+    vec::{Vec, VecExt},
+};
+
+use super::{
+    arc::{Arc, ArcExt, Weak},
+    ExSpinMutex,
+};
+
+struct CancellationInner {
+    cancelled: AtomicBool,
+    event: KeEvent,
+    //Woken after the event, lets a future wait for the token
+    waker: AtomicWaker,
+    children: ExSpinMutex<Vec<Weak<CancellationInner>>>,
+}
+
+unsafe impl Send for CancellationInner {}
+unsafe impl Sync for CancellationInner {}
+unsafe impl DispatchSafe for CancellationInner {}
+
+impl TaggedObject for CancellationInner {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"cncl")
+    }
+}
+
+impl CancellationInner {
+    fn cancel(&self) {
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        self.event.signal();
+        self.waker.wake();
+
+        //Children registered after this point see the flag and cancel themselves
+        let children = core::mem::replace(&mut *self.children.write_raw(), Self::children_vec());
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+
+    fn children_vec() -> Vec<Weak<CancellationInner>> {
+        Vec::new_in(GlobalKernelAllocator::new(
+            MemoryTag::new_from_bytes(b"cnlc"),
+            PoolFlags::POOL_FLAG_NON_PAGED,
+        ))
+    }
+}
+
+///
+/// Cooperative stop signal for long running work
+///
+/// Cancelling a token cancels every child created from it, cancelling a child
+/// does not affect its parent. Clones share the same state.
+/// The token is a notification event, waiting on it returns once it is cancelled
+/// and `WaitableObject::wait_cancellable_irql` returns `WaitResponse::Cancelled`.
+///
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<CancellationInner>,
+}
+
+unsafe impl DispatchSafe for CancellationToken {}
+
+impl CancellationToken {
+    pub fn try_create() -> anyhow::Result<Self> {
+        let inner = Arc::try_create(CancellationInner {
+            cancelled: AtomicBool::new(false),
+            event: unsafe { KeEvent::new() },
+            waker: AtomicWaker::new(),
+            children: ExSpinMutex::new(CancellationInner::children_vec()),
+        })?;
+        unsafe { inner.event.init(EventType::Notification, false) };
+
+        Ok(Self { inner })
+    }
+
+    ///
+    /// Creates a token that is cancelled together with `self`
+    ///
+    pub fn try_child(&self) -> anyhow::Result<Self> {
+        let child = Self::try_create()?;
+
+        {
+            let mut children = self.inner.children.write_raw();
+            if !self.inner.cancelled.load(Ordering::Acquire) {
+                //Drop the entries of children that no longer exist
+                children.retain(|child| child.strong_count() != 0);
+                children.try_push(Arc::downgrade(&child.inner))?;
+
+                return Ok(child);
+            }
+        }
+
+        child.cancel();
+        Ok(child)
+    }
+
+    #[inline]
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    ///
+    /// Wakes `waker` once the token is cancelled
+    ///
+    /// A token has a single waker slot, a future registers on a child
+    /// token of its own.
+    ///
+    #[inline]
+    pub(crate) fn register_waker(&self, waker: &Waker) {
+        self.inner.waker.register(waker);
+    }
+}
+
+unsafe impl WaitableObject for CancellationToken {
+    fn kernel_object(&self) -> &WaitableKernelObject {
+        self.inner.event.kernel_object()
+    }
+}
@@ -32,7 +32,7 @@ impl Event {
         let event = Arc::try_create(Self {
             inner: unsafe { KeEvent::new() },
         })?;
-        event.inner.init(ev_type, signaled);
+        unsafe { event.inner.init(ev_type, signaled) };
 
         Ok(event)
     }
@@ -41,7 +41,7 @@ impl Event {
         let pb = Box::try_pin(Self {
             inner: unsafe { KeEvent::new() },
         })?;
-        pb.inner.init(ev_type, signaled);
+        unsafe { pb.inner.init(ev_type, signaled) };
 
         Ok(pb)
     }
@@ -78,16 +78,6 @@ unsafe impl WaitableObject for Event {
         self.inner.kernel_object()
     }
 
-    #[inline]
-    fn wait(&self) -> crate::sys::WaitResponse {
-        self.inner.wait()
-    }
-
-    #[inline]
-    fn wait_for(&self, duration: core::time::Duration) -> crate::sys::WaitResponse {
-        self.inner.wait_for(duration)
-    }
-
     #[inline]
     fn wait_status(&self) -> crate::sys::WaitResponse {
         self.inner.wait_status()
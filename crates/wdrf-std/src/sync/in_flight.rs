@@ -0,0 +1,178 @@
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+
+use crate::{
+    boxed::{Box, BoxExt},
+    kmalloc::TaggedObject,
+    sys::{
+        event::{EventType, KeEvent},
+        wait_object, WaitResponse,
+    },
+    traits::DispatchSafe,
+};
+
+use super::arc::{Arc, ArcExt};
+
+const DRAINING: u32 = 1 << 31;
+const COUNT_MASK: u32 = !DRAINING;
+
+///
+/// Counts outstanding operations so they can be drained before unload
+///
+/// Every operation holds an `InFlightToken` while it runs.
+/// Once `drain` is called new entries are rejected and the caller
+/// blocks until the last token is dropped.
+///
+pub struct InFlightCounter {
+    state: AtomicU32,
+    drained: KeEvent,
+}
+
+unsafe impl Send for InFlightCounter {}
+unsafe impl Sync for InFlightCounter {}
+unsafe impl DispatchSafe for InFlightCounter {}
+
+impl TaggedObject for InFlightCounter {
+    fn tag() -> crate::kmalloc::MemoryTag {
+        crate::kmalloc::MemoryTag::new_from_bytes(b"infl")
+    }
+}
+
+pub struct InFlightToken<'a> {
+    counter: &'a InFlightCounter,
+}
+
+unsafe impl Send for InFlightToken<'_> {}
+unsafe impl Sync for InFlightToken<'_> {}
+unsafe impl DispatchSafe for InFlightToken<'_> {}
+
+impl Drop for InFlightToken<'_> {
+    fn drop(&mut self) {
+        self.counter.exit();
+    }
+}
+
+pub struct InFlightTokenOwned {
+    counter: Arc<InFlightCounter>,
+}
+
+unsafe impl DispatchSafe for InFlightTokenOwned {}
+
+impl Drop for InFlightTokenOwned {
+    fn drop(&mut self) {
+        self.counter.exit();
+    }
+}
+
+impl InFlightCounter {
+    pub fn try_create_arc() -> anyhow::Result<Arc<Self>> {
+        let counter = Arc::try_create(Self {
+            state: AtomicU32::new(0),
+            drained: unsafe { KeEvent::new() },
+        })?;
+        unsafe { counter.drained.init(EventType::Notification, false) };
+
+        Ok(counter)
+    }
+
+    pub fn try_create_box() -> anyhow::Result<Pin<Box<Self>>> {
+        let counter = Box::try_pin(Self {
+            state: AtomicU32::new(0),
+            drained: unsafe { KeEvent::new() },
+        })?;
+        unsafe { counter.drained.init(EventType::Notification, false) };
+
+        Ok(counter)
+    }
+
+    ///
+    /// Returns `None` if the counter is draining
+    ///
+    pub fn enter(&self) -> Option<InFlightToken<'_>> {
+        if self.try_increment() {
+            Some(InFlightToken { counter: self })
+        } else {
+            None
+        }
+    }
+
+    pub fn enter_owned(self: &Arc<Self>) -> Option<InFlightTokenOwned> {
+        if self.try_increment() {
+            Some(InFlightTokenOwned {
+                counter: self.clone(),
+            })
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn count(&self) -> u32 {
+        self.state.load(Ordering::Acquire) & COUNT_MASK
+    }
+
+    #[inline]
+    pub fn is_draining(&self) -> bool {
+        (self.state.load(Ordering::Acquire) & DRAINING) != 0
+    }
+
+    ///
+    /// Rejects new entries and waits for the outstanding ones
+    ///
+    /// Returns false if the timeout expired before the count reached zero,
+    /// the counter stays in the draining state either way.
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn drain(&self, timeout: Option<Duration>) -> bool {
+        let previous = self.state.fetch_or(DRAINING, Ordering::AcqRel);
+        if (previous & COUNT_MASK) == 0 {
+            self.drained.signal();
+            return true;
+        }
+
+        let status = wait_object(&self.drained, timeout);
+
+        status == WaitResponse::Success
+    }
+
+    ///
+    /// Accepts new entries again after a `drain`
+    ///
+    pub fn resume(&self) {
+        self.drained.clear();
+        self.state.fetch_and(COUNT_MASK, Ordering::AcqRel);
+    }
+
+    fn try_increment(&self) -> bool {
+        let mut current = self.state.load(Ordering::Relaxed);
+        loop {
+            if (current & DRAINING) != 0 {
+                return false;
+            }
+
+            match self.state.compare_exchange_weak(
+                current,
+                current + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn exit(&self) {
+        let previous = self.state.fetch_sub(1, Ordering::AcqRel);
+        if previous == (DRAINING | 1) {
+            self.drained.signal();
+        }
+    }
+}
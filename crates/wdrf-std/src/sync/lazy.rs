@@ -0,0 +1,85 @@
+use core::{cell::UnsafeCell, ops::Deref};
+
+use crate::traits::DispatchSafe;
+
+use super::once::OnceLock;
+
+///
+/// A value that is initialized on first access
+///
+/// Built on top of `OnceLock`, the same spinning rules apply.
+/// After `teardown` the initializer is gone and accessing the value panics.
+///
+pub struct LazyLock<T, F = fn() -> T> {
+    once: OnceLock<T>,
+    init: UnsafeCell<Option<F>>,
+}
+
+unsafe impl<T: Send, F: Send> Send for LazyLock<T, F> {}
+unsafe impl<T: Send + Sync, F: Send> Sync for LazyLock<T, F> {}
+unsafe impl<T: DispatchSafe, F> DispatchSafe for LazyLock<T, F> {}
+
+impl<T, F> LazyLock<T, F>
+where
+    F: FnOnce() -> T,
+{
+    pub const fn new(init: F) -> Self {
+        Self {
+            once: OnceLock::new(),
+            init: UnsafeCell::new(Some(init)),
+        }
+    }
+
+    pub fn force(this: &Self) -> &T {
+        this.once.get_or_init(|| {
+            //Only the thread that won the OnceLock race gets here
+            let init = unsafe { (*this.init.get()).take() };
+            match init {
+                Some(init) => init(),
+                None => panic!("LazyLock accessed after teardown"),
+            }
+        })
+    }
+
+    #[inline]
+    pub fn get(this: &Self) -> Option<&T> {
+        this.once.get()
+    }
+
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as `OnceLock::teardown`
+    ///
+    pub unsafe fn teardown(this: &Self) {
+        this.once.teardown();
+        *this.init.get() = None;
+    }
+}
+
+impl<T, F> Deref for LazyLock<T, F>
+where
+    F: FnOnce() -> T,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        Self::force(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LazyLock;
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let lazy = LazyLock::new(|| 10u32);
+        assert!(LazyLock::get(&lazy).is_none());
+
+        assert_eq!(*lazy, 10);
+        assert_eq!(LazyLock::get(&lazy), Some(&10));
+
+        Ok(())
+    }
+}
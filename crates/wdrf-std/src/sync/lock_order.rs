@@ -0,0 +1,641 @@
+//!
+//! Lock order checker enabled by the `lock-order-checks` feature
+//!
+//! Locks are grouped in classes keyed by the source location that constructed
+//! them, every lock created by the same `new` call site shares a class, the
+//! same way lockdep keys classes by their init site. Each acquisition records
+//! an edge from every class already held by the current execution context to
+//! the class being acquired.
+//! A cycle in that graph means two paths take the same locks in opposite order
+//! and will eventually deadlock. Acquiring a lock that is already held is
+//! reported as a recursive acquire, that check is keyed by the lock address.
+//! Holding two locks of the same class, such as a parent and a child object
+//! built by the same constructor, is allowed and records no edge.
+//!
+//! The bookkeeping lives in `LockOrderGraph` which does not touch the kernel,
+//! the global instance is protected by a raw ex spin lock.
+//!
+
+use core::{
+    fmt::Display,
+    panic::Location,
+    sync::atomic::{AtomicPtr, AtomicU32, AtomicUsize, Ordering},
+};
+
+pub const MAX_LOCK_CLASSES: usize = 256;
+pub const MAX_HELD_LOCKS: usize = 128;
+pub const MAX_RECORDED_EDGES: usize = 1024;
+
+const CLASS_WORDS: usize = MAX_LOCK_CLASSES / 64;
+
+/// Id 0 is reserved for locks that could not get a class (table full)
+const UNTRACKED_CLASS: u32 = 0;
+
+//Construction site of every class, the index is the class id
+static CLASS_SITES: [AtomicPtr<Location<'static>>; MAX_LOCK_CLASSES] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_LOCK_CLASSES];
+
+pub struct LockClass {
+    site: &'static Location<'static>,
+    id: AtomicU32,
+}
+
+impl LockClass {
+    ///
+    /// Class of every lock constructed at the caller location
+    ///
+    /// Lock constructors are `#[track_caller]` so this is the site that called `new`.
+    ///
+    #[track_caller]
+    pub fn new() -> Self {
+        Self::with_site(Location::caller())
+    }
+
+    pub const fn with_site(site: &'static Location<'static>) -> Self {
+        Self {
+            site,
+            id: AtomicU32::new(UNTRACKED_CLASS),
+        }
+    }
+
+    #[inline]
+    pub fn site(&self) -> &'static Location<'static> {
+        self.site
+    }
+
+    pub fn id(&self) -> u32 {
+        let id = self.id.load(Ordering::Relaxed);
+        if id != UNTRACKED_CLASS {
+            return id;
+        }
+
+        //Racing lookups of the same site resolve to the same id
+        let id = class_for_site(self.site);
+        self.id.store(id, Ordering::Relaxed);
+
+        id
+    }
+}
+
+impl Default for LockClass {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn same_site(a: &Location<'_>, b: &Location<'_>) -> bool {
+    a.line() == b.line() && a.column() == b.column() && a.file() == b.file()
+}
+
+///
+/// Finds the class registered for `site` or registers it in the first free slot
+///
+/// Returns `UNTRACKED_CLASS` once every class is taken.
+///
+fn class_for_site(site: &'static Location<'static>) -> u32 {
+    let site_ptr = site as *const Location<'static> as *mut Location<'static>;
+
+    for (idx, slot) in CLASS_SITES.iter().enumerate().skip(1) {
+        let mut current = slot.load(Ordering::Acquire);
+        if current.is_null() {
+            match slot.compare_exchange(
+                core::ptr::null_mut(),
+                site_ptr,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return idx as u32,
+                Err(other) => current = other,
+            }
+        }
+
+        if same_site(unsafe { &*current }, site) {
+            return idx as u32;
+        }
+    }
+
+    UNTRACKED_CLASS
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LockOrderViolation {
+    Recursive {
+        class: u32,
+        first: &'static Location<'static>,
+        second: &'static Location<'static>,
+    },
+    Inversion {
+        held: u32,
+        acquired: u32,
+        held_site: &'static Location<'static>,
+        acquired_site: &'static Location<'static>,
+        //Sites of the first recorded edge going back from acquired to held
+        previous: Option<(&'static Location<'static>, &'static Location<'static>)>,
+    },
+}
+
+impl Display for LockOrderViolation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LockOrderViolation::Recursive {
+                class,
+                first,
+                second,
+            } => write!(
+                f,
+                "Recursive acquire of lock class {class}: first at {first}, again at {second}"
+            ),
+            LockOrderViolation::Inversion {
+                held,
+                acquired,
+                held_site,
+                acquired_site,
+                previous,
+            } => {
+                write!(
+                    f,
+                    "Lock order inversion: class {acquired} acquired at {acquired_site} while holding class {held} acquired at {held_site}"
+                )?;
+                if let Some((from, to)) = previous {
+                    write!(f, ", opposite order recorded at {from} -> {to}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct HeldLock {
+    context: usize,
+    class: u32,
+    instance: usize,
+    site: &'static Location<'static>,
+}
+
+#[derive(Clone, Copy)]
+struct Edge {
+    from: u32,
+    to: u32,
+    from_site: &'static Location<'static>,
+    to_site: &'static Location<'static>,
+}
+
+pub struct LockOrderGraph {
+    adjacency: [[u64; CLASS_WORDS]; MAX_LOCK_CLASSES],
+    edges: [Option<Edge>; MAX_RECORDED_EDGES],
+    edge_count: usize,
+    held: [Option<HeldLock>; MAX_HELD_LOCKS],
+}
+
+impl LockOrderGraph {
+    pub const fn new() -> Self {
+        Self {
+            adjacency: [[0; CLASS_WORDS]; MAX_LOCK_CLASSES],
+            edges: [None; MAX_RECORDED_EDGES],
+            edge_count: 0,
+            held: [None; MAX_HELD_LOCKS],
+        }
+    }
+
+    ///
+    /// Records that `context` is about to acquire the lock at address
+    /// `instance`, which belongs to `class`
+    ///
+    /// The lock is recorded as held even if a violation is returned
+    /// so the matching `release` stays balanced.
+    ///
+    pub fn acquire(
+        &mut self,
+        context: usize,
+        class: u32,
+        instance: usize,
+        recursive: bool,
+        site: &'static Location<'static>,
+    ) -> Result<(), LockOrderViolation> {
+        if class == UNTRACKED_CLASS {
+            return Ok(());
+        }
+
+        let mut violation = None;
+
+        for idx in 0..MAX_HELD_LOCKS {
+            let Some(held) = self.held[idx] else {
+                continue;
+            };
+            if held.context != context {
+                continue;
+            }
+
+            if held.instance == instance {
+                if !recursive && violation.is_none() {
+                    violation = Some(LockOrderViolation::Recursive {
+                        class,
+                        first: held.site,
+                        second: site,
+                    });
+                }
+                continue;
+            }
+
+            //Another lock of the same class, the class graph can not order them
+            if held.class == class {
+                continue;
+            }
+
+            if self.has_edge(held.class, class) {
+                continue;
+            }
+
+            if self.reaches(class, held.class) {
+                if violation.is_none() {
+                    violation = Some(LockOrderViolation::Inversion {
+                        held: held.class,
+                        acquired: class,
+                        held_site: held.site,
+                        acquired_site: site,
+                        previous: self.first_edge_on_path(class, held.class),
+                    });
+                }
+                continue;
+            }
+
+            self.add_edge(held.class, class, held.site, site);
+        }
+
+        self.push_held(HeldLock {
+            context,
+            class,
+            instance,
+            site,
+        });
+
+        match violation {
+            Some(violation) => Err(violation),
+            None => Ok(()),
+        }
+    }
+
+    pub fn release(&mut self, context: usize, instance: usize) {
+        //Release the most recent acquisition first
+        for slot in self.held.iter_mut().rev() {
+            if slot.is_some_and(|held| held.context == context && held.instance == instance) {
+                *slot = None;
+                return;
+            }
+        }
+    }
+
+    pub fn held_count(&self, context: usize) -> usize {
+        self.held
+            .iter()
+            .filter(|held| held.is_some_and(|held| held.context == context))
+            .count()
+    }
+
+    fn push_held(&mut self, held: HeldLock) {
+        //If the table is full the lock is simply not tracked
+        if let Some(slot) = self.held.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(held);
+        }
+    }
+
+    #[inline]
+    fn has_edge(&self, from: u32, to: u32) -> bool {
+        let to = to as usize;
+        (self.adjacency[from as usize][to / 64] & (1 << (to % 64))) != 0
+    }
+
+    fn add_edge(
+        &mut self,
+        from: u32,
+        to: u32,
+        from_site: &'static Location<'static>,
+        to_site: &'static Location<'static>,
+    ) {
+        let to_idx = to as usize;
+        self.adjacency[from as usize][to_idx / 64] |= 1 << (to_idx % 64);
+
+        if self.edge_count < MAX_RECORDED_EDGES {
+            self.edges[self.edge_count] = Some(Edge {
+                from,
+                to,
+                from_site,
+                to_site,
+            });
+            self.edge_count += 1;
+        }
+    }
+
+    fn reaches(&self, from: u32, to: u32) -> bool {
+        self.search(from, to, &mut [0; MAX_LOCK_CLASSES])
+    }
+
+    ///
+    /// Depth first search, `parents` is filled with the class
+    /// each visited class was reached from (plus one, 0 means unvisited)
+    ///
+    fn search(&self, from: u32, to: u32, parents: &mut [u32; MAX_LOCK_CLASSES]) -> bool {
+        let mut stack = [0u32; MAX_LOCK_CLASSES];
+        let mut top = 0;
+
+        stack[top] = from;
+        top += 1;
+        parents[from as usize] = from + 1;
+
+        while top > 0 {
+            top -= 1;
+            let current = stack[top];
+            if current == to {
+                return true;
+            }
+
+            for next in 0..MAX_LOCK_CLASSES as u32 {
+                if parents[next as usize] == 0 && self.has_edge(current, next) {
+                    parents[next as usize] = current + 1;
+                    stack[top] = next;
+                    top += 1;
+                }
+            }
+        }
+
+        false
+    }
+
+    fn first_edge_on_path(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Option<(&'static Location<'static>, &'static Location<'static>)> {
+        let mut parents = [0; MAX_LOCK_CLASSES];
+        if !self.search(from, to, &mut parents) {
+            return None;
+        }
+
+        let mut current = to;
+        while parents[current as usize] - 1 != from {
+            current = parents[current as usize] - 1;
+        }
+
+        self.edges[..self.edge_count]
+            .iter()
+            .flatten()
+            .find(|edge| edge.from == from && edge.to == current)
+            .map(|edge| (edge.from_site, edge.to_site))
+    }
+}
+
+impl Default for LockOrderGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn default_violation_handler(violation: &LockOrderViolation) {
+    panic!("{violation}");
+}
+
+static VIOLATION_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+///
+/// Replaces the default handler, which panics, for example with a logger
+///
+pub fn set_violation_handler(handler: fn(&LockOrderViolation)) {
+    VIOLATION_HANDLER.store(handler as usize, Ordering::SeqCst);
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn report(violation: &LockOrderViolation) {
+    let handler = VIOLATION_HANDLER.load(Ordering::SeqCst);
+    if handler == 0 {
+        default_violation_handler(violation);
+    } else {
+        let handler: fn(&LockOrderViolation) = unsafe { core::mem::transmute(handler) };
+        handler(violation);
+    }
+}
+
+#[cfg(not(test))]
+mod global {
+    use core::{cell::UnsafeCell, panic::Location};
+
+    use windows_sys::Wdk::System::SystemServices::{
+        ExAcquireSpinLockExclusive, ExReleaseSpinLockExclusive, KeGetCurrentProcessorNumberEx,
+        KeIsExecutingDpc, PsGetCurrentThreadId,
+    };
+
+    use super::{report, LockClass, LockOrderGraph};
+
+    struct GlobalGraph {
+        lock: UnsafeCell<i32>,
+        graph: UnsafeCell<LockOrderGraph>,
+    }
+
+    unsafe impl Sync for GlobalGraph {}
+
+    static GLOBAL_GRAPH: GlobalGraph = GlobalGraph {
+        lock: UnsafeCell::new(0),
+        graph: UnsafeCell::new(LockOrderGraph::new()),
+    };
+
+    //DPCs borrow whatever thread they interrupted, key them by processor instead
+    const DPC_CONTEXT_BIT: usize = 1 << (usize::BITS - 1);
+
+    fn current_context() -> usize {
+        unsafe {
+            if KeIsExecutingDpc() != 0 {
+                DPC_CONTEXT_BIT | KeGetCurrentProcessorNumberEx(core::ptr::null_mut()) as usize
+            } else {
+                PsGetCurrentThreadId() as usize
+            }
+        }
+    }
+
+    fn with_graph<R, F: FnOnce(&mut LockOrderGraph) -> R>(f: F) -> R {
+        unsafe {
+            let old_irql = ExAcquireSpinLockExclusive(GLOBAL_GRAPH.lock.get());
+            let result = f(&mut *GLOBAL_GRAPH.graph.get());
+            ExReleaseSpinLockExclusive(GLOBAL_GRAPH.lock.get(), old_irql);
+
+            result
+        }
+    }
+
+    //Every lock embeds its own `LockClass`, its address identifies the lock
+    #[inline]
+    fn instance(class: &LockClass) -> usize {
+        class as *const LockClass as usize
+    }
+
+    #[track_caller]
+    pub(crate) fn on_acquire(class: &LockClass, recursive: bool) {
+        let site = Location::caller();
+        let context = current_context();
+        let instance = instance(class);
+        let class = class.id();
+
+        let result = with_graph(|graph| graph.acquire(context, class, instance, recursive, site));
+        if let Err(violation) = result {
+            report(&violation);
+        }
+    }
+
+    pub(crate) fn on_release(class: &LockClass) {
+        let context = current_context();
+        let instance = instance(class);
+
+        with_graph(|graph| graph.release(context, instance));
+    }
+}
+
+#[cfg(not(test))]
+pub(crate) use global::*;
+
+//Host builds have no kernel to key contexts on, the graph is tested directly
+#[cfg(test)]
+#[allow(dead_code)]
+mod global {
+    use super::LockClass;
+
+    pub(crate) fn on_acquire(_class: &LockClass, _recursive: bool) {}
+    pub(crate) fn on_release(_class: &LockClass) {}
+}
+
+#[cfg(test)]
+#[allow(unused_imports)]
+pub(crate) use global::*;
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::boxed::Box;
+
+    use core::panic::Location;
+
+    use super::{LockClass, LockOrderGraph, LockOrderViolation};
+
+    fn class_from_helper() -> LockClass {
+        LockClass::new()
+    }
+
+    #[test]
+    fn class_by_site() -> anyhow::Result<()> {
+        let first = class_from_helper();
+        let second = class_from_helper();
+        let other = LockClass::new();
+
+        assert_eq!(first.id(), second.id());
+        assert_ne!(first.id(), other.id());
+        assert_ne!(other.id(), 0);
+
+        //Cached ids stay stable
+        assert_eq!(first.id(), class_from_helper().id());
+
+        Ok(())
+    }
+
+    #[test]
+    fn inversion() -> anyhow::Result<()> {
+        let mut graph = Box::new(LockOrderGraph::new());
+        let site = Location::caller();
+
+        assert!(graph.acquire(1, 1, 1, false, site).is_ok());
+        assert!(graph.acquire(1, 2, 2, false, site).is_ok());
+        graph.release(1, 2);
+        graph.release(1, 1);
+        assert_eq!(graph.held_count(1), 0);
+
+        assert!(graph.acquire(2, 2, 2, false, site).is_ok());
+        let result = graph.acquire(2, 1, 1, false, site);
+        assert!(matches!(
+            result,
+            Err(LockOrderViolation::Inversion {
+                held: 2,
+                acquired: 1,
+                previous: Some(_),
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn transitive_inversion() -> anyhow::Result<()> {
+        let mut graph = Box::new(LockOrderGraph::new());
+        let site = Location::caller();
+
+        //1 -> 2 -> 3
+        graph.acquire(1, 1, 1, false, site).unwrap();
+        graph.acquire(1, 2, 2, false, site).unwrap();
+        graph.release(1, 1);
+        graph.acquire(1, 3, 3, false, site).unwrap();
+        graph.release(1, 3);
+        graph.release(1, 2);
+
+        graph.acquire(2, 3, 3, false, site).unwrap();
+        assert!(graph.acquire(2, 1, 1, false, site).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn recursive() -> anyhow::Result<()> {
+        let mut graph = Box::new(LockOrderGraph::new());
+        let site = Location::caller();
+
+        graph.acquire(1, 5, 5, true, site).unwrap();
+        assert!(graph.acquire(1, 5, 5, true, site).is_ok());
+
+        graph.acquire(1, 6, 6, false, site).unwrap();
+        assert!(matches!(
+            graph.acquire(1, 6, 6, false, site),
+            Err(LockOrderViolation::Recursive { class: 6, .. })
+        ));
+
+        //Different contexts holding the same lock is just contention
+        graph.acquire(2, 7, 7, false, site).unwrap();
+        assert!(graph.acquire(3, 7, 7, false, site).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn same_class() -> anyhow::Result<()> {
+        let mut graph = Box::new(LockOrderGraph::new());
+        let site = Location::caller();
+
+        //Two locks built by the same constructor, e.g. a parent and its child
+        graph.acquire(1, 8, 100, false, site).unwrap();
+        assert!(graph.acquire(1, 8, 200, false, site).is_ok());
+        graph.release(1, 200);
+        graph.release(1, 100);
+
+        //The child taken first orders nothing either
+        graph.acquire(1, 8, 200, false, site).unwrap();
+        assert!(graph.acquire(1, 8, 100, false, site).is_ok());
+        graph.release(1, 100);
+        graph.release(1, 200);
+        assert_eq!(graph.held_count(1), 0);
+
+        //Edges to other classes are still recorded for the class
+        graph.acquire(1, 8, 100, false, site).unwrap();
+        graph.acquire(1, 9, 300, false, site).unwrap();
+        graph.release(1, 300);
+        graph.release(1, 100);
+
+        graph.acquire(2, 9, 300, false, site).unwrap();
+        assert!(matches!(
+            graph.acquire(2, 8, 200, false, site),
+            Err(LockOrderViolation::Inversion {
+                held: 9,
+                acquired: 8,
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+}
@@ -1,10 +1,16 @@
 use core::cell::UnsafeCell;
 
 use windows_sys::Wdk::System::SystemServices::{
-    ExAcquireSpinLockExclusive, ExAcquireSpinLockShared, ExReleaseSpinLockExclusive,
-    ExReleaseSpinLockShared,
+    ExAcquireSpinLockExclusive, ExAcquireSpinLockExclusiveAtDpcLevel, ExAcquireSpinLockShared,
+    ExAcquireSpinLockSharedAtDpcLevel, ExReleaseSpinLockExclusive,
+    ExReleaseSpinLockExclusiveFromDpcLevel, ExReleaseSpinLockShared,
+    ExReleaseSpinLockSharedFromDpcLevel, ExTryAcquireSpinLockExclusiveAtDpcLevel,
+    ExTryAcquireSpinLockSharedAtDpcLevel,
 };
 
+use crate::irql::{raise_to_dispatch, BelowDispatch, Dispatch, IrqlGuard};
+#[cfg(feature = "lock-order-checks")]
+use crate::sync::lock_order::{self, LockClass};
 use crate::traits::DispatchSafe;
 
 use super::guard::{MutexGuard, ReadMutexGuard, Unlockable};
@@ -12,6 +18,8 @@ use super::guard::{MutexGuard, ReadMutexGuard, Unlockable};
 pub struct ExSpinMutex<T: DispatchSafe> {
     mutex: UnsafeCell<i32>,
     inner: UnsafeCell<T>,
+    #[cfg(feature = "lock-order-checks")]
+    class: LockClass,
 }
 
 unsafe impl<T: Send + DispatchSafe> Send for ExSpinMutex<T> {}
@@ -21,39 +29,160 @@ impl<T> ExSpinMutex<T>
 where
     T: DispatchSafe,
 {
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
     pub fn new(data: T) -> Self {
         Self {
             mutex: UnsafeCell::new(0),
             inner: UnsafeCell::new(data),
+            #[cfg(feature = "lock-order-checks")]
+            class: LockClass::new(),
         }
     }
 
+    ///
+    /// Acquires the lock at an unknown IRQL
+    ///
+    #[deprecated(note = "use `write_irql` or `write_at_dpc_level`")]
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
     pub fn write(&self) -> MutexGuard<ExSpinWriteUnlockable<'_, T>> {
+        self.write_raw()
+    }
+
+    #[deprecated(note = "use `read_irql` or `read_at_dpc_level`")]
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn read(&self) -> ReadMutexGuard<ExSpinReadUnlockable<'_, T>> {
+        self.read_raw()
+    }
+
+    ///
+    /// Raises to DISPATCH_LEVEL and hands out a `Dispatch` token for the
+    /// lifetime of the guard, the lower token stays borrowed until then
+    ///
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn write_irql<'a, 'b, I: BelowDispatch>(
+        &'a self,
+        irql: &'b mut I,
+    ) -> IrqlGuard<MutexGuard<'a, ExSpinWriteUnlockable<'a, T>>, Dispatch<'b>> {
+        IrqlGuard::new(self.write_raw(), raise_to_dispatch(irql))
+    }
+
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn read_irql<'a, 'b, I: BelowDispatch>(
+        &'a self,
+        irql: &'b mut I,
+    ) -> IrqlGuard<ReadMutexGuard<'a, ExSpinReadUnlockable<'a, T>>, Dispatch<'b>> {
+        IrqlGuard::new(self.read_raw(), raise_to_dispatch(irql))
+    }
+
+    ///
+    /// Skips raising the IRQL, the `Dispatch` token proves it already is at DISPATCH_LEVEL
+    ///
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn write_at_dpc_level<'a, 'b>(
+        &'a self,
+        irql: &'b mut Dispatch<'_>,
+    ) -> IrqlGuard<MutexGuard<'a, ExSpinWriteUnlockable<'a, T>>, Dispatch<'b>> {
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.class, false);
+
+        unsafe { ExAcquireSpinLockExclusiveAtDpcLevel(self.mutex.get()) };
+
+        let guard = MutexGuard::new(ExSpinWriteUnlockable::new(self, None), unsafe {
+            &mut *self.inner.get()
+        });
+        IrqlGuard::new(guard, irql.reborrow())
+    }
+
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn read_at_dpc_level<'a, 'b>(
+        &'a self,
+        irql: &'b mut Dispatch<'_>,
+    ) -> IrqlGuard<ReadMutexGuard<'a, ExSpinReadUnlockable<'a, T>>, Dispatch<'b>> {
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.class, false);
+
+        unsafe { ExAcquireSpinLockSharedAtDpcLevel(self.mutex.get()) };
+
+        let guard = ReadMutexGuard::new(ExSpinReadUnlockable::new(self, None), unsafe {
+            &*self.inner.get()
+        });
+        IrqlGuard::new(guard, irql.reborrow())
+    }
+
+    ///
+    /// Returns `None` without spinning if the lock is held
+    ///
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn try_write_at_dpc_level<'a, 'b>(
+        &'a self,
+        irql: &'b mut Dispatch<'_>,
+    ) -> Option<IrqlGuard<MutexGuard<'a, ExSpinWriteUnlockable<'a, T>>, Dispatch<'b>>> {
+        if unsafe { ExTryAcquireSpinLockExclusiveAtDpcLevel(self.mutex.get()) } == 0 {
+            return None;
+        }
+
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.class, false);
+
+        let guard = MutexGuard::new(ExSpinWriteUnlockable::new(self, None), unsafe {
+            &mut *self.inner.get()
+        });
+        Some(IrqlGuard::new(guard, irql.reborrow()))
+    }
+
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn try_read_at_dpc_level<'a, 'b>(
+        &'a self,
+        irql: &'b mut Dispatch<'_>,
+    ) -> Option<IrqlGuard<ReadMutexGuard<'a, ExSpinReadUnlockable<'a, T>>, Dispatch<'b>>> {
+        if unsafe { ExTryAcquireSpinLockSharedAtDpcLevel(self.mutex.get()) } == 0 {
+            return None;
+        }
+
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.class, false);
+
+        let guard = ReadMutexGuard::new(ExSpinReadUnlockable::new(self, None), unsafe {
+            &*self.inner.get()
+        });
+        Some(IrqlGuard::new(guard, irql.reborrow()))
+    }
+
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub(crate) fn write_raw(&self) -> MutexGuard<ExSpinWriteUnlockable<'_, T>> {
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.class, false);
+
         let old_irql = unsafe { ExAcquireSpinLockExclusive(self.mutex.get()) };
 
-        MutexGuard::new(ExSpinWriteUnlockable::new(self, old_irql), unsafe {
+        MutexGuard::new(ExSpinWriteUnlockable::new(self, Some(old_irql)), unsafe {
             &mut *self.inner.get()
         })
     }
 
-    pub fn read(&self) -> ReadMutexGuard<ExSpinReadUnlockable<'_, T>> {
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub(crate) fn read_raw(&self) -> ReadMutexGuard<ExSpinReadUnlockable<'_, T>> {
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.class, false);
+
         let old_irql = unsafe { ExAcquireSpinLockShared(self.mutex.get()) };
 
-        ReadMutexGuard::new(ExSpinReadUnlockable::new(self, old_irql), unsafe {
-            &mut *self.inner.get()
+        ReadMutexGuard::new(ExSpinReadUnlockable::new(self, Some(old_irql)), unsafe {
+            &*self.inner.get()
         })
     }
 }
 
 pub struct ExSpinWriteUnlockable<'a, T: DispatchSafe> {
     guard: &'a ExSpinMutex<T>,
-    old_irql: u8,
+    //None if the lock was acquired at DISPATCH_LEVEL
+    old_irql: Option<u8>,
 }
 
 unsafe impl<'a, T> Send for ExSpinWriteUnlockable<'a, T> where T: Send + DispatchSafe {}
 
 impl<'a, T: DispatchSafe> ExSpinWriteUnlockable<'a, T> {
-    fn new(guard: &'a ExSpinMutex<T>, old_irql: u8) -> Self {
+    fn new(guard: &'a ExSpinMutex<T>, old_irql: Option<u8>) -> Self {
         Self { guard, old_irql }
     }
 }
@@ -63,20 +192,27 @@ impl<'a, T: DispatchSafe> Unlockable for ExSpinWriteUnlockable<'a, T> {
 
     fn unlock(&self) {
         unsafe {
-            ExReleaseSpinLockExclusive(self.guard.mutex.get(), self.old_irql);
+            match self.old_irql {
+                Some(old_irql) => ExReleaseSpinLockExclusive(self.guard.mutex.get(), old_irql),
+                None => ExReleaseSpinLockExclusiveFromDpcLevel(self.guard.mutex.get()),
+            }
         }
+
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_release(&self.guard.class);
     }
 }
 
 pub struct ExSpinReadUnlockable<'a, T: DispatchSafe> {
     guard: &'a ExSpinMutex<T>,
-    old_irql: u8,
+    //None if the lock was acquired at DISPATCH_LEVEL
+    old_irql: Option<u8>,
 }
 
 unsafe impl<'a, T> Send for ExSpinReadUnlockable<'a, T> where T: Send + DispatchSafe {}
 
 impl<'a, T: DispatchSafe> ExSpinReadUnlockable<'a, T> {
-    fn new(guard: &'a ExSpinMutex<T>, old_irql: u8) -> Self {
+    fn new(guard: &'a ExSpinMutex<T>, old_irql: Option<u8>) -> Self {
         Self { guard, old_irql }
     }
 }
@@ -86,7 +222,13 @@ impl<'a, T: DispatchSafe> Unlockable for ExSpinReadUnlockable<'a, T> {
 
     fn unlock(&self) {
         unsafe {
-            ExReleaseSpinLockShared(self.guard.mutex.get(), self.old_irql);
+            match self.old_irql {
+                Some(old_irql) => ExReleaseSpinLockShared(self.guard.mutex.get(), old_irql),
+                None => ExReleaseSpinLockSharedFromDpcLevel(self.guard.mutex.get()),
+            }
         }
+
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_release(&self.guard.class);
     }
 }
@@ -3,26 +3,35 @@ use core::cell::UnsafeCell;
 use anyhow::Ok;
 use windows_sys::Wdk::{
     Foundation::FAST_MUTEX,
-    System::SystemServices::{KeInitializeGuardedMutex, KeReleaseGuardedMutex},
+    System::SystemServices::{
+        KeAcquireGuardedMutex, KeInitializeGuardedMutex, KeReleaseGuardedMutex,
+    },
 };
 
 use crate::{
     boxed::{Box, BoxExt},
     constants::PoolFlags,
+    irql::{raise_to_apc, Apc, BelowDispatch, IrqlGuard},
     kmalloc::{GlobalKernelAllocator, MemoryTag},
 };
 
+#[cfg(feature = "lock-order-checks")]
+use crate::sync::lock_order::{self, LockClass};
+
 use super::guard::{MutexGuard, Unlockable};
 
 pub struct GuardedMutex<T> {
     mutex: Box<FAST_MUTEX>,
     inner: UnsafeCell<T>,
+    #[cfg(feature = "lock-order-checks")]
+    class: LockClass,
 }
 
 unsafe impl<T: Send> Send for GuardedMutex<T> {}
 unsafe impl<T: Sync> Sync for GuardedMutex<T> {}
 
 impl<T> GuardedMutex<T> {
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
     pub fn new(data: T) -> anyhow::Result<Self> {
         unsafe {
             let mut mutex = Box::try_create_in(
@@ -38,15 +47,44 @@ impl<T> GuardedMutex<T> {
             Ok(Self {
                 mutex,
                 inner: UnsafeCell::new(data),
+                #[cfg(feature = "lock-order-checks")]
+                class: LockClass::new(),
             })
         }
     }
 
+    #[deprecated(note = "use `lock_irql`")]
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
     pub fn lock(&self) -> MutexGuard<GuardedUnlockable<'_, T>> {
+        self.lock_raw()
+    }
+
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    fn lock_raw(&self) -> MutexGuard<GuardedUnlockable<'_, T>> {
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.class, false);
+
+        unsafe {
+            let ptr: *const FAST_MUTEX = self.mutex.as_ref();
+            KeAcquireGuardedMutex(ptr as _);
+        }
+
         MutexGuard::new(GuardedUnlockable { guard: self }, unsafe {
             &mut *self.inner.get()
         })
     }
+
+    ///
+    /// Acquiring a guarded mutex disables all APCs,
+    /// the returned guard carries an `Apc` token
+    ///
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn lock_irql<'a, 'b, I: BelowDispatch>(
+        &'a self,
+        irql: &'b mut I,
+    ) -> IrqlGuard<MutexGuard<'a, GuardedUnlockable<'a, T>>, Apc<'b>> {
+        IrqlGuard::new(self.lock_raw(), raise_to_apc(irql))
+    }
 }
 
 pub struct GuardedUnlockable<'a, T> {
@@ -63,5 +101,8 @@ impl<'a, T> Unlockable for GuardedUnlockable<'a, T> {
             let ptr: *const FAST_MUTEX = self.guard.mutex.as_ref();
             KeReleaseGuardedMutex(ptr as _);
         }
+
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_release(&self.guard.class);
     }
 }
@@ -1,11 +1,13 @@
 mod ex_spin;
 mod guard;
 mod guarded_lock;
+mod push_lock;
 mod resource;
 mod stack_spin;
 
 pub use ex_spin::*;
 pub use guard::*;
 pub use guarded_lock::*;
+pub use push_lock::*;
 pub use resource::*;
 pub use stack_spin::*;
@@ -0,0 +1,143 @@
+use core::cell::UnsafeCell;
+#[cfg(feature = "lock-order-checks")]
+use core::panic::Location;
+
+use windows_sys::Wdk::System::SystemServices::{
+    ExAcquirePushLockExclusiveEx, ExAcquirePushLockSharedEx, ExReleasePushLockExclusiveEx,
+    ExReleasePushLockSharedEx, KeEnterCriticalRegion, KeLeaveCriticalRegion,
+};
+
+use crate::irql::BelowDispatch;
+#[cfg(feature = "lock-order-checks")]
+use crate::sync::lock_order::{self, LockClass};
+
+use super::{MutexGuard, ReadMutexGuard, Unlockable};
+
+///
+/// Reader/writer lock backed by an `EX_PUSH_LOCK`
+///
+/// A push lock is a single pointer sized word initialized to zero, so unlike
+/// `EResource` it needs no allocation and can be built in a `static`.
+/// It is not recursive and must be acquired at or below APC_LEVEL,
+/// normal kernel APCs are disabled while it is held.
+///
+pub struct PushLock<T> {
+    lock: UnsafeCell<usize>,
+    data: UnsafeCell<T>,
+    #[cfg(feature = "lock-order-checks")]
+    class: LockClass,
+}
+
+unsafe impl<T: Send> Send for PushLock<T> {}
+unsafe impl<T: Send + Sync> Sync for PushLock<T> {}
+
+impl<T> PushLock<T> {
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            lock: UnsafeCell::new(0),
+            data: UnsafeCell::new(data),
+            #[cfg(feature = "lock-order-checks")]
+            class: LockClass::with_site(Location::caller()),
+        }
+    }
+
+    #[deprecated(note = "use `write_irql`")]
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn write(&self) -> MutexGuard<'_, PushLockUnlockable<'_, T>> {
+        self.write_raw()
+    }
+
+    ///
+    /// Acquires the lock exclusively, the token proves the caller is below
+    /// DISPATCH_LEVEL. The IRQL is not raised, only normal kernel APCs are
+    /// disabled until the guard is dropped
+    ///
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn write_irql<I: BelowDispatch>(
+        &self,
+        _irql: &I,
+    ) -> MutexGuard<'_, PushLockUnlockable<'_, T>> {
+        self.write_raw()
+    }
+
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub(crate) fn write_raw(&self) -> MutexGuard<'_, PushLockUnlockable<'_, T>> {
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.class, false);
+
+        unsafe {
+            KeEnterCriticalRegion();
+            ExAcquirePushLockExclusiveEx(self.lock.get(), 0);
+        }
+
+        MutexGuard::new(
+            PushLockUnlockable {
+                lock: self,
+                exclusive: true,
+            },
+            unsafe { &mut *self.data.get() },
+        )
+    }
+
+    #[deprecated(note = "use `read_irql`")]
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn read(&self) -> ReadMutexGuard<'_, PushLockUnlockable<'_, T>> {
+        self.read_raw()
+    }
+
+    ///
+    /// Acquires the lock shared, see `write_irql`
+    ///
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn read_irql<I: BelowDispatch>(
+        &self,
+        _irql: &I,
+    ) -> ReadMutexGuard<'_, PushLockUnlockable<'_, T>> {
+        self.read_raw()
+    }
+
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub(crate) fn read_raw(&self) -> ReadMutexGuard<'_, PushLockUnlockable<'_, T>> {
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.class, false);
+
+        unsafe {
+            KeEnterCriticalRegion();
+            ExAcquirePushLockSharedEx(self.lock.get(), 0);
+        }
+
+        ReadMutexGuard::new(
+            PushLockUnlockable {
+                lock: self,
+                exclusive: false,
+            },
+            unsafe { &*self.data.get() },
+        )
+    }
+}
+
+pub struct PushLockUnlockable<'a, T> {
+    lock: &'a PushLock<T>,
+    exclusive: bool,
+}
+
+unsafe impl<'a, T> Send for PushLockUnlockable<'a, T> where T: Send {}
+
+impl<'a, T> Unlockable for PushLockUnlockable<'a, T> {
+    type Item = T;
+
+    fn unlock(&self) {
+        unsafe {
+            if self.exclusive {
+                ExReleasePushLockExclusiveEx(self.lock.lock.get(), 0);
+            } else {
+                ExReleasePushLockSharedEx(self.lock.lock.get(), 0);
+            }
+            KeLeaveCriticalRegion();
+        }
+
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_release(&self.lock.class);
+    }
+}
@@ -14,10 +14,14 @@ use windows_sys::{
 use crate::{
     boxed::{Box, BoxExt},
     constants::PoolFlags,
+    irql::BelowDispatch,
     kmalloc::{MemoryTag, TaggedObject},
     NtResult, NtResultEx, NtStatusError,
 };
 
+#[cfg(feature = "lock-order-checks")]
+use crate::sync::lock_order::{self, LockClass};
+
 use super::{MutexGuard, ReadMutexGuard, Unlockable};
 
 pub struct EResource<T> {
@@ -30,13 +34,18 @@ unsafe impl<T: Sync> Sync for EResource<T> {}
 struct EResourceInner<T> {
     resource: UnsafeCell<ERESOURCE>,
     data: UnsafeCell<T>,
+    #[cfg(feature = "lock-order-checks")]
+    class: LockClass,
 }
 
 impl<T> EResource<T> {
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
     pub fn try_create(data: T) -> NtResult<Self> {
         let inner = Box::try_create(EResourceInner {
             resource: unsafe { core::mem::zeroed() },
             data: UnsafeCell::new(data),
+            #[cfg(feature = "lock-order-checks")]
+            class: LockClass::new(),
         })
         .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
         let status = unsafe { ExInitializeResourceLite(inner.resource.get()) };
@@ -44,7 +53,31 @@ impl<T> EResource<T> {
         NtResult::from_status(status, move || Self { inner })
     }
 
+    #[deprecated(note = "use `write_irql`")]
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
     pub fn write<'a>(&'a self) -> MutexGuard<'a, EResourceUnlockable<'a, T>> {
+        self.write_raw()
+    }
+
+    ///
+    /// Acquires the resource exclusively, the token proves the caller is
+    /// below DISPATCH_LEVEL. Normal kernel APCs are disabled until the guard
+    /// is dropped
+    ///
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn write_irql<'a, I: BelowDispatch>(
+        &'a self,
+        _irql: &I,
+    ) -> MutexGuard<'a, EResourceUnlockable<'a, T>> {
+        self.write_raw()
+    }
+
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    fn write_raw<'a>(&'a self) -> MutexGuard<'a, EResourceUnlockable<'a, T>> {
+        //ERESOURCE can be acquired recursively by the owning thread
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.inner.class, true);
+
         unsafe {
             KeEnterCriticalRegion();
             let _ = ExAcquireResourceExclusiveLite(self.inner.resource.get(), true as _);
@@ -58,7 +91,28 @@ impl<T> EResource<T> {
         )
     }
 
+    #[deprecated(note = "use `read_irql`")]
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
     pub fn read<'a>(&'a self) -> ReadMutexGuard<'a, EResourceUnlockable<'a, T>> {
+        self.read_raw()
+    }
+
+    ///
+    /// Acquires the resource shared, see `write_irql`
+    ///
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn read_irql<'a, I: BelowDispatch>(
+        &'a self,
+        _irql: &I,
+    ) -> ReadMutexGuard<'a, EResourceUnlockable<'a, T>> {
+        self.read_raw()
+    }
+
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    fn read_raw<'a>(&'a self) -> ReadMutexGuard<'a, EResourceUnlockable<'a, T>> {
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.inner.class, true);
+
         unsafe {
             KeEnterCriticalRegion();
             let _ = ExAcquireResourceSharedLite(self.inner.resource.get(), true as _);
@@ -97,5 +151,8 @@ impl<'a, T> Unlockable for EResourceUnlockable<'a, T> {
             ExReleaseResourceLite(self.resource.resource.get());
             KeLeaveCriticalRegion();
         }
+
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_release(&self.resource.class);
     }
 }
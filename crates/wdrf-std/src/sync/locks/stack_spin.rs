@@ -1,8 +1,12 @@
-use core::cell::UnsafeCell;
+use core::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomPinned,
+    pin::Pin,
+};
 
 use windows_sys::Wdk::System::SystemServices::{
-    KeAcquireInStackQueuedSpinLock, KeInitializeSpinLock, KeReleaseInStackQueuedSpinLock,
-    KLOCK_QUEUE_HANDLE,
+    KeAcquireInStackQueuedSpinLock, KeAcquireInStackQueuedSpinLockAtDpcLevel, KeInitializeSpinLock,
+    KeReleaseInStackQueuedSpinLock, KeReleaseInStackQueuedSpinLockFromDpcLevel, KLOCK_QUEUE_HANDLE,
 };
 
 #[cfg(feature = "irql-checks")]
@@ -10,17 +14,42 @@ use windows_sys::Wdk::System::SystemServices::DISPATCH_LEVEL;
 
 use wdrf_macros::irql_check;
 
+use crate::irql::{raise_to_dispatch, BelowDispatch, Dispatch, IrqlGuard};
+#[cfg(feature = "lock-order-checks")]
+use crate::sync::lock_order::{self, LockClass};
 use crate::traits::DispatchSafe;
 
 use super::guard::{MutexGuard, Unlockable};
 
-pub struct InStackLockHandle {
-    handle: UnsafeCell<KLOCK_QUEUE_HANDLE>,
-}
-
+///
+/// Spin lock acquired with an in-stack queued handle
+///
+/// The `KLOCK_QUEUE_HANDLE` is linked into the lock queue while the lock is held,
+/// so it lives inside a pinned `StackSpinGuard` and can not be moved.
+/// Use the closure functions or the `stack_spin_lock!` macro:
+///
+/// ```ignore
+/// let len = mutex.with_lock_irql(&mut irql, |data| data.len());
+///
+/// stack_spin_lock!(let mut data = mutex, &mut irql);
+/// data.push(10);
+/// ```
+///
+/// Callers that do not know their IRQL raise with `DispatchGuard` and use
+/// `with_lock_at_dpc_level`.
+///
+/// There is no `try_lock`. The kernel has no try-acquire for in-stack queued
+/// spin locks, `KeTryToAcquireQueuedSpinLock` only takes the numbered system
+/// locks and `KeTryToAcquireSpinLockAtDpcLevel` must not be mixed with queued
+/// acquires of the same lock. Checking with `KeTestSpinLock` first still
+/// spins when another processor wins the race. Data that needs a try path
+/// belongs in an `ExSpinMutex`, see `try_write_at_dpc_level`.
+///
 pub struct StackSpinMutex<T: DispatchSafe> {
     lock: UnsafeCell<usize>,
     inner: UnsafeCell<T>,
+    #[cfg(feature = "lock-order-checks")]
+    class: LockClass,
 }
 
 unsafe impl<T: DispatchSafe> Send for StackSpinMutex<T> {}
@@ -30,6 +59,7 @@ impl<T> StackSpinMutex<T>
 where
     T: DispatchSafe,
 {
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
     pub fn new(data: T) -> Self {
         let handle = unsafe {
             let mut handle = 0;
@@ -40,69 +70,214 @@ where
         Self {
             lock: UnsafeCell::new(handle),
             inner: UnsafeCell::new(data),
+            #[cfg(feature = "lock-order-checks")]
+            class: LockClass::new(),
         }
     }
 
+    #[inline]
+    pub fn guard(&self) -> StackSpinGuard<'_, T> {
+        StackSpinGuard::new(self)
+    }
+
+    #[deprecated(note = "use `with_lock_irql` or `with_lock_at_dpc_level`")]
     #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
-    pub fn lock<'a>(
-        &'a self,
-        handle: &'a InStackLockHandle,
-    ) -> MutexGuard<'a, InStackSpinLockUnlocakble<'a, T>> {
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn with_lock<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        let guard = core::pin::pin!(self.guard());
+        let mut data = guard.into_ref().lock_raw();
+
+        f(&mut data)
+    }
+
+    ///
+    /// Raises to DISPATCH_LEVEL for the duration of `f`
+    ///
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn with_lock_irql<I: BelowDispatch, R, F: FnOnce(&mut T) -> R>(
+        &self,
+        irql: &mut I,
+        f: F,
+    ) -> R {
+        let guard = core::pin::pin!(self.guard());
+        let mut data = guard.into_ref().lock_irql(irql);
+
+        f(&mut data)
+    }
+
+    ///
+    /// Skips raising the IRQL, the `Dispatch` token proves it already is at DISPATCH_LEVEL
+    ///
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn with_lock_at_dpc_level<R, F: FnOnce(&mut T) -> R>(
+        &self,
+        irql: &mut Dispatch<'_>,
+        f: F,
+    ) -> R {
+        let guard = core::pin::pin!(self.guard());
+        let mut data = guard.into_ref().lock_at_dpc_level(irql);
+
+        f(&mut data)
+    }
+
+    unsafe fn release(&self, handle: *mut KLOCK_QUEUE_HANDLE, at_dpc: bool) {
         unsafe {
-            self.lock_unchecked(handle);
+            if at_dpc {
+                KeReleaseInStackQueuedSpinLockFromDpcLevel(handle);
+            } else {
+                KeReleaseInStackQueuedSpinLock(handle);
+            }
         }
 
-        MutexGuard::new(InStackSpinLockUnlocakble::new(self, handle), unsafe {
-            &mut *self.inner.get()
-        })
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_release(&self.class);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GuardState {
+    Unlocked,
+    Locked,
+    LockedAtDpc,
+}
+
+///
+/// Owns the queue handle of a single acquisition
+///
+/// Must be pinned before locking, the lock is released when the returned
+/// `MutexGuard` is dropped or at the latest when this guard is dropped.
+///
+pub struct StackSpinGuard<'a, T: DispatchSafe> {
+    mutex: &'a StackSpinMutex<T>,
+    handle: UnsafeCell<KLOCK_QUEUE_HANDLE>,
+    state: Cell<GuardState>,
+    _pin: PhantomPinned,
+}
+
+impl<'a, T> StackSpinGuard<'a, T>
+where
+    T: DispatchSafe,
+{
+    pub fn new(mutex: &'a StackSpinMutex<T>) -> Self {
+        Self {
+            mutex,
+            handle: unsafe { core::mem::zeroed() },
+            state: Cell::new(GuardState::Unlocked),
+            _pin: PhantomPinned,
+        }
     }
 
-    unsafe fn lock_unchecked<'a>(&'a self, handle: &'a InStackLockHandle) {
+    #[deprecated(note = "use `lock_irql` or `lock_at_dpc_level`")]
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn lock<'g>(self: Pin<&'g Self>) -> MutexGuard<'g, StackSpinUnlockable<'g, 'a, T>> {
+        self.lock_raw()
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    fn lock_raw<'g>(self: Pin<&'g Self>) -> MutexGuard<'g, StackSpinUnlockable<'g, 'a, T>> {
+        self.assert_unlocked();
+
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.mutex.class, false);
+
         unsafe {
-            KeAcquireInStackQueuedSpinLock(self.lock.get(), &mut *handle.handle.get());
+            KeAcquireInStackQueuedSpinLock(self.mutex.lock.get(), self.handle.get());
         }
+
+        self.locked(GuardState::Locked)
     }
 
-    unsafe fn unlock_unchecked(&self, handle: &InStackLockHandle) {
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn lock_at_dpc_level<'g>(
+        self: Pin<&'g Self>,
+        _irql: &'g mut Dispatch<'_>,
+    ) -> MutexGuard<'g, StackSpinUnlockable<'g, 'a, T>> {
+        self.assert_unlocked();
+
+        #[cfg(feature = "lock-order-checks")]
+        lock_order::on_acquire(&self.mutex.class, false);
+
         unsafe {
-            KeReleaseInStackQueuedSpinLock(&mut *handle.handle.get());
+            KeAcquireInStackQueuedSpinLockAtDpcLevel(self.mutex.lock.get(), self.handle.get());
         }
+
+        self.locked(GuardState::LockedAtDpc)
     }
-}
 
-impl InStackLockHandle {
-    pub fn new() -> Self {
-        Self {
-            handle: unsafe { core::mem::zeroed() },
+    #[cfg_attr(feature = "lock-order-checks", track_caller)]
+    pub fn lock_irql<'g, 'b, I: BelowDispatch>(
+        self: Pin<&'g Self>,
+        irql: &'b mut I,
+    ) -> IrqlGuard<MutexGuard<'g, StackSpinUnlockable<'g, 'a, T>>, Dispatch<'b>> {
+        IrqlGuard::new(self.lock_raw(), raise_to_dispatch(irql))
+    }
+
+    fn locked<'g>(
+        self: Pin<&'g Self>,
+        state: GuardState,
+    ) -> MutexGuard<'g, StackSpinUnlockable<'g, 'a, T>> {
+        self.state.set(state);
+
+        let guard = self.get_ref();
+        MutexGuard::new(StackSpinUnlockable { guard }, unsafe {
+            &mut *guard.mutex.inner.get()
+        })
+    }
+
+    fn assert_unlocked(&self) {
+        if self.state.get() != GuardState::Unlocked {
+            panic!("StackSpinGuard is already locked");
         }
     }
-}
 
-pub struct InStackSpinLockUnlocakble<'a, T: DispatchSafe> {
-    mutex: &'a StackSpinMutex<T>,
-    handle: &'a InStackLockHandle,
+    fn unlock(&self) {
+        let state = self.state.replace(GuardState::Unlocked);
+        if state != GuardState::Unlocked {
+            unsafe {
+                self.mutex
+                    .release(self.handle.get(), state == GuardState::LockedAtDpc);
+            }
+        }
+    }
 }
 
-unsafe impl<'a, T> Send for InStackSpinLockUnlocakble<'a, T> where T: Send + DispatchSafe {}
-
-impl<'a, T> InStackSpinLockUnlocakble<'a, T>
+impl<'a, T> Drop for StackSpinGuard<'a, T>
 where
     T: DispatchSafe,
 {
-    pub fn new(mutex: &'a StackSpinMutex<T>, handle: &'a InStackLockHandle) -> Self {
-        Self { mutex, handle }
+    fn drop(&mut self) {
+        //Only reached with the lock held if the MutexGuard was forgotten
+        self.unlock();
     }
 }
 
-impl<'a, T> Unlockable for InStackSpinLockUnlocakble<'a, T>
+pub struct StackSpinUnlockable<'g, 'a, T: DispatchSafe> {
+    guard: &'g StackSpinGuard<'a, T>,
+}
+
+impl<'g, 'a, T> Unlockable for StackSpinUnlockable<'g, 'a, T>
 where
     T: DispatchSafe,
 {
     type Item = T;
 
     fn unlock(&self) {
-        unsafe {
-            self.mutex.unlock_unchecked(self.handle);
-        }
+        self.guard.unlock();
     }
 }
+
+///
+/// Locks a `StackSpinMutex` for the rest of the scope
+///
+/// `stack_spin_lock!(let mut data = mutex, &mut irql)` pins the queue handle on
+/// the stack and binds the guard to `data`, `irql` stays borrowed until the end
+/// of the scope.
+///
+#[macro_export]
+macro_rules! stack_spin_lock {
+    (let $guard:pat = $mutex:expr, $irql:expr) => {
+        let __stack_spin_guard = ::core::pin::pin!($crate::sync::StackSpinGuard::new(&$mutex));
+        let $guard = __stack_spin_guard.into_ref().lock_irql($irql);
+    };
+}
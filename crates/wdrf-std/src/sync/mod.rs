@@ -3,7 +3,15 @@ mod locks;
 pub use locks::*;
 
 pub mod arc;
+pub mod arc_swap;
+pub mod cancel;
 pub mod event;
+pub mod in_flight;
+pub mod lazy;
+#[cfg(any(feature = "lock-order-checks", test))]
+pub mod lock_order;
 //pub mod mutex;
+pub mod once;
+pub mod per_cpu;
 //pub mod rwlock;
 pub mod semaphore;
@@ -0,0 +1,216 @@
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::traits::DispatchSafe;
+
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+
+///
+/// A cell that is written exactly once and can be used as a driver global
+///
+/// Unlike `Context<T>`, reading an uninitialized `OnceLock` returns `None`
+/// instead of handing out a reference to uninitialized memory.
+///
+/// Concurrent initializers spin until the running initializer finishes,
+/// so it is usable up to DISPATCH_LEVEL as long as the initializer itself is.
+/// A thread must not wait on a lock that it is currently initializing.
+///
+pub struct OnceLock<T> {
+    state: AtomicU8,
+    data: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for OnceLock<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+unsafe impl<T: DispatchSafe> DispatchSafe for OnceLock<T> {}
+
+impl<T> OnceLock<T> {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(INCOMPLETE),
+            data: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    #[inline]
+    pub fn is_initialized(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    #[inline]
+    pub fn get(&self) -> Option<&T> {
+        if self.is_initialized() {
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if *self.state.get_mut() == COMPLETE {
+            Some(unsafe { (*self.data.get()).assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Stores `value` if the lock was not initialized yet
+    /// Returns the value back if someone else initialized it first
+    ///
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    pub fn get_or_init<F>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> T,
+    {
+        match self.get_or_try_init(|| Ok::<T, core::convert::Infallible>(f())) {
+            Ok(value) => value,
+            Err(e) => match e {},
+        }
+    }
+
+    ///
+    /// Runs `f` if the lock is not initialized
+    ///
+    /// If `f` fails the lock stays uninitialized and the error is returned,
+    /// the next caller will try to initialize it again.
+    ///
+    pub fn get_or_try_init<E, F>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+
+        loop {
+            match self.state.compare_exchange_weak(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(COMPLETE) => return Ok(unsafe { self.get_unchecked() }),
+                Err(_) => core::hint::spin_loop(),
+            }
+        }
+
+        match f() {
+            Ok(value) => {
+                unsafe {
+                    (*self.data.get()).write(value);
+                }
+                self.state.store(COMPLETE, Ordering::Release);
+
+                Ok(unsafe { self.get_unchecked() })
+            }
+            Err(e) => {
+                self.state.store(INCOMPLETE, Ordering::Release);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn take(&mut self) -> Option<T> {
+        if *self.state.get_mut() == COMPLETE {
+            *self.state.get_mut() = INCOMPLETE;
+            Some(unsafe { (*self.data.get()).assume_init_read() })
+        } else {
+            None
+        }
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        self.take()
+    }
+
+    ///
+    /// # Safety
+    ///
+    /// Drops the stored value and marks the lock as uninitialized
+    /// There must be no outstanding references returned by `get`
+    /// and no concurrent initializers, usually only called on driver unload
+    ///
+    pub unsafe fn teardown(&self) {
+        if self
+            .state
+            .compare_exchange(COMPLETE, RUNNING, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            (*self.data.get()).assume_init_drop();
+            self.state.store(INCOMPLETE, Ordering::Release);
+        }
+    }
+
+    #[inline]
+    unsafe fn get_unchecked(&self) -> &T {
+        (*self.data.get()).assume_init_ref()
+    }
+}
+
+impl<T> Default for OnceLock<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<T> for OnceLock<T> {
+    fn from(value: T) -> Self {
+        Self {
+            state: AtomicU8::new(COMPLETE),
+            data: UnsafeCell::new(MaybeUninit::new(value)),
+        }
+    }
+}
+
+impl<T> Drop for OnceLock<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == COMPLETE {
+            unsafe {
+                (*self.data.get()).assume_init_drop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OnceLock;
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let once: OnceLock<u32> = OnceLock::new();
+        assert!(once.get().is_none());
+
+        let failed: Result<&u32, ()> = once.get_or_try_init(|| Err(()));
+        assert!(failed.is_err());
+        assert!(once.get().is_none());
+
+        assert_eq!(*once.get_or_init(|| 10), 10);
+        assert_eq!(*once.get_or_init(|| 20), 10);
+        assert_eq!(once.set(30), Err(30));
+
+        unsafe { once.teardown() };
+        assert!(once.get().is_none());
+        assert_eq!(once.set(40), Ok(()));
+        assert_eq!(once.into_inner(), Some(40));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//!
+//! Storage with one slot per logical processor
+//!
+//! A slot is only handed out while the IRQL is at DISPATCH_LEVEL so the
+//! thread can not migrate while it holds the reference. Every slot lives on
+//! its own cache line, processors bumping their counters do not contend.
+//! Other processors may still read a slot through `iter`, so the values are
+//! shared and mutation goes through atomics or a lock inside `T`.
+//!
+
+use core::ops::Deref;
+
+use windows_sys::{
+    Wdk::System::SystemServices::{KeGetCurrentProcessorNumberEx, KeQueryActiveProcessorCountEx},
+    Win32::System::Threading::ALL_PROCESSOR_GROUPS,
+};
+
+use crate::{
+    aligned::CacheAligned,
+    constants::PoolFlags,
+    irql::{Dispatch, DispatchGuard},
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    vec::Vec,
+};
+
+pub struct PerCpu<T> {
+    slots: Vec<CacheAligned<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for PerCpu<T> {}
+unsafe impl<T: Send + Sync> Sync for PerCpu<T> {}
+
+impl<T> PerCpu<T> {
+    ///
+    /// Creates a slot for every active processor in every processor group,
+    /// `init` receives the system wide processor index
+    ///
+    pub fn try_create_with<F>(mut init: F) -> anyhow::Result<Self>
+    where
+        F: FnMut(u32) -> T,
+    {
+        let count = unsafe { KeQueryActiveProcessorCountEx(ALL_PROCESSOR_GROUPS) } as usize;
+
+        let mut slots = Vec::new_in(GlobalKernelAllocator::new(
+            MemoryTag::new_from_bytes(b"pcpu"),
+            PoolFlags::POOL_FLAG_NON_PAGED,
+        ));
+        slots
+            .try_reserve_exact(count)
+            .map_err(|_| anyhow::Error::msg("Failed to reserve per cpu slots"))?;
+
+        for index in 0..count {
+            slots.push(CacheAligned(init(index as u32)));
+        }
+
+        Ok(Self { slots })
+    }
+
+    pub fn try_create() -> anyhow::Result<Self>
+    where
+        T: Default,
+    {
+        Self::try_create_with(|_| T::default())
+    }
+
+    ///
+    /// Number of slots, the active processor count at creation
+    ///
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    ///
+    /// Raises to DISPATCH_LEVEL and returns the slot of the current processor
+    ///
+    /// # Panics
+    ///
+    /// If called above DISPATCH_LEVEL
+    ///
+    pub fn get(&self) -> PerCpuGuard<'_, T> {
+        let guard = DispatchGuard::raise();
+        let slot = self.current_slot();
+
+        PerCpuGuard { slot, guard }
+    }
+
+    ///
+    /// The slot of the current processor for a caller already at DISPATCH_LEVEL
+    ///
+    #[inline]
+    pub fn get_at_dispatch<'a>(&'a self, _irql: &'a Dispatch<'_>) -> &'a T {
+        self.current_slot()
+    }
+
+    ///
+    /// Slot of a given processor index, meant for reporting
+    ///
+    #[inline]
+    pub fn get_for(&self, index: u32) -> Option<&T> {
+        self.slots.get(index as usize).map(|slot| &slot.0)
+    }
+
+    ///
+    /// Iterates every slot in processor index order
+    ///
+    /// The other processors keep running, the values seen are a snapshot
+    /// at best.
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().map(|slot| &slot.0)
+    }
+
+    fn current_slot(&self) -> &T {
+        let index = unsafe { KeGetCurrentProcessorNumberEx(core::ptr::null_mut()) } as usize;
+
+        //Processors hot added after creation share the existing slots
+        &self.slots[index % self.slots.len()].0
+    }
+}
+
+///
+/// Slot of the current processor, the IRQL goes back down on drop
+///
+pub struct PerCpuGuard<'a, T> {
+    slot: &'a T,
+    guard: DispatchGuard,
+}
+
+impl<'a, T> PerCpuGuard<'a, T> {
+    ///
+    /// Token for calling DISPATCH_LEVEL only APIs while holding the slot
+    ///
+    #[inline]
+    pub fn irql(&mut self) -> Dispatch<'_> {
+        self.guard.irql()
+    }
+}
+
+impl<'a, T> Deref for PerCpuGuard<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.slot
+    }
+}
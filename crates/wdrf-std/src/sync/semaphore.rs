@@ -2,8 +2,9 @@ use core::{num::NonZeroU32, pin::Pin};
 
 use crate::{
     boxed::{Box, BoxExt},
+    irql::BelowDispatch,
     kmalloc::TaggedObject,
-    sys::{semaphore::KeSemaphore, WaitResponse, WaitableObject},
+    sys::{semaphore::KeSemaphore, wait_object, WaitResponse, WaitableObject},
     traits::DispatchSafe,
 };
 
@@ -74,7 +75,7 @@ impl Semaphore {
             limit,
         })?;
 
-        arc_sem.inner.init(count as _, limit as _);
+        unsafe { arc_sem.inner.init(count as _, limit as _) };
 
         Ok(arc_sem)
     }
@@ -85,13 +86,28 @@ impl Semaphore {
             limit,
         })?;
 
-        box_sem.inner.init(count as _, limit as _);
+        unsafe { box_sem.inner.init(count as _, limit as _) };
 
         Ok(box_sem)
     }
 
+    #[deprecated(note = "use `acquire_irql`")]
     pub fn acquire(&self) -> anyhow::Result<SemaphorePermit<'_>, AcquireError> {
-        if self.wait() != WaitResponse::Success {
+        self.acquire_raw()
+    }
+
+    ///
+    /// Waits for a permit, the token proves the thread can wait
+    ///
+    pub fn acquire_irql<I: BelowDispatch>(
+        &self,
+        _irql: &I,
+    ) -> anyhow::Result<SemaphorePermit<'_>, AcquireError> {
+        self.acquire_raw()
+    }
+
+    fn acquire_raw(&self) -> anyhow::Result<SemaphorePermit<'_>, AcquireError> {
+        if wait_object(self, None) != WaitResponse::Success {
             Err(AcquireError)
         } else {
             Ok(SemaphorePermit::new(self))
@@ -106,8 +122,20 @@ impl Semaphore {
         }
     }
 
+    #[deprecated(note = "use `acquire_owned_irql`")]
     pub fn acquire_owned(self: &Arc<Self>) -> anyhow::Result<SemaphorePermitOwned, AcquireError> {
-        if self.wait() != WaitResponse::Success {
+        self.acquire_owned_raw()
+    }
+
+    pub fn acquire_owned_irql<I: BelowDispatch>(
+        self: &Arc<Self>,
+        _irql: &I,
+    ) -> anyhow::Result<SemaphorePermitOwned, AcquireError> {
+        self.acquire_owned_raw()
+    }
+
+    fn acquire_owned_raw(self: &Arc<Self>) -> anyhow::Result<SemaphorePermitOwned, AcquireError> {
+        if wait_object(&**self, None) != WaitResponse::Success {
             Err(AcquireError)
         } else {
             Ok(SemaphorePermitOwned::new(self.clone()))
@@ -139,16 +167,6 @@ unsafe impl WaitableObject for Semaphore {
         self.inner.kernel_object()
     }
 
-    #[inline]
-    fn wait(&self) -> crate::sys::WaitResponse {
-        self.inner.wait()
-    }
-
-    #[inline]
-    fn wait_for(&self, duration: core::time::Duration) -> crate::sys::WaitResponse {
-        self.inner.wait_for(duration)
-    }
-
     #[inline]
     fn wait_status(&self) -> crate::sys::WaitResponse {
         self.inner.wait_status()
@@ -1,3 +1,5 @@
+use core::marker::PhantomPinned;
+
 use windows_sys::{
     Wdk::{
         Foundation::KEVENT,
@@ -9,10 +11,13 @@ use windows_sys::{
 
 use crate::kmalloc::TaggedObject;
 
-use super::{WaitableKernelObject, WaitableObject};
+use super::{pin_init::PinInit, WaitableKernelObject, WaitableObject};
 
+///
+/// Not `Unpin`, a pinned event can not be moved back out
+///
 #[repr(C)]
-pub struct KeEvent(KEVENT);
+pub struct KeEvent(KEVENT, PhantomPinned);
 
 impl TaggedObject for KeEvent {
     fn tag() -> crate::kmalloc::MemoryTag {
@@ -29,7 +34,7 @@ pub enum EventType {
 unsafe impl Send for KeEvent {}
 
 impl EventType {
-    fn as_wdm_value(self) -> i32 {
+    pub(crate) fn as_wdm_value(self) -> i32 {
         match self {
             EventType::Notification => NotificationEvent,
             EventType::Synchronization => SynchronizationEvent,
@@ -45,10 +50,25 @@ impl KeEvent {
     /// resulting in  a BugCheck
     ///
     pub unsafe fn new() -> Self {
-        unsafe { Self(core::mem::zeroed::<KEVENT>()) }
+        unsafe { Self(core::mem::zeroed::<KEVENT>(), PhantomPinned) }
+    }
+
+    pub fn pin_init(evtype: EventType, signaled: bool) -> impl PinInit<Self> {
+        unsafe {
+            super::pin_init::pin_init_from_closure(move |slot: *mut Self| {
+                KeInitializeEvent(slot.cast(), evtype.as_wdm_value(), signaled as _);
+                Ok(())
+            })
+        }
     }
 
-    pub fn init(&self, evtype: EventType, signaled: bool) {
+    ///
+    /// # Safety
+    ///
+    /// The object must not be in use and must not move afterwards,
+    /// `pin_init` is the safe way to build one
+    ///
+    pub unsafe fn init(&self, evtype: EventType, signaled: bool) {
         unsafe {
             let event: *const KEVENT = &self.0;
             KeInitializeEvent(event as _, evtype.as_wdm_value(), signaled as _);
@@ -1,25 +1,31 @@
 #![allow(dead_code)]
 
 pub mod event;
-//pub(crate) mod mutex;
+pub mod multi_wait;
+pub mod mutex;
+pub mod pin_init;
+pub mod resource;
 pub mod semaphore;
+pub mod timer;
 
 use core::time::Duration;
 
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::{APC_LEVEL, DISPATCH_LEVEL};
 use windows_sys::{
-    Wdk::System::SystemServices::{
-        Executive, KeWaitForMultipleObjects, KeWaitForSingleObject, KernelMode,
-    },
-    Win32::{
-        Foundation::{
-            NTSTATUS, STATUS_ABANDONED_WAIT_0, STATUS_ABANDONED_WAIT_63,
-            STATUS_MUTANT_LIMIT_EXCEEDED, STATUS_SUCCESS, STATUS_TIMEOUT, STATUS_WAIT_0,
-            STATUS_WAIT_63,
-        },
-        System::Kernel::{WaitAll, WaitAny},
+    Wdk::System::SystemServices::{Executive, KeWaitForSingleObject, KernelMode},
+    Win32::Foundation::{
+        NTSTATUS, STATUS_ABANDONED_WAIT_0, STATUS_ABANDONED_WAIT_63, STATUS_ALERTED,
+        STATUS_MUTANT_LIMIT_EXCEEDED, STATUS_SUCCESS, STATUS_TIMEOUT, STATUS_USER_APC,
+        STATUS_WAIT_0, STATUS_WAIT_63,
     },
 };
 
+use crate::{irql::BelowDispatch, sync::cancel::CancellationToken, time::duration_to_ticks};
+
+use multi_wait::{MultiWaitBuilder, MultiWaitResult};
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum WaitResponse {
     Success,
@@ -27,6 +33,12 @@ pub enum WaitResponse {
     MutantLimitExceeded,
     Object(u32),
     Abandoned(u32),
+    Alerted,
+    UserApc,
+    ///
+    /// The token passed to `wait_cancellable` was cancelled
+    ///
+    Cancelled,
 }
 
 impl WaitResponse {
@@ -39,6 +51,8 @@ impl WaitResponse {
                 Self::Abandoned((status - STATUS_ABANDONED_WAIT_0) as _)
             }
             STATUS_MUTANT_LIMIT_EXCEEDED => Self::MutantLimitExceeded,
+            STATUS_ALERTED => Self::Alerted,
+            STATUS_USER_APC => Self::UserApc,
             _ => panic!("Unknown KeWaitForSingleObject status: {}", status),
         }
     }
@@ -56,37 +70,17 @@ pub struct WaitableKernelObject;
 pub unsafe trait WaitableObject {
     fn kernel_object(&self) -> &WaitableKernelObject;
 
-    //#[cfg_attr(feature = "irql-check", irql_check(irql = APC_LELVEL))]
+    #[deprecated(note = "use `wait_irql`")]
     fn wait(&self) -> WaitResponse {
-        unsafe {
-            let ptr: *const WaitableKernelObject = self.kernel_object();
-
-            let status = KeWaitForSingleObject(
-                ptr as _,
-                Executive,
-                KernelMode as _,
-                false as _,
-                core::ptr::null_mut(),
-            );
-
-            WaitResponse::from_ntstatus(status)
-        }
+        wait_object(self, None)
     }
 
-    //#[cfg_attr(feature = "irql-check", irql_check(irql = APC_LELVEL))]
+    #[deprecated(note = "use `wait_for_irql`")]
     fn wait_for(&self, duration: Duration) -> WaitResponse {
-        unsafe {
-            let timeout: i64 = -((duration.as_nanos() / 100) as i64);
-
-            let ptr: *const WaitableKernelObject = self.kernel_object();
-            let status =
-                KeWaitForSingleObject(ptr.cast(), Executive, KernelMode as _, false as _, &timeout);
-
-            WaitResponse::from_ntstatus(status)
-        }
+        wait_object(self, Some(duration))
     }
 
-    //#[cfg_attr(feature = "irql-check", irql_check(irql = DISPATCH_LEVEL))]
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
     fn wait_status(&self) -> WaitResponse {
         unsafe {
             let timeout: i64 = 0;
@@ -98,96 +92,111 @@ pub unsafe trait WaitableObject {
             WaitResponse::from_ntstatus(status)
         }
     }
-}
-
-pub struct DpcWaitError;
-
-pub struct MultiWaitArray<'a> {
-    wait_array: &'a [&'a WaitableKernelObject],
-    wait_all: i32,
-}
 
-impl<'a> MultiWaitArray<'a> {
-    #[inline(always)]
-    pub fn new(objects: &'a [&'a WaitableKernelObject]) -> Self {
-        Self {
-            wait_array: objects,
-            wait_all: WaitAny,
-        }
+    #[deprecated(note = "use `wait_cancellable_irql`")]
+    fn wait_cancellable(&self, token: &CancellationToken, timeout: Option<Duration>) -> WaitResponse
+    where
+        Self: Sized,
+    {
+        wait_object_cancellable(self, token, timeout)
     }
 
-    #[inline(always)]
-    pub fn new_wait_all(objects: &'a [&'a WaitableKernelObject]) -> Self {
-        Self {
-            wait_array: objects,
-            wait_all: WaitAll,
-        }
+    ///
+    /// Waits until the object is signaled or `token` is cancelled
+    ///
+    #[inline]
+    fn wait_cancellable_irql<I: BelowDispatch>(
+        &self,
+        _irql: &I,
+        token: &CancellationToken,
+        timeout: Option<Duration>,
+    ) -> WaitResponse
+    where
+        Self: Sized,
+    {
+        wait_object_cancellable(self, token, timeout)
     }
-}
 
-unsafe impl<'a> WaitableObject for MultiWaitArray<'a> {
-    fn kernel_object(&self) -> &WaitableKernelObject {
-        panic!("Cannot get kernel_object from a waitable array")
+    ///
+    /// Waits until the object is signaled, the token proves the thread can wait
+    ///
+    #[inline]
+    fn wait_irql<I: BelowDispatch>(&self, _irql: &I) -> WaitResponse
+    where
+        Self: Sized,
+    {
+        wait_object(self, None)
     }
 
-    fn wait(&self) -> WaitResponse {
-        unsafe {
-            let array = self.wait_array.as_ptr();
-
-            let status = KeWaitForMultipleObjects(
-                self.wait_array.len() as _,
-                array as _,
-                self.wait_all,
-                Executive,
-                KernelMode as _,
-                false as _,
-                core::ptr::null_mut(),
-                core::ptr::null_mut(),
-            );
-
-            WaitResponse::from_ntstatus(status)
-        }
+    #[inline]
+    fn wait_for_irql<I: BelowDispatch>(&self, _irql: &I, duration: Duration) -> WaitResponse
+    where
+        Self: Sized,
+    {
+        wait_object(self, Some(duration))
     }
+}
 
-    fn wait_for(&self, duration: Duration) -> WaitResponse {
-        unsafe {
-            let timeout = -((duration.as_nanos() / 100) as i64);
-
-            let array = self.wait_array.as_ptr();
-
-            let status = KeWaitForMultipleObjects(
-                self.wait_array.len() as _,
-                array as _,
-                self.wait_all,
+///
+/// Untokened wait used inside the crate where the IRQL is known from context
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = APC_LEVEL))]
+pub(crate) fn wait_object<W: WaitableObject + ?Sized>(
+    object: &W,
+    timeout: Option<Duration>,
+) -> WaitResponse {
+    unsafe {
+        let ptr: *const WaitableKernelObject = object.kernel_object();
+
+        let status = match timeout {
+            Some(duration) => {
+                let timeout: i64 = -duration_to_ticks(duration);
+                KeWaitForSingleObject(ptr as _, Executive, KernelMode as _, false as _, &timeout)
+            }
+            None => KeWaitForSingleObject(
+                ptr as _,
                 Executive,
                 KernelMode as _,
                 false as _,
-                &timeout,
                 core::ptr::null_mut(),
-            );
+            ),
+        };
 
-            WaitResponse::from_ntstatus(status)
-        }
+        WaitResponse::from_ntstatus(status)
     }
+}
 
-    fn wait_status(&self) -> WaitResponse {
-        unsafe {
-            let timeout = 0;
-
-            let array = self.wait_array.as_ptr();
-
-            let status = KeWaitForMultipleObjects(
-                self.wait_array.len() as _,
-                array as _,
-                self.wait_all,
-                Executive,
-                KernelMode as _,
-                false as _,
-                &timeout,
-                core::ptr::null_mut(),
-            );
+///
+/// Untokened cancellable wait used inside the crate
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = APC_LEVEL))]
+pub(crate) fn wait_object_cancellable<W: WaitableObject>(
+    object: &W,
+    token: &CancellationToken,
+    timeout: Option<Duration>,
+) -> WaitResponse {
+    if token.is_cancelled() {
+        return WaitResponse::Cancelled;
+    }
 
-            WaitResponse::from_ntstatus(status)
-        }
+    let mut wait = match MultiWaitBuilder::any()
+        .object(object)
+        .cancellable(token)
+        .build()
+    {
+        Ok(wait) => wait,
+        //Two objects fit in the thread wait blocks, nothing is allocated
+        Err(_) => unreachable!(),
+    };
+
+    match wait.wait_timeout(timeout) {
+        MultiWaitResult::Signaled(_) | MultiWaitResult::AllSignaled => WaitResponse::Success,
+        MultiWaitResult::Abandoned(index) => WaitResponse::Abandoned(index as _),
+        MultiWaitResult::Timeout => WaitResponse::Timeout,
+        MultiWaitResult::Alerted => WaitResponse::Alerted,
+        MultiWaitResult::UserApc => WaitResponse::UserApc,
+        MultiWaitResult::Cancelled => WaitResponse::Cancelled,
     }
 }
+
+pub struct DpcWaitError;
@@ -0,0 +1,333 @@
+use core::{ffi::c_void, marker::PhantomData, time::Duration};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::{APC_LEVEL, DISPATCH_LEVEL};
+use windows_sys::{
+    Wdk::{
+        Foundation::KWAIT_BLOCK,
+        System::SystemServices::{
+            Executive, KeWaitForMultipleObjects, KernelMode, UserMode, UserRequest,
+            THREAD_WAIT_OBJECTS,
+        },
+    },
+    Win32::{
+        Foundation::{
+            NTSTATUS, STATUS_ABANDONED_WAIT_0, STATUS_ABANDONED_WAIT_63, STATUS_ALERTED,
+            STATUS_TIMEOUT, STATUS_USER_APC, STATUS_WAIT_0, STATUS_WAIT_63,
+        },
+        System::{
+            Kernel::{WaitAll, WaitAny},
+            SystemServices::MAXIMUM_WAIT_OBJECTS,
+        },
+    },
+};
+
+use crate::{
+    constants::PoolFlags,
+    irql::BelowDispatch,
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    sync::cancel::CancellationToken,
+    time::duration_to_ticks,
+    vec::{Vec, VecExt},
+};
+
+use super::WaitableObject;
+
+const MAX_OBJECTS: usize = MAXIMUM_WAIT_OBJECTS as usize;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaitType {
+    Any,
+    All,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaitMode {
+    Kernel,
+    ///
+    /// The thread can receive user APCs while waiting and its kernel stack
+    /// can be paged out, all the objects must live in non paged memory
+    ///
+    User,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MultiWaitResult {
+    ///
+    /// Index of the object that satisfied a `WaitType::Any` wait
+    /// in the order the objects were added
+    ///
+    Signaled(usize),
+    ///
+    /// Every object was signaled, only returned for `WaitType::All`
+    ///
+    AllSignaled,
+    Abandoned(usize),
+    Timeout,
+    Alerted,
+    UserApc,
+    ///
+    /// The token added with `MultiWaitBuilder::cancellable` was cancelled
+    ///
+    Cancelled,
+}
+
+impl MultiWaitResult {
+    fn from_ntstatus(status: NTSTATUS, wait_type: WaitType) -> Self {
+        match status {
+            STATUS_WAIT_0..=STATUS_WAIT_63 => match wait_type {
+                WaitType::Any => Self::Signaled((status - STATUS_WAIT_0) as _),
+                WaitType::All => Self::AllSignaled,
+            },
+            STATUS_ABANDONED_WAIT_0..=STATUS_ABANDONED_WAIT_63 => {
+                Self::Abandoned((status - STATUS_ABANDONED_WAIT_0) as _)
+            }
+            STATUS_TIMEOUT => Self::Timeout,
+            STATUS_ALERTED => Self::Alerted,
+            STATUS_USER_APC => Self::UserApc,
+            _ => panic!("Unknown KeWaitForMultipleObjects status: {}", status),
+        }
+    }
+}
+
+///
+/// Collects the objects of a `KeWaitForMultipleObjects` wait
+///
+/// ```ignore
+/// let mut wait = MultiWaitBuilder::new(WaitType::Any)
+///     .object(&stop_event)
+///     .object(&work_semaphore)
+///     .alertable(true)
+///     .build()?;
+///
+/// match wait.wait_irql(&passive) {
+///     MultiWaitResult::Signaled(0) => stop(),
+///     MultiWaitResult::Signaled(1) => work(),
+///     _ => {}
+/// }
+/// ```
+///
+pub struct MultiWaitBuilder<'a> {
+    objects: [*const c_void; MAX_OBJECTS],
+    count: usize,
+    wait_type: WaitType,
+    mode: WaitMode,
+    alertable: bool,
+    cancel_index: Option<usize>,
+    _objects: PhantomData<&'a dyn WaitableObject>,
+}
+
+impl<'a> MultiWaitBuilder<'a> {
+    pub fn new(wait_type: WaitType) -> Self {
+        Self {
+            objects: [core::ptr::null(); MAX_OBJECTS],
+            count: 0,
+            wait_type,
+            mode: WaitMode::Kernel,
+            alertable: false,
+            cancel_index: None,
+            _objects: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn any() -> Self {
+        Self::new(WaitType::Any)
+    }
+
+    #[inline]
+    pub fn all() -> Self {
+        Self::new(WaitType::All)
+    }
+
+    ///
+    /// # Panics
+    ///
+    /// If more than `MAXIMUM_WAIT_OBJECTS` objects are added
+    ///
+    pub fn object(mut self, object: &'a dyn WaitableObject) -> Self {
+        if self.try_add(object).is_none() {
+            panic!("MultiWaitBuilder supports at most {} objects", MAX_OBJECTS);
+        }
+
+        self
+    }
+
+    ///
+    /// Returns the index reported by `MultiWaitResult::Signaled` for this object
+    /// or `None` if the builder is full
+    ///
+    pub fn try_add(&mut self, object: &'a dyn WaitableObject) -> Option<usize> {
+        if self.count == MAX_OBJECTS {
+            return None;
+        }
+
+        let index = self.count;
+        let ptr: *const super::WaitableKernelObject = object.kernel_object();
+        self.objects[index] = ptr.cast();
+        self.count += 1;
+
+        Some(index)
+    }
+
+    ///
+    /// Makes the wait return `MultiWaitResult::Cancelled` once `token` is cancelled
+    ///
+    /// Only meaningful for `WaitType::Any`, in a `WaitType::All` wait
+    /// the token is just one more object that must be signaled.
+    ///
+    pub fn cancellable(mut self, token: &'a CancellationToken) -> Self {
+        self = self.object(token);
+        self.cancel_index = Some(self.count - 1);
+
+        self
+    }
+
+    #[inline]
+    pub fn mode(mut self, mode: WaitMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    #[inline]
+    pub fn alertable(mut self, alertable: bool) -> Self {
+        self.alertable = alertable;
+        self
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    ///
+    /// Allocates the wait blocks if more than `THREAD_WAIT_OBJECTS`
+    /// objects were added, the thread built in ones are used otherwise
+    ///
+    pub fn build(self) -> anyhow::Result<MultiWait<'a>> {
+        if self.count == 0 {
+            return Err(anyhow::Error::msg("MultiWait requires at least one object"));
+        }
+
+        let wait_blocks = if self.count > THREAD_WAIT_OBJECTS as usize {
+            let mut blocks = Vec::new_in(GlobalKernelAllocator::new(
+                MemoryTag::new_from_bytes(b"wblk"),
+                PoolFlags::POOL_FLAG_NON_PAGED,
+            ));
+            blocks.try_resize(self.count, unsafe { core::mem::zeroed::<KWAIT_BLOCK>() })?;
+
+            Some(blocks)
+        } else {
+            None
+        };
+
+        Ok(MultiWait {
+            config: self,
+            wait_blocks,
+        })
+    }
+}
+
+///
+/// A reusable wait on multiple objects
+///
+/// Waiting takes `&mut self` because the kernel writes to the wait blocks
+/// for the duration of the wait.
+///
+pub struct MultiWait<'a> {
+    config: MultiWaitBuilder<'a>,
+    wait_blocks: Option<Vec<KWAIT_BLOCK>>,
+}
+
+impl<'a> MultiWait<'a> {
+    #[deprecated(note = "use `wait_irql`")]
+    pub fn wait(&mut self) -> MultiWaitResult {
+        self.wait_timeout(None)
+    }
+
+    #[deprecated(note = "use `wait_for_irql`")]
+    pub fn wait_for(&mut self, duration: Duration) -> MultiWaitResult {
+        self.wait_timeout(Some(duration))
+    }
+
+    ///
+    /// Waits on the objects, the token proves the thread can wait
+    ///
+    #[inline]
+    pub fn wait_irql<I: BelowDispatch>(&mut self, _irql: &I) -> MultiWaitResult {
+        self.wait_timeout(None)
+    }
+
+    #[inline]
+    pub fn wait_for_irql<I: BelowDispatch>(
+        &mut self,
+        _irql: &I,
+        duration: Duration,
+    ) -> MultiWaitResult {
+        self.wait_timeout(Some(duration))
+    }
+
+    ///
+    /// Untokened wait used inside the crate where the IRQL is known from context
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = APC_LEVEL))]
+    pub(crate) fn wait_timeout(&mut self, timeout: Option<Duration>) -> MultiWaitResult {
+        match timeout {
+            Some(duration) => {
+                let timeout: i64 = -duration_to_ticks(duration);
+                unsafe { self.wait_raw(&timeout) }
+            }
+            None => unsafe { self.wait_raw(core::ptr::null()) },
+        }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    pub fn wait_status(&mut self) -> MultiWaitResult {
+        let timeout: i64 = 0;
+
+        unsafe { self.wait_raw(&timeout) }
+    }
+
+    unsafe fn wait_raw(&mut self, timeout: *const i64) -> MultiWaitResult {
+        let config = &self.config;
+
+        let (wait_reason, wait_mode) = match config.mode {
+            WaitMode::Kernel => (Executive, KernelMode),
+            WaitMode::User => (UserRequest, UserMode),
+        };
+
+        let wait_type = match config.wait_type {
+            WaitType::Any => WaitAny,
+            WaitType::All => WaitAll,
+        };
+
+        let wait_blocks = match self.wait_blocks {
+            Some(ref mut blocks) => blocks.as_mut_ptr(),
+            None => core::ptr::null_mut(),
+        };
+
+        let status = KeWaitForMultipleObjects(
+            config.count as _,
+            config.objects.as_ptr(),
+            wait_type,
+            wait_reason,
+            wait_mode as _,
+            config.alertable as _,
+            timeout,
+            wait_blocks,
+        );
+
+        match MultiWaitResult::from_ntstatus(status, config.wait_type) {
+            MultiWaitResult::Signaled(index) if Some(index) == config.cancel_index => {
+                MultiWaitResult::Cancelled
+            }
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use core::marker::PhantomPinned;
+
+use windows_sys::Wdk::{
+    Foundation::KMUTANT,
+    System::SystemServices::{KeInitializeMutex, KeReadStateMutex, KeReleaseMutex},
+};
+
+use crate::kmalloc::TaggedObject;
+
+use super::{pin_init::PinInit, WaitableKernelObject, WaitableObject};
+
+///
+/// A kernel mutex, acquired by waiting on it
+///
+/// Not `Unpin`, a pinned mutex can not be moved back out.
+///
+#[repr(C)]
+pub struct KeMutex(KMUTANT, PhantomPinned);
+
+impl TaggedObject for KeMutex {
+    fn tag() -> crate::kmalloc::MemoryTag {
+        crate::kmalloc::MemoryTag::new_from_bytes(b"kmtx")
+    }
+}
+
+unsafe impl Send for KeMutex {}
+
+impl KeMutex {
+    ///
+    ///# Safety
+    ///
+    /// Moving this object will invalidate internal pointers
+    /// resulting in  a BugCheck
+    ///
+    pub unsafe fn new() -> Self {
+        Self(unsafe { core::mem::zeroed() }, PhantomPinned)
+    }
+
+    pub fn pin_init() -> impl PinInit<Self> {
+        unsafe {
+            super::pin_init::pin_init_from_closure(|slot: *mut Self| {
+                KeInitializeMutex(slot.cast(), 0);
+                Ok(())
+            })
+        }
+    }
+
+    ///
+    /// # Safety
+    ///
+    /// The object must not be in use and must not move afterwards,
+    /// `pin_init` is the safe way to build one
+    ///
+    pub unsafe fn init(&self) {
+        unsafe {
+            let ptr: *const KMUTANT = &self.0;
+            KeInitializeMutex(ptr as _, 0);
+        }
+    }
+
+    ///
+    /// # Safety
+    ///
+    /// The current thread must own the mutex
+    ///
+    pub unsafe fn release(&self) {
+        let ptr: *const KMUTANT = &self.0;
+        KeReleaseMutex(ptr as _, false as _);
+    }
+
+    pub fn read_state(&self) -> i32 {
+        unsafe {
+            let ptr: *const KMUTANT = &self.0;
+            KeReadStateMutex(ptr)
+        }
+    }
+}
+
+unsafe impl WaitableObject for KeMutex {
+    fn kernel_object(&self) -> &WaitableKernelObject {
+        unsafe {
+            let ptr: *const KMUTANT = &self.0;
+            &*ptr.cast()
+        }
+    }
+}
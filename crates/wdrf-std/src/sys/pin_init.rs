@@ -0,0 +1,278 @@
+//!
+//! In place initialization of kernel objects
+//!
+//! Dispatcher objects and resources contain list entries that point back
+//! into the object, they must be initialized where they are going to live
+//! and never move afterwards. `PinInit<T>` describes how to initialize a `T`
+//! inside an already pinned slot, `pin_init!` combines the initializers of
+//! every field into an initializer for the parent so several kernel objects
+//! can share a single allocation.
+//!
+//! ```ignore
+//! struct Queue {
+//!     ready: KeEvent,
+//!     items: KeSemaphore,
+//!     pending: u32,
+//! }
+//!
+//! let queue: Pin<Box<Queue>> = try_pin_init(pin_init!(Queue {
+//!     ready <- KeEvent::pin_init(EventType::Notification, false),
+//!     items <- KeSemaphore::pin_init(0, i32::MAX),
+//!     pending: 0,
+//! }))?;
+//! ```
+//!
+
+use core::{
+    alloc::Allocator,
+    convert::Infallible,
+    fmt::{Debug, Display},
+    marker::PhantomData,
+    pin::Pin,
+};
+
+use crate::{
+    boxed::Box,
+    kmalloc::{GlobalKernelAllocator, TaggedObject},
+    sync::arc::Arc,
+};
+
+///
+/// # Safety
+///
+/// When `__pinned_init` returns `Ok` the slot must hold a valid `T`,
+/// when it returns `Err` the slot must be left without anything to drop
+///
+pub unsafe trait PinInit<T, E = Infallible>: Sized {
+    ///
+    /// # Safety
+    ///
+    /// `slot` must be valid for writes and must not move until
+    /// the initialized value is dropped
+    ///
+    unsafe fn __pinned_init(self, slot: *mut T) -> Result<(), E>;
+}
+
+struct InitClosure<F, T, E>(F, PhantomData<fn(*mut T) -> E>);
+
+unsafe impl<F, T, E> PinInit<T, E> for InitClosure<F, T, E>
+where
+    F: FnOnce(*mut T) -> Result<(), E>,
+{
+    unsafe fn __pinned_init(self, slot: *mut T) -> Result<(), E> {
+        (self.0)(slot)
+    }
+}
+
+///
+/// # Safety
+///
+/// `f` must uphold the `PinInit` contract for the slot it receives
+///
+pub unsafe fn pin_init_from_closure<T, E, F>(f: F) -> impl PinInit<T, E>
+where
+    F: FnOnce(*mut T) -> Result<(), E>,
+{
+    InitClosure(f, PhantomData)
+}
+
+///
+/// Initializer that moves an already built value into the slot
+///
+pub fn init_value<T>(value: T) -> impl PinInit<T> {
+    unsafe {
+        pin_init_from_closure(move |slot: *mut T| {
+            slot.write(value);
+            Ok(())
+        })
+    }
+}
+
+///
+/// Drops an initialized field if a later field fails to initialize
+///
+#[doc(hidden)]
+pub struct DropGuard<T>(*mut T);
+
+impl<T> DropGuard<T> {
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to an initialized value that is owned by the guard
+    ///
+    #[doc(hidden)]
+    #[inline(always)]
+    pub unsafe fn new(ptr: *mut T) -> Self {
+        Self(ptr)
+    }
+}
+
+impl<T> Drop for DropGuard<T> {
+    fn drop(&mut self) {
+        unsafe { core::ptr::drop_in_place(self.0) };
+    }
+}
+
+pub fn try_pin_init_in<T, E, A>(
+    init: impl PinInit<T, E>,
+    allocator: A,
+) -> anyhow::Result<Pin<Box<T, A>>>
+where
+    A: Allocator + 'static,
+    E: Display + Debug + Send + Sync + 'static,
+{
+    let mut uninit = Box::try_new_uninit_in(allocator)
+        .map_err(|_| anyhow::Error::msg("Failed to allocate Box<T>"))?;
+
+    unsafe {
+        init.__pinned_init(uninit.as_mut_ptr())
+            .map_err(anyhow::Error::msg)?;
+
+        Ok(Box::into_pin(uninit.assume_init()))
+    }
+}
+
+pub fn try_pin_init<T, E>(init: impl PinInit<T, E>) -> anyhow::Result<Pin<Box<T>>>
+where
+    T: TaggedObject,
+    E: Display + Debug + Send + Sync + 'static,
+{
+    try_pin_init_in(init, GlobalKernelAllocator::new_for_tagged::<T>())
+}
+
+pub fn try_arc_init_in<T, E, A>(
+    init: impl PinInit<T, E>,
+    allocator: A,
+) -> anyhow::Result<Pin<Arc<T, A>>>
+where
+    A: Allocator + 'static,
+    E: Display + Debug + Send + Sync + 'static,
+{
+    let mut uninit = Arc::try_new_uninit_in(allocator)
+        .map_err(|_| anyhow::Error::msg("Failed to allocate ArcInner<T>"))?;
+
+    unsafe {
+        //The Arc was just created, nobody else can observe it
+        let slot = match Arc::get_mut(&mut uninit) {
+            Some(slot) => slot.as_mut_ptr(),
+            None => unreachable!(),
+        };
+        init.__pinned_init(slot).map_err(anyhow::Error::msg)?;
+
+        Ok(Pin::new_unchecked(uninit.assume_init()))
+    }
+}
+
+pub fn try_arc_init<T, E>(init: impl PinInit<T, E>) -> anyhow::Result<Pin<Arc<T>>>
+where
+    T: TaggedObject,
+    E: Display + Debug + Send + Sync + 'static,
+{
+    try_arc_init_in(init, GlobalKernelAllocator::new_for_tagged::<T>())
+}
+
+///
+/// Builds a `PinInit` for a struct out of initializers for its fields
+///
+/// `field <- init` runs a `PinInit` directly inside the field,
+/// `field: value` moves the value in. Every field must be listed.
+/// The error type defaults to `Infallible` and can be given after the braces:
+/// `pin_init!(Parent { resource <- KeResource::pin_init() }? NtStatusError)`,
+/// field errors are converted with `From` so it must implement `From<Infallible>`.
+/// If a field fails, the fields that were already initialized are dropped.
+///
+#[macro_export]
+macro_rules! pin_init {
+    ($t:path { $($fields:tt)* }) => {
+        $crate::pin_init!($t { $($fields)* }? ::core::convert::Infallible)
+    };
+    ($t:path { $($fields:tt)* }? $err:ty) => {{
+        let init = move |slot: *mut $t| -> ::core::result::Result<(), $err> {
+            $crate::__pin_init_fields!(slot, $t, [], $($fields)*);
+            Ok(())
+        };
+
+        unsafe { $crate::sys::pin_init::pin_init_from_closure::<$t, $err, _>(init) }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __pin_init_fields {
+    //The guards are macro locals so they never shadow a caller variable,
+    //each one is forgotten after the fields that follow it are initialized
+    ($slot:ident, $t:path, [$($done:ident)*], $field:ident <- $init:expr $(, $($rest:tt)*)?) => {
+        let init = $init;
+        let guard = unsafe {
+            let ptr = ::core::ptr::addr_of_mut!((*$slot).$field);
+            $crate::sys::pin_init::PinInit::__pinned_init(init, ptr)?;
+            $crate::sys::pin_init::DropGuard::new(ptr)
+        };
+        $crate::__pin_init_fields!($slot, $t, [$($done)* $field], $($($rest)*)?);
+        ::core::mem::forget(guard);
+    };
+    ($slot:ident, $t:path, [$($done:ident)*], $field:ident : $value:expr $(, $($rest:tt)*)?) => {
+        let value = $value;
+        let guard = unsafe {
+            let ptr = ::core::ptr::addr_of_mut!((*$slot).$field);
+            ptr.write(value);
+            $crate::sys::pin_init::DropGuard::new(ptr)
+        };
+        $crate::__pin_init_fields!($slot, $t, [$($done)* $field], $($($rest)*)?);
+        ::core::mem::forget(guard);
+    };
+    ($slot:ident, $t:path, [$($done:ident)*], ) => {
+        //Fails to compile if a field was not initialized
+        #[allow(unreachable_code, clippy::diverging_sub_expression)]
+        let _ = || -> $t {
+            $t {
+                $($done: unreachable!(),)*
+            }
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{init_value, try_pin_init};
+    use crate::NtStatusError;
+
+    extern crate std;
+
+    struct Parent {
+        first: u32,
+        second: u64,
+    }
+
+    impl crate::kmalloc::TaggedObject for Parent {}
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let parent = try_pin_init(crate::pin_init!(Parent {
+            first <- init_value(10),
+            second: 20,
+        }))?;
+
+        assert_eq!(parent.first, 10);
+        assert_eq!(parent.second, 20);
+
+        //A local named like an earlier field is not replaced by its guard
+        let first = 30u64;
+        let parent = try_pin_init(crate::pin_init!(Parent {
+            first: 40,
+            second: first,
+        }))?;
+        assert_eq!(parent.second, 30);
+
+        let failing = unsafe {
+            super::pin_init_from_closure(|_slot: *mut u32| Err(NtStatusError::Status(-1)))
+        };
+        let result = try_pin_init(crate::pin_init!(Parent {
+            first <- failing,
+            second: 20,
+        }? NtStatusError));
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
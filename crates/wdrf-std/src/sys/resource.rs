@@ -0,0 +1,82 @@
+use core::{cell::UnsafeCell, marker::PhantomPinned};
+
+use windows_sys::Wdk::{
+    Foundation::ERESOURCE,
+    System::SystemServices::{
+        ExAcquireResourceExclusiveLite, ExAcquireResourceSharedLite, ExDeleteResourceLite,
+        ExInitializeResourceLite, ExReleaseResourceLite,
+    },
+};
+
+use crate::{kmalloc::TaggedObject, NtResult, NtResultEx, NtStatusError};
+
+use super::pin_init::PinInit;
+
+///
+/// A raw executive resource, `sync::EResource` is the lock built on top
+///
+/// The resource is deleted when dropped. It is linked into the global
+/// resource list so it is not `Unpin`, it can only be built with `pin_init`.
+///
+#[repr(transparent)]
+pub struct KeResource(UnsafeCell<ERESOURCE>, PhantomPinned);
+
+impl TaggedObject for KeResource {
+    fn tag() -> crate::kmalloc::MemoryTag {
+        crate::kmalloc::MemoryTag::new_from_bytes(b"kres")
+    }
+}
+
+unsafe impl Send for KeResource {}
+unsafe impl Sync for KeResource {}
+
+impl KeResource {
+    pub fn pin_init() -> impl PinInit<Self, NtStatusError> {
+        unsafe {
+            super::pin_init::pin_init_from_closure(|slot: *mut Self| {
+                let status = ExInitializeResourceLite(slot.cast());
+                NtResult::from_status(status, || ())
+            })
+        }
+    }
+
+    ///
+    /// # Safety
+    ///
+    /// Normal kernel APCs must be disabled, usually with `KeEnterCriticalRegion`
+    ///
+    pub unsafe fn acquire_exclusive(&self, wait: bool) -> bool {
+        ExAcquireResourceExclusiveLite(self.0.get(), wait as _) != 0
+    }
+
+    ///
+    /// # Safety
+    ///
+    /// Same as `acquire_exclusive`
+    ///
+    pub unsafe fn acquire_shared(&self, wait: bool) -> bool {
+        ExAcquireResourceSharedLite(self.0.get(), wait as _) != 0
+    }
+
+    ///
+    /// # Safety
+    ///
+    /// The current thread must own the resource
+    ///
+    pub unsafe fn release(&self) {
+        ExReleaseResourceLite(self.0.get());
+    }
+
+    #[inline]
+    pub fn as_raw(&self) -> *mut ERESOURCE {
+        self.0.get()
+    }
+}
+
+impl Drop for KeResource {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = ExDeleteResourceLite(self.0.get());
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use core::num::NonZeroU32;
+use core::{marker::PhantomPinned, num::NonZeroU32};
 
 use windows_sys::Wdk::{
     Storage::FileSystem::IO_NO_INCREMENT,
@@ -9,10 +9,13 @@ use windows_sys::Wdk::{
 
 use crate::kmalloc::TaggedObject;
 
-use super::{WaitableKernelObject, WaitableObject};
+use super::{pin_init::PinInit, WaitableKernelObject, WaitableObject};
 
+///
+/// Not `Unpin`, a pinned semaphore can not be moved back out
+///
 #[repr(C)]
-pub struct KeSemaphore(KSEMAPHORE);
+pub struct KeSemaphore(KSEMAPHORE, PhantomPinned);
 
 impl TaggedObject for KeSemaphore {
     fn tag() -> crate::kmalloc::MemoryTag {
@@ -29,17 +32,37 @@ impl KeSemaphore {
     /// resulting in  a BugCheck
     ///
     pub unsafe fn new() -> Self {
-        Self(unsafe { core::mem::zeroed() })
+        Self(unsafe { core::mem::zeroed() }, PhantomPinned)
     }
 
-    pub fn init(&self, count: i32, limit: i32) {
+    pub fn pin_init(count: i32, limit: i32) -> impl PinInit<Self> {
+        unsafe {
+            super::pin_init::pin_init_from_closure(move |slot: *mut Self| {
+                KeInitializeSemaphore(slot.cast(), count as _, limit as _);
+                Ok(())
+            })
+        }
+    }
+
+    ///
+    /// # Safety
+    ///
+    /// The object must not be in use and must not move afterwards,
+    /// `pin_init` is the safe way to build one
+    ///
+    pub unsafe fn init(&self, count: i32, limit: i32) {
         unsafe {
             let ptr: *const KSEMAPHORE = &self.0;
             KeInitializeSemaphore(ptr as _, count as _, limit as _);
         }
     }
 
-    pub fn init_max(&self) {
+    ///
+    /// # Safety
+    ///
+    /// Same as `init`
+    ///
+    pub unsafe fn init_max(&self) {
         self.init(0, i32::MAX);
     }
 
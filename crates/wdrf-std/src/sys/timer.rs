@@ -0,0 +1,128 @@
+use core::{marker::PhantomPinned, time::Duration};
+
+use windows_sys::{
+    Wdk::System::SystemServices::{
+        KeCancelTimer, KeInitializeTimerEx, KeReadStateTimer, KeSetTimerEx, KTIMER,
+    },
+    Win32::System::Kernel::{NotificationTimer, SynchronizationTimer},
+};
+
+use crate::{kmalloc::TaggedObject, time::duration_to_ticks};
+
+use super::{pin_init::PinInit, WaitableKernelObject, WaitableObject};
+
+#[derive(Clone, Copy, Debug)]
+pub enum TimerType {
+    Notification,
+    Synchronization,
+}
+
+impl TimerType {
+    fn as_wdm_value(self) -> i32 {
+        match self {
+            TimerType::Notification => NotificationTimer,
+            TimerType::Synchronization => SynchronizationTimer,
+        }
+    }
+}
+
+///
+/// A kernel timer without a DPC, threads wait on it to expire
+///
+/// The timer is cancelled when dropped. Not `Unpin`, a pinned timer can
+/// not be moved back out.
+///
+#[repr(C)]
+pub struct KeTimer(KTIMER, PhantomPinned);
+
+impl TaggedObject for KeTimer {
+    fn tag() -> crate::kmalloc::MemoryTag {
+        crate::kmalloc::MemoryTag::new_from_bytes(b"ktmr")
+    }
+}
+
+unsafe impl Send for KeTimer {}
+
+impl KeTimer {
+    ///
+    ///# Safety
+    ///
+    /// Moving this object will invalidate internal pointers
+    /// resulting in  a BugCheck
+    ///
+    pub unsafe fn new() -> Self {
+        Self(unsafe { core::mem::zeroed() }, PhantomPinned)
+    }
+
+    pub fn pin_init(timer_type: TimerType) -> impl PinInit<Self> {
+        unsafe {
+            super::pin_init::pin_init_from_closure(move |slot: *mut Self| {
+                KeInitializeTimerEx(slot.cast(), timer_type.as_wdm_value());
+                Ok(())
+            })
+        }
+    }
+
+    ///
+    /// # Safety
+    ///
+    /// The object must not be in use and must not move afterwards,
+    /// `pin_init` is the safe way to build one
+    ///
+    pub unsafe fn init(&self, timer_type: TimerType) {
+        unsafe {
+            let ptr: *const KTIMER = &self.0;
+            KeInitializeTimerEx(ptr as _, timer_type.as_wdm_value());
+        }
+    }
+
+    ///
+    /// Starts the timer relative to now, returns true if it was already running
+    ///
+    /// The period has millisecond granularity and is clamped to
+    /// `1..=i32::MAX` milliseconds.
+    ///
+    pub fn set(&self, due: Duration, period: Option<Duration>) -> bool {
+        let due_time: i64 = -duration_to_ticks(due);
+        let period = period.map_or(0, |period| {
+            period.as_millis().clamp(1, i32::MAX as u128) as i32
+        });
+
+        unsafe {
+            let ptr: *const KTIMER = &self.0;
+            KeSetTimerEx(ptr as _, due_time, period, core::ptr::null()) != 0
+        }
+    }
+
+    ///
+    /// Returns true if the timer was running
+    ///
+    pub fn cancel(&self) -> bool {
+        unsafe {
+            let ptr: *const KTIMER = &self.0;
+            KeCancelTimer(ptr as _) != 0
+        }
+    }
+
+    pub fn read_state(&self) -> bool {
+        unsafe {
+            let ptr: *const KTIMER = &self.0;
+            KeReadStateTimer(ptr) != 0
+        }
+    }
+}
+
+impl Drop for KeTimer {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+unsafe impl WaitableObject for KeTimer {
+    fn kernel_object(&self) -> &WaitableKernelObject {
+        unsafe {
+            let ptr: *const KTIMER = &self.0;
+            &*ptr.cast()
+        }
+    }
+}
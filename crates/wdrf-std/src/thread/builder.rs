@@ -0,0 +1,203 @@
+use windows_sys::{
+    Wdk::System::{
+        SystemServices::{KeSetPriorityThread, KeSetSystemGroupAffinityThread, HIGH_PRIORITY},
+        Threading::ZwSetInformationThread,
+    },
+    Win32::{
+        Foundation::{STATUS_INVALID_PARAMETER, STATUS_NO_MEMORY, UNICODE_STRING},
+        System::SystemInformation::GROUP_AFFINITY,
+    },
+};
+
+use crate::{
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    vec::Vec,
+    NtResult, NtStatusError,
+};
+
+use super::{spawn_with_config, this_thread, JoinHandle, Scope, ScopedJoinHandle};
+
+//Not exposed by windows-sys
+const THREAD_NAME_INFORMATION: i32 = 38;
+const NT_CURRENT_THREAD: isize = -2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GroupAffinity {
+    pub group: u16,
+    pub mask: usize,
+}
+
+pub(super) struct ThreadConfig {
+    priority: Option<i32>,
+    affinity: Option<GroupAffinity>,
+    name: Option<Vec<u16>>,
+}
+
+impl ThreadConfig {
+    pub(super) fn new() -> Self {
+        Self {
+            priority: None,
+            affinity: None,
+            name: None,
+        }
+    }
+
+    ///
+    /// Runs on the new thread before the user function
+    ///
+    pub(super) unsafe fn apply(&self) {
+        if let Some(priority) = self.priority {
+            KeSetPriorityThread(this_thread::current_thread() as _, priority);
+        }
+
+        if let Some(affinity) = self.affinity {
+            let mut raw: GROUP_AFFINITY = core::mem::zeroed();
+            raw.Group = affinity.group;
+            raw.Mask = affinity.mask;
+
+            //The system affinity stays for the lifetime of the thread
+            let mut previous: GROUP_AFFINITY = core::mem::zeroed();
+            KeSetSystemGroupAffinityThread(&raw, &mut previous);
+        }
+
+        if let Some(ref name) = self.name {
+            let bytes = (name.len() * core::mem::size_of::<u16>()) as u16;
+            let name = UNICODE_STRING {
+                Length: bytes,
+                MaximumLength: bytes,
+                Buffer: name.as_ptr() as _,
+            };
+
+            //The name is only a debugging aid, failing to set it is not fatal
+            let _ = ZwSetInformationThread(
+                NT_CURRENT_THREAD as _,
+                THREAD_NAME_INFORMATION,
+                &name as *const UNICODE_STRING as _,
+                core::mem::size_of::<UNICODE_STRING>() as _,
+            );
+        }
+    }
+}
+
+///
+/// Configures a system thread before spawning it
+///
+/// ```ignore
+/// let handle = thread::Builder::new()
+///     .name("scanner")
+///     .priority(LOW_REALTIME_PRIORITY as _)
+///     .spawn(move || scan())?;
+/// ```
+///
+pub struct Builder<'a> {
+    priority: Option<i32>,
+    affinity: Option<GroupAffinity>,
+    name: Option<&'a str>,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new() -> Self {
+        Self {
+            priority: None,
+            affinity: None,
+            name: None,
+        }
+    }
+
+    ///
+    /// Debug name visible in the debugger and in ETW traces
+    ///
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    ///
+    /// Kernel priority set with `KeSetPriorityThread`, between 0 and `HIGH_PRIORITY`
+    ///
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn affinity(mut self, affinity: GroupAffinity) -> Self {
+        self.affinity = Some(affinity);
+        self
+    }
+
+    pub fn spawn<T, F>(self, f: F) -> NtResult<JoinHandle<T>>
+    where
+        F: FnOnce() -> T,
+        F: 'static + Send,
+        T: 'static + Send,
+    {
+        spawn_with_config(f, self.into_config()?)
+    }
+
+    ///
+    /// Spawns the thread in `scope`, like `Scope::spawn`
+    ///
+    pub fn spawn_scoped<'scope, 'env, T, F>(
+        self,
+        scope: &'scope Scope<'scope, 'env>,
+        f: F,
+    ) -> NtResult<ScopedJoinHandle<'scope, T>>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        scope.spawn_with_config(f, self.into_config()?)
+    }
+
+    fn into_config(self) -> NtResult<ThreadConfig> {
+        if let Some(priority) = self.priority {
+            if !(0..=HIGH_PRIORITY as i32).contains(&priority) {
+                return Err(NtStatusError::Status(STATUS_INVALID_PARAMETER));
+            }
+        }
+
+        if let Some(affinity) = self.affinity {
+            if affinity.mask == 0 {
+                return Err(NtStatusError::Status(STATUS_INVALID_PARAMETER));
+            }
+        }
+
+        let name = match self.name {
+            Some(name) => Some(Self::encode_name(name)?),
+            None => None,
+        };
+
+        Ok(ThreadConfig {
+            priority: self.priority,
+            affinity: self.affinity,
+            name,
+        })
+    }
+
+    fn encode_name(name: &str) -> NtResult<Vec<u16>> {
+        let mut encoded = Vec::new_in(GlobalKernelAllocator::new(
+            MemoryTag::new_from_bytes(b"thnm"),
+            PoolFlags::POOL_FLAG_NON_PAGED,
+        ));
+
+        //UNICODE_STRING lengths are in bytes and limited to u16
+        let len = name.encode_utf16().count();
+        if len > (u16::MAX as usize / 2) {
+            return Err(NtStatusError::Status(STATUS_INVALID_PARAMETER));
+        }
+
+        encoded
+            .try_reserve_exact(len)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        encoded.extend(name.encode_utf16());
+
+        Ok(encoded)
+    }
+}
+
+impl<'a> Default for Builder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
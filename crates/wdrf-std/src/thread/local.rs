@@ -0,0 +1,337 @@
+//!
+//! Per thread storage keyed by the current ETHREAD
+//!
+//! The kernel has no TLS slots for drivers. Values live in a map owned by
+//! the `ThreadLocal` and are dropped from a thread exit notification that
+//! runs on the exiting thread, so no stale entry can outlive its thread
+//! and be picked up by a new ETHREAD allocated at the same address.
+//!
+//! A single notify routine is shared by every `ThreadLocal` in the driver,
+//! it is registered with the first one and removed with the last one.
+//!
+
+use core::{cell::Cell, marker::PhantomData};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::{APC_LEVEL, PASSIVE_LEVEL};
+use windows_sys::{
+    Wdk::System::SystemServices::{
+        PsRemoveCreateThreadNotifyRoutine, PsSetCreateThreadNotifyRoutine,
+    },
+    Win32::Foundation::{BOOLEAN, HANDLE, STATUS_NO_MEMORY},
+};
+
+use crate::{
+    boxed::{Box, BoxExt},
+    constants::PoolFlags,
+    hashbrown::{Entry, HashMap, HashMapExt},
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+    nt_success,
+    sync::{
+        arc::{Arc, ArcExt},
+        ExSpinMutex, PushLock,
+    },
+    traits::DispatchSafe,
+    NtResult, NtStatusError,
+};
+
+use super::this_thread;
+
+trait ThreadExitHook: Send + Sync {
+    fn on_thread_exit(&self, thread: usize);
+}
+
+struct Registry {
+    //Taken by create and drop only, serializes the notify routine registration
+    registration: PushLock<()>,
+    //The notify routine takes it shared
+    hooks: PushLock<crate::vec::Vec<Arc<dyn ThreadExitHook>>>,
+}
+
+static REGISTRY: Registry = Registry {
+    registration: PushLock::new(()),
+    hooks: PushLock::new(crate::vec::Vec::new_in(GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"tlsr"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    ))),
+};
+
+fn add_hook(hook: Arc<dyn ThreadExitHook>) -> NtResult<()> {
+    let _registration = REGISTRY.registration.write_raw();
+
+    let first = {
+        let mut hooks = REGISTRY.hooks.write_raw();
+        hooks
+            .try_reserve(1)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        hooks.is_empty()
+    };
+
+    if first {
+        let status = unsafe { PsSetCreateThreadNotifyRoutine(Some(thread_notify_routine)) };
+        if !nt_success(status) {
+            return Err(NtStatusError::Status(status));
+        }
+    }
+
+    REGISTRY.hooks.write_raw().push(hook);
+
+    Ok(())
+}
+
+fn remove_hook(hook: *const dyn ThreadExitHook) {
+    let _registration = REGISTRY.registration.write_raw();
+
+    let (removed, now_empty) = {
+        let mut hooks = REGISTRY.hooks.write_raw();
+        let removed = hooks
+            .iter()
+            .position(|h| core::ptr::addr_eq(Arc::as_ptr(h), hook))
+            .map(|index| hooks.swap_remove(index));
+
+        (removed, hooks.is_empty())
+    };
+
+    if removed.is_some() && now_empty {
+        //Waits for routines that are still running
+        let _ = unsafe { PsRemoveCreateThreadNotifyRoutine(Some(thread_notify_routine)) };
+    }
+
+    //The values are dropped outside of the locks
+    drop(removed);
+}
+
+unsafe extern "system" fn thread_notify_routine(
+    _process_id: HANDLE,
+    _thread_id: HANDLE,
+    create: BOOLEAN,
+) {
+    if create != 0 {
+        return;
+    }
+
+    //Runs on the exiting thread
+    let thread = this_thread::current_thread() as usize;
+
+    let hooks = REGISTRY.hooks.read_raw();
+    for hook in hooks.iter() {
+        hook.on_thread_exit(thread);
+    }
+}
+
+struct Entries<T> {
+    map: HashMap<usize, Box<T>>,
+}
+
+//Only touched under the spin lock, the values are never accessed there
+unsafe impl<T> DispatchSafe for Entries<T> {}
+
+struct LocalInner<T, F> {
+    entries: ExSpinMutex<Entries<T>>,
+    init: F,
+}
+
+//A value is only dereferenced by the thread that owns it
+unsafe impl<T: Send, F: Send> Send for LocalInner<T, F> {}
+unsafe impl<T: Send, F: Sync> Sync for LocalInner<T, F> {}
+
+impl<T, F> TaggedObject for LocalInner<T, F> {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"tlsi")
+    }
+}
+
+impl<T, F> ThreadExitHook for LocalInner<T, F>
+where
+    T: Send,
+    F: Send + Sync,
+{
+    fn on_thread_exit(&self, thread: usize) {
+        let value = self.entries.write_raw().map.remove(&thread);
+        drop(value);
+    }
+}
+
+fn value_allocator() -> GlobalKernelAllocator {
+    GlobalKernelAllocator::new(
+        MemoryTag::new_from_bytes(b"tlsv"),
+        PoolFlags::POOL_FLAG_NON_PAGED,
+    )
+}
+
+///
+/// A value per kernel thread, created on first use by `init`
+///
+/// Values are only reachable from the thread that owns them, so `T` needs
+/// to be `Send` but not `Sync`. A value is dropped when its thread exits or
+/// when the `ThreadLocal` is dropped.
+///
+/// Access is keyed by `PsGetCurrentThread`, it must happen below
+/// DISPATCH_LEVEL where the current thread is the one doing the work.
+///
+pub struct ThreadLocal<T, F = fn() -> T>
+where
+    T: Send + 'static,
+    F: Fn() -> T + Send + Sync + 'static,
+{
+    inner: Arc<LocalInner<T, F>>,
+}
+
+impl<T, F> ThreadLocal<T, F>
+where
+    T: Send + 'static,
+    F: Fn() -> T + Send + Sync + 'static,
+{
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn try_create(init: F) -> NtResult<Self> {
+        let map = HashMap::create_in(GlobalKernelAllocator::new(
+            MemoryTag::new_from_bytes(b"tlsm"),
+            PoolFlags::POOL_FLAG_NON_PAGED,
+        ));
+
+        let inner = Arc::try_create(LocalInner {
+            entries: ExSpinMutex::new(Entries { map }),
+            init,
+        })
+        .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        add_hook(inner.clone())?;
+
+        Ok(Self { inner })
+    }
+
+    ///
+    /// Runs `f` with the value of the current thread, creating it if needed
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = APC_LEVEL))]
+    pub fn with<R, G>(&self, f: G) -> anyhow::Result<R>
+    where
+        G: FnOnce(&T) -> R,
+    {
+        Ok(f(unsafe { &*self.get_or_create()? }))
+    }
+
+    ///
+    /// Runs `f` only if the current thread already has a value
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = APC_LEVEL))]
+    pub fn try_with<R, G>(&self, f: G) -> Option<R>
+    where
+        G: FnOnce(&T) -> R,
+    {
+        self.get().map(|value| f(unsafe { &*value }))
+    }
+
+    fn get(&self) -> Option<*const T> {
+        let thread = this_thread::current_thread() as usize;
+
+        self.inner
+            .entries
+            .read_raw()
+            .map
+            .get(&thread)
+            .map(|value| &**value as *const T)
+    }
+
+    fn get_or_create(&self) -> anyhow::Result<*const T> {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+
+        let thread = this_thread::current_thread() as usize;
+
+        //Built outside of the lock, only this thread can insert its own key
+        let value = Box::try_create_in((self.inner.init)(), value_allocator())?;
+        let ptr = &*value as *const T;
+
+        let unused = {
+            let mut entries = self.inner.entries.write_raw();
+            entries
+                .map
+                .try_reserve(1)
+                .map_err(|_| anyhow::Error::msg("Failed to reserve thread local entry"))?;
+
+            match entries.map.entry(thread) {
+                //`init` used this `ThreadLocal` and already created the value,
+                //references to it may be alive so it is the one kept
+                Entry::Occupied(entry) => Some((&**entry.get() as *const T, value)),
+                Entry::Vacant(entry) => {
+                    entry.insert(value);
+                    None
+                }
+            }
+        };
+
+        //Dropped outside of the spin lock
+        match unused {
+            Some((existing, _value)) => Ok(existing),
+            None => Ok(ptr),
+        }
+    }
+}
+
+impl<T, F> Drop for ThreadLocal<T, F>
+where
+    T: Send + 'static,
+    F: Fn() -> T + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        let hook: *const dyn ThreadExitHook = Arc::as_ptr(&self.inner);
+        remove_hook(hook);
+    }
+}
+
+///
+/// Per thread flag for detecting recursion into our own callbacks
+///
+/// ```ignore
+/// let flag = ReentrancyFlag::try_create(Cell::default)?;
+///
+/// if let Some(_guard) = flag.enter()? {
+///     //Our own I/O issued here will see `enter` return None
+/// }
+/// ```
+///
+pub type ReentrancyFlag = ThreadLocal<Cell<bool>>;
+
+impl<F> ThreadLocal<Cell<bool>, F>
+where
+    F: Fn() -> Cell<bool> + Send + Sync + 'static,
+{
+    ///
+    /// Marks the current thread as inside, `None` if it already was
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = APC_LEVEL))]
+    pub fn enter(&self) -> anyhow::Result<Option<ReentrancyGuard<'_>>> {
+        let flag = unsafe { &*self.get_or_create()? };
+        if flag.replace(true) {
+            Ok(None)
+        } else {
+            Ok(Some(ReentrancyGuard {
+                flag,
+                _not_send: PhantomData,
+            }))
+        }
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = APC_LEVEL))]
+    pub fn is_entered(&self) -> bool {
+        self.try_with(Cell::get).unwrap_or(false)
+    }
+}
+
+///
+/// Clears the reentrancy flag of the current thread on drop
+///
+pub struct ReentrancyGuard<'a> {
+    flag: &'a Cell<bool>,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl Drop for ReentrancyGuard<'_> {
+    fn drop(&mut self) {
+        self.flag.set(false);
+    }
+}
@@ -1,6 +1,15 @@
+mod builder;
+mod local;
+mod scoped;
 pub mod this_thread;
 
-use core::cell::UnsafeCell;
+pub use builder::{Builder, GroupAffinity};
+pub use local::{ReentrancyFlag, ReentrancyGuard, ThreadLocal};
+pub use scoped::{scope, Scope, ScopedJoinHandle};
+
+use core::{cell::UnsafeCell, time::Duration};
+
+use builder::ThreadConfig;
 
 use windows_sys::{
     Wdk::System::SystemServices::PsCreateSystemThread,
@@ -20,7 +29,7 @@ use crate::{
     object::{attribute::ObjectAttributes, ArcKernelObj},
     structs::PKTHREAD,
     sync::arc::{Arc, ArcExt},
-    sys::{WaitableKernelObject, WaitableObject},
+    sys::{wait_object, WaitResponse, WaitableKernelObject, WaitableObject},
     NtResult, NtStatusError,
 };
 
@@ -28,6 +37,7 @@ struct InnerPacket<T> {
     thread_obj: Option<ArcKernelObj<PKTHREAD>>,
     result: Option<T>,
     function: Option<Box<dyn FnOnce() -> T>>,
+    config: ThreadConfig,
 }
 
 struct Packet<T> {
@@ -38,7 +48,7 @@ impl<T> Packet<T>
 where
     T: 'static + Send,
 {
-    pub fn new<F>(fnc: F) -> anyhow::Result<Self>
+    pub fn new<F>(fnc: F, config: ThreadConfig) -> anyhow::Result<Self>
     where
         F: FnOnce() -> T,
         F: 'static + Send,
@@ -55,6 +65,7 @@ where
             thread_obj: None,
             result: None,
             function: Some(fnc),
+            config,
         };
         Ok(Self {
             inner: UnsafeCell::new(inner),
@@ -71,16 +82,16 @@ where
     }
 }
 
+///
+/// Owned permission to join a thread
+///
+/// Dropping the handle detaches the thread without waiting for it.
+/// A detached thread must still finish before the driver unloads.
+///
 pub struct JoinHandle<T> {
     packet: Arc<Packet<T>>,
 }
 
-impl<T> Drop for JoinHandle<T> {
-    fn drop(&mut self) {
-        let _ = self.wait();
-    }
-}
-
 unsafe impl<T> Send for JoinHandle<T> {}
 unsafe impl<T> Sync for JoinHandle<T> {}
 
@@ -97,15 +108,42 @@ unsafe impl<T> WaitableObject for JoinHandle<T> {
 
 impl<T> JoinHandle<T> {
     pub fn join(self) -> T {
-        self.wait();
+        wait_object(&self, None);
 
-        unsafe {
-            let packet = &mut *self.packet.inner.get();
-            packet.result.take().unwrap()
+        unsafe { self.take_result() }
+    }
+
+    ///
+    /// Gives the handle back if the thread did not finish in time
+    ///
+    pub fn join_timeout(self, timeout: Duration) -> Result<T, Self> {
+        match wait_object(&self, Some(timeout)) {
+            WaitResponse::Success => Ok(unsafe { self.take_result() }),
+            _ => Err(self),
         }
     }
 
-    pub fn detach(self) {}
+    pub fn is_finished(&self) -> bool {
+        self.wait_status() == WaitResponse::Success
+    }
+
+    ///
+    /// Lets the thread run on its own, same as dropping the handle
+    ///
+    #[inline]
+    pub fn detach(self) {
+        drop(self);
+    }
+
+    ///
+    /// # Safety
+    ///
+    /// The thread must have terminated
+    ///
+    unsafe fn take_result(&self) -> T {
+        let packet = &mut *self.packet.inner.get();
+        packet.result.take().unwrap()
+    }
 
     pub fn thread_object(&self) -> &ArcKernelObj<PKTHREAD> {
         unsafe {
@@ -121,7 +159,16 @@ where
     F: 'static + Send,
     T: 'static + Send,
 {
-    let p = Packet::new(f).map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+    spawn_with_config(f, ThreadConfig::new())
+}
+
+fn spawn_with_config<T, F>(f: F, config: ThreadConfig) -> NtResult<JoinHandle<T>>
+where
+    F: FnOnce() -> T,
+    F: 'static + Send,
+    T: 'static + Send,
+{
+    let p = Packet::new(f, config).map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
     let packet = Arc::try_create(p).map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
 
     unsafe {
@@ -161,6 +208,8 @@ unsafe extern "system" fn thread_main<T: 'static + Send>(context: *mut core::ffi
     let packet = Arc::from_raw_in(leaked, GlobalKernelAllocator::new_for_tagged::<Packet<T>>());
 
     let packet = &mut *packet.inner.get();
+    packet.config.apply();
+
     let fnc = packet.function.take().unwrap();
 
     let result = (fnc)();
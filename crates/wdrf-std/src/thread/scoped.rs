@@ -0,0 +1,248 @@
+use core::{cell::UnsafeCell, marker::PhantomData};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::PASSIVE_LEVEL;
+use windows_sys::Win32::Foundation::STATUS_NO_MEMORY;
+
+use crate::{
+    boxed::{Box, BoxExt},
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+    object::ArcKernelObj,
+    structs::PKTHREAD,
+    sync::{
+        arc::{Arc, ArcExt},
+        ExSpinMutex,
+    },
+    sys::wait_object,
+    traits::DispatchSafe,
+    vec::Vec,
+    NtResult, NtStatusError,
+};
+
+use super::{builder::ThreadConfig, spawn_with_config, JoinHandle};
+
+struct ScopeThreads {
+    threads: Vec<ArcKernelObj<PKTHREAD>>,
+    //Slots promised to spawns that did not create their thread yet
+    reserved: usize,
+}
+
+unsafe impl DispatchSafe for ScopeThreads {}
+
+struct ScopeData {
+    //Every thread spawned in the scope, joined when the scope ends
+    threads: ExSpinMutex<ScopeThreads>,
+}
+
+unsafe impl Send for ScopeData {}
+unsafe impl Sync for ScopeData {}
+
+impl TaggedObject for ScopeData {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"thsc")
+    }
+}
+
+impl ScopeData {
+    ///
+    /// Makes room for one more thread so `push` can not fail
+    /// once the thread is running
+    ///
+    fn try_reserve(&self) -> NtResult<()> {
+        let mut threads = self.threads.write_raw();
+        let reserved = threads.reserved + 1;
+        threads
+            .threads
+            .try_reserve(reserved)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+        threads.reserved = reserved;
+
+        Ok(())
+    }
+
+    fn push(&self, thread: ArcKernelObj<PKTHREAD>) {
+        let mut threads = self.threads.write_raw();
+        threads.reserved -= 1;
+        threads.threads.push(thread);
+    }
+
+    fn unreserve(&self) {
+        self.threads.write_raw().reserved -= 1;
+    }
+
+    ///
+    /// Waits for the thread objects, a thread has left the driver code
+    /// once its object is signaled
+    ///
+    fn join_all(&self) {
+        //Scoped threads can spawn more threads into the scope,
+        //they are pushed before the spawning thread can exit
+        loop {
+            let thread = self.threads.write_raw().threads.pop();
+            match thread {
+                Some(thread) => {
+                    let _ = wait_object(&thread, None);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+struct ScopedPacket<T> {
+    result: UnsafeCell<Option<T>>,
+}
+
+unsafe impl<T: Send> Send for ScopedPacket<T> {}
+unsafe impl<T: Send> Sync for ScopedPacket<T> {}
+
+impl<T> TaggedObject for ScopedPacket<T> {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"thsp")
+    }
+}
+
+///
+/// Threads spawned in a scope can borrow from outside of it
+///
+/// Created by `scope`, see its documentation.
+///
+pub struct Scope<'scope, 'env: 'scope> {
+    data: Arc<ScopeData>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+///
+/// Owned permission to join a scoped thread
+///
+/// Threads that are not joined explicitly are joined when the scope ends.
+///
+pub struct ScopedJoinHandle<'scope, T> {
+    handle: JoinHandle<()>,
+    packet: Arc<ScopedPacket<T>>,
+    _scope: PhantomData<&'scope ()>,
+}
+
+///
+/// Runs `f` with a `Scope` that spawns threads allowed to borrow local data
+///
+/// Every thread spawned in the scope is joined before `scope` returns,
+/// that is its thread object is signaled and it no longer runs driver code.
+/// The value returned by `f` is handed back once they all finished.
+///
+/// ```ignore
+/// let mut hits = [0usize; 2];
+/// let (left, right) = buffers.split_at(buffers.len() / 2);
+///
+/// thread::scope(|s| {
+///     let (l, r) = hits.split_at_mut(1);
+///     s.spawn(|| l[0] = scan(left))?;
+///     s.spawn(|| r[0] = scan(right))?;
+///     Ok::<_, NtStatusError>(())
+/// })??;
+/// ```
+///
+#[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+pub fn scope<'env, F, T>(f: F) -> NtResult<T>
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+{
+    let data = Arc::try_create(ScopeData {
+        threads: ExSpinMutex::new(ScopeThreads {
+            threads: Vec::new_in(GlobalKernelAllocator::new(
+                MemoryTag::new_from_bytes(b"thst"),
+                PoolFlags::POOL_FLAG_NON_PAGED,
+            )),
+            reserved: 0,
+        }),
+    })
+    .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+    let scope = Scope {
+        data,
+        scope: PhantomData,
+        env: PhantomData,
+    };
+
+    let result = f(&scope);
+    scope.data.join_all();
+
+    Ok(result)
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    pub fn spawn<F, T>(&'scope self, f: F) -> NtResult<ScopedJoinHandle<'scope, T>>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        self.spawn_with_config(f, ThreadConfig::new())
+    }
+
+    pub(super) fn spawn_with_config<F, T>(
+        &'scope self,
+        f: F,
+        config: ThreadConfig,
+    ) -> NtResult<ScopedJoinHandle<'scope, T>>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let packet = Arc::try_create(ScopedPacket {
+            result: UnsafeCell::new(None),
+        })
+        .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        let their_packet = packet.clone();
+        let main = move || {
+            let result = f();
+            unsafe { *their_packet.result.get() = Some(result) };
+        };
+
+        let main: Box<dyn FnOnce() + Send + 'scope> = Box::try_create_in(
+            main,
+            GlobalKernelAllocator::new(
+                MemoryTag::new_from_bytes(b"thsf"),
+                PoolFlags::POOL_FLAG_NON_PAGED,
+            ),
+        )
+        .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        //The scope joins the thread so nothing borrowed outlives it
+        let main: Box<dyn FnOnce() + Send + 'static> = unsafe { core::mem::transmute(main) };
+
+        self.data.try_reserve()?;
+        let handle = match spawn_with_config(main, config) {
+            Ok(handle) => handle,
+            Err(e) => {
+                self.data.unreserve();
+                return Err(e);
+            }
+        };
+        self.data.push(handle.thread_object().clone());
+
+        Ok(ScopedJoinHandle {
+            handle,
+            packet,
+            _scope: PhantomData,
+        })
+    }
+}
+
+impl<'scope, T> ScopedJoinHandle<'scope, T> {
+    pub fn join(self) -> T {
+        self.handle.join();
+
+        match unsafe { (*self.packet.result.get()).take() } {
+            Some(result) => result,
+            None => unreachable!(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
@@ -1,9 +1,66 @@
-use windows_sys::Wdk::System::SystemServices::{KeDelayExecutionThread, KernelMode};
+use windows_sys::{
+    Wdk::System::SystemServices::{
+        KeDelayExecutionThread, KeGetCurrentProcessorNumberEx, KernelMode, PsGetCurrentThreadId,
+    },
+    Win32::System::Kernel::PROCESSOR_NUMBER,
+};
 
-use crate::time::Timeout;
+use crate::{structs::PETHREAD, time::Timeout};
+
+#[link(name = "ntoskrnl")]
+extern "system" {
+    fn PsGetCurrentThread() -> PETHREAD;
+}
 
 pub fn delay_execution(timeout: Timeout) {
     unsafe {
         let _ = KeDelayExecutionThread(KernelMode as _, false as _, timeout.as_ptr());
     }
 }
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ProcessorNumber {
+    pub group: u16,
+    pub number: u8,
+    ///
+    /// System wide index, same value as `KeGetCurrentProcessorNumberEx` returns
+    ///
+    pub index: u32,
+}
+
+#[inline]
+pub fn id() -> usize {
+    unsafe { PsGetCurrentThreadId() as usize }
+}
+
+#[inline]
+pub fn current_thread() -> PETHREAD {
+    unsafe { PsGetCurrentThread() }
+}
+
+///
+/// Gives up the rest of the time slice to ready threads of the same priority
+///
+pub fn yield_now() {
+    //A zero relative timeout only yields the processor
+    let interval: i64 = 0;
+    unsafe {
+        let _ = KeDelayExecutionThread(KernelMode as _, false as _, &interval);
+    }
+}
+
+///
+/// The thread can migrate right after this returns unless the IRQL is DISPATCH_LEVEL or higher
+///
+pub fn current_processor() -> ProcessorNumber {
+    unsafe {
+        let mut number: PROCESSOR_NUMBER = core::mem::zeroed();
+        let index = KeGetCurrentProcessorNumberEx(&mut number);
+
+        ProcessorNumber {
+            group: number.Group,
+            number: number.Number,
+            index,
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//!
+//! Conversion between 100ns ticks since 1601-01-01 and calendar fields
+//!
+//! Same results as `RtlTimeToTimeFields` and `RtlTimeFieldsToTime`
+//! without calling into the kernel, so it can be used at any IRQL.
+//!
+
+use core::fmt;
+
+pub const TICKS_PER_MILLISECOND: u64 = 10_000;
+pub const TICKS_PER_SECOND: u64 = 1000 * TICKS_PER_MILLISECOND;
+pub const TICKS_PER_DAY: u64 = 86_400 * TICKS_PER_SECOND;
+
+//Days between 1601-01-01 and 1970-01-01
+const DAYS_TO_UNIX_EPOCH: i64 = 134_774;
+
+const MAX_YEAR: u16 = 30827;
+
+///
+/// Broken down UTC time, the same fields as `TIME_FIELDS`
+///
+/// `weekday` is 0 for Sunday, it is ignored when converting back to ticks.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeFields {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub milliseconds: u16,
+    pub weekday: u8,
+}
+
+impl TimeFields {
+    pub fn from_ticks(ticks: u64) -> Self {
+        let days = (ticks / TICKS_PER_DAY) as i64;
+        let mut rest = ticks % TICKS_PER_DAY;
+
+        let hour = rest / (3600 * TICKS_PER_SECOND);
+        rest %= 3600 * TICKS_PER_SECOND;
+        let minute = rest / (60 * TICKS_PER_SECOND);
+        rest %= 60 * TICKS_PER_SECOND;
+        let second = rest / TICKS_PER_SECOND;
+        rest %= TICKS_PER_SECOND;
+
+        let (year, month, day) = civil_from_days(days - DAYS_TO_UNIX_EPOCH);
+
+        Self {
+            year: year as u16,
+            month,
+            day,
+            hour: hour as u8,
+            minute: minute as u8,
+            second: second as u8,
+            milliseconds: (rest / TICKS_PER_MILLISECOND) as u16,
+            //1601-01-01 was a Monday
+            weekday: ((days + 1) % 7) as u8,
+        }
+    }
+
+    ///
+    /// Returns `None` if a field is out of range, like `RtlTimeFieldsToTime`
+    ///
+    pub fn to_ticks(&self) -> Option<u64> {
+        if !(1601..=MAX_YEAR).contains(&self.year)
+            || !(1..=12).contains(&self.month)
+            || self.day == 0
+            || self.day > days_in_month(self.year, self.month)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 59
+            || self.milliseconds > 999
+        {
+            return None;
+        }
+
+        let days = days_from_civil(self.year as i64, self.month, self.day) + DAYS_TO_UNIX_EPOCH;
+        let seconds = self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64;
+
+        Some(
+            days as u64 * TICKS_PER_DAY
+                + seconds * TICKS_PER_SECOND
+                + self.milliseconds as u64 * TICKS_PER_MILLISECOND,
+        )
+    }
+}
+
+///
+/// ISO-8601 in UTC with millisecond precision, `2024-03-05T08:15:30.250Z`
+///
+impl fmt::Display for TimeFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.milliseconds
+        )
+    }
+}
+
+pub fn is_leap_year(year: u16) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+//Howard Hinnant's civil calendar algorithms, days are relative to 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimeFields, TICKS_PER_DAY, TICKS_PER_MILLISECOND};
+
+    extern crate std;
+    use std::string::ToString;
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let origin = TimeFields::from_ticks(0);
+        assert_eq!(origin.to_string(), "1601-01-01T00:00:00.000Z");
+        assert_eq!(origin.weekday, 1);
+
+        let unix = TimeFields::from_ticks(116_444_736_000_000_000);
+        assert_eq!(unix.to_string(), "1970-01-01T00:00:00.000Z");
+        assert_eq!(unix.weekday, 4);
+
+        let leap = TimeFields {
+            year: 2024,
+            month: 2,
+            day: 29,
+            hour: 23,
+            minute: 59,
+            second: 59,
+            milliseconds: 999,
+            weekday: 0,
+        };
+        let ticks = leap.to_ticks().unwrap();
+        assert_eq!(
+            TimeFields::from_ticks(ticks),
+            TimeFields { weekday: 4, ..leap }
+        );
+        assert_eq!(
+            TimeFields::from_ticks(ticks + TICKS_PER_MILLISECOND).to_string(),
+            "2024-03-01T00:00:00.000Z"
+        );
+
+        assert!(TimeFields { year: 2023, ..leap }.to_ticks().is_none());
+        assert!(TimeFields { year: 2100, ..leap }.to_ticks().is_none());
+        assert!(TimeFields { year: 2000, ..leap }.to_ticks().is_some());
+        assert!(TimeFields { year: 1600, ..leap }.to_ticks().is_none());
+        assert!(TimeFields { month: 13, ..leap }.to_ticks().is_none());
+
+        //Every day boundary round trips through 2100, a non leap century
+        let mut ticks = 0;
+        while ticks < 500 * 365 * TICKS_PER_DAY {
+            let fields = TimeFields::from_ticks(ticks);
+            assert_eq!(fields.to_ticks(), Some(ticks));
+            ticks += TICKS_PER_DAY + 1234 * TICKS_PER_MILLISECOND;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use core::{
+    cmp::Ordering,
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+use windows_sys::Wdk::System::SystemServices::KeQueryInterruptTimePrecise;
+
+use super::{duration_to_ticks, ticks_to_duration};
+
+///
+/// Monotonic time in 100ns ticks since boot, includes the time spent in sleep
+///
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[repr(transparent)]
+pub struct Instant {
+    ticks: u64,
+}
+
+impl Instant {
+    pub fn now() -> Self {
+        let mut qpc: u64 = 0;
+        let ticks = unsafe { KeQueryInterruptTimePrecise(&mut qpc) };
+
+        Self { ticks }
+    }
+
+    #[inline]
+    pub const fn from_raw(ticks: u64) -> Self {
+        Self { ticks }
+    }
+
+    #[inline]
+    pub fn raw_ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    ///
+    /// Zero if `earlier` is later than `self`
+    ///
+    pub fn saturating_duration_since(&self, earlier: Instant) -> Duration {
+        ticks_to_duration(self.ticks.saturating_sub(earlier.ticks))
+    }
+
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        self.ticks.checked_sub(earlier.ticks).map(ticks_to_duration)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Self::now().saturating_duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.ticks
+            .checked_add(duration_to_ticks(duration) as u64)
+            .map(Self::from_raw)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        self.ticks
+            .checked_sub(duration_to_ticks(duration) as u64)
+            .map(Self::from_raw)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        self.checked_add(rhs)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, rhs: Duration) -> Instant {
+        self.checked_sub(rhs)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.saturating_duration_since(rhs)
+    }
+}
+
+///
+/// Point on the monotonic clock an operation must finish by
+///
+/// Unlike an absolute `Timeout` it is not affected by wall clock changes,
+/// it is turned into a relative timeout for the time that is left.
+///
+/// ```ignore
+/// let deadline = Deadline::after(Duration::from_secs(5));
+/// while !try_work() {
+///     if deadline.has_expired() {
+///         return Err(STATUS_IO_TIMEOUT);
+///     }
+///     this_thread::delay_execution(Timeout::relative(RETRY_DELAY));
+/// }
+/// ```
+///
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Deadline {
+    //`None` never expires
+    at: Option<Instant>,
+}
+
+impl Deadline {
+    #[inline]
+    pub fn never() -> Self {
+        Self { at: None }
+    }
+
+    #[inline]
+    pub fn at(instant: Instant) -> Self {
+        Self { at: Some(instant) }
+    }
+
+    ///
+    /// A duration too large for the clock never expires
+    ///
+    pub fn after(duration: Duration) -> Self {
+        Self {
+            at: Instant::now().checked_add(duration),
+        }
+    }
+
+    #[inline]
+    pub fn instant(&self) -> Option<Instant> {
+        self.at
+    }
+
+    #[inline]
+    pub fn is_never(&self) -> bool {
+        self.at.is_none()
+    }
+
+    ///
+    /// `None` if the deadline never expires
+    ///
+    pub fn remaining(&self) -> Option<Duration> {
+        self.at.map(|_| self.remaining_at(Instant::now()))
+    }
+
+    pub fn has_expired(&self) -> bool {
+        self.at.is_some_and(|at| Instant::now() >= at)
+    }
+
+    fn remaining_at(&self, now: Instant) -> Duration {
+        match self.at {
+            Some(at) => at.saturating_duration_since(now),
+            None => Duration::MAX,
+        }
+    }
+
+    ///
+    /// The earlier of the two deadlines
+    ///
+    pub fn min(self, other: Deadline) -> Deadline {
+        match (self.at, other.at) {
+            (Some(a), Some(b)) => Deadline::at(a.min(b)),
+            (Some(_), None) => self,
+            _ => other,
+        }
+    }
+}
+
+///
+/// Earlier deadlines sort first, `never` sorts after every instant
+///
+impl Ord for Deadline {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.at, other.at) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+impl PartialOrd for Deadline {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{Deadline, Instant};
+
+    extern crate std;
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let start = Instant::from_raw(1_000);
+        let later = start + Duration::from_micros(5);
+
+        assert_eq!(later.raw_ticks(), 1_050);
+        assert_eq!(later - start, Duration::from_micros(5));
+        assert_eq!(start - later, Duration::ZERO);
+        assert_eq!(start.checked_duration_since(later), None);
+        assert_eq!(start.checked_sub(Duration::from_secs(1)), None);
+
+        let deadline = Deadline::at(later);
+        assert_eq!(deadline.remaining_at(start), Duration::from_micros(5));
+        assert_eq!(
+            deadline.remaining_at(later + Duration::from_micros(1)),
+            Duration::ZERO
+        );
+        assert_eq!(Deadline::never().remaining_at(start), Duration::MAX);
+
+        assert_eq!(Deadline::never().min(deadline), deadline);
+        assert_eq!(deadline.min(Deadline::at(start)), Deadline::at(start));
+
+        assert!(Deadline::at(start) < deadline);
+        assert!(deadline < Deadline::never());
+        assert_eq!(Deadline::never().max(deadline), Deadline::never());
+
+        Ok(())
+    }
+}
@@ -1,9 +1,41 @@
-use core::time::Duration;
+//!
+//! Time sources and kernel timeouts
+//!
+//! `Instant` follows the interrupt time, it is monotonic and keeps counting
+//! while the system sleeps. `SystemTime` is the UTC wall clock and can jump
+//! when the clock is changed. Both count 100ns ticks.
+//!
 
-use windows_sys::Wdk::System::SystemServices::KeQuerySystemTimePrecise;
+pub mod calendar;
+mod instant;
+pub mod timer;
+
+pub use instant::{Deadline, Instant};
+
+use core::{fmt, time::Duration};
+
+use windows_sys::Wdk::System::SystemServices::{ExSystemTimeToLocalTime, KeQuerySystemTimePrecise};
 
 use crate::kmalloc::{MemoryTag, TaggedObject};
 
+use calendar::TimeFields;
+
+#[inline]
+pub(crate) fn duration_to_ticks(duration: Duration) -> i64 {
+    (duration.as_nanos() / 100).min(i64::MAX as u128) as i64
+}
+
+#[inline]
+pub(crate) fn ticks_to_duration(ticks: u64) -> Duration {
+    Duration::new(ticks / 10_000_000, ((ticks % 10_000_000) * 100) as u32)
+}
+
+///
+/// Timeout passed to the kernel wait functions
+///
+/// The kernel reads negative values as an interval from now
+/// and positive values as an absolute `SystemTime`.
+///
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Timeout {
     timeout: Option<i64>,
@@ -14,20 +46,61 @@ impl Timeout {
         Self { timeout: None }
     }
 
-    pub fn relative(relative: u64) -> Self {
+    ///
+    /// Returns right away, only checks the state of the object
+    ///
+    pub fn zero() -> Self {
+        Self { timeout: Some(0) }
+    }
+
+    ///
+    /// Expires `duration` from now, the interval does not shift with clock changes
+    ///
+    pub fn relative(duration: Duration) -> Self {
         Self {
-            timeout: Some(relative as i64),
+            timeout: Some(-duration_to_ticks(duration)),
         }
     }
 
-    pub fn timeout(timeout_in_ns: u64) -> Self {
+    ///
+    /// Expires once the wall clock reaches `time`, follows clock changes
+    ///
+    pub fn absolute(time: SystemTime) -> Self {
         Self {
-            timeout: Some((timeout_in_ns / 100) as i64),
+            timeout: Some(time.raw_time().min(i64::MAX as u64) as i64),
         }
     }
 
+    ///
+    /// Relative timeout in nanoseconds
+    ///
+    pub fn timeout(timeout_in_ns: u64) -> Self {
+        Self::relative(Duration::from_nanos(timeout_in_ns))
+    }
+
+    #[inline]
     pub fn from_duration(duration: Duration) -> Self {
-        Self::timeout(duration.as_nanos() as u64)
+        Self::relative(duration)
+    }
+
+    ///
+    /// Relative timeout for the time left until `deadline`
+    ///
+    pub fn until(deadline: Deadline) -> Self {
+        match deadline.remaining() {
+            Some(remaining) => Self::relative(remaining),
+            None => Self::infinite(),
+        }
+    }
+
+    #[inline]
+    pub fn is_infinite(&self) -> bool {
+        self.timeout.is_none()
+    }
+
+    #[inline]
+    pub fn is_relative(&self) -> bool {
+        self.timeout.is_some_and(|timeout| timeout <= 0)
     }
 
     pub fn as_ptr(&self) -> *const i64 {
@@ -39,7 +112,22 @@ impl Timeout {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+impl From<Duration> for Timeout {
+    fn from(duration: Duration) -> Self {
+        Self::relative(duration)
+    }
+}
+
+impl From<Deadline> for Timeout {
+    fn from(deadline: Deadline) -> Self {
+        Self::until(deadline)
+    }
+}
+
+///
+/// UTC wall clock time in 100ns ticks since 1601-01-01
+///
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 #[repr(transparent)]
 pub struct SystemTime {
     time: u64,
@@ -52,12 +140,33 @@ impl TaggedObject for SystemTime {
 }
 
 impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = SystemTime {
+        time: 116_444_736_000_000_000,
+    };
+
     pub fn new() -> Self {
         Self {
             time: Self::get_precise(),
         }
     }
 
+    #[inline]
+    pub fn now() -> Self {
+        Self::new()
+    }
+
+    #[inline]
+    pub const fn from_raw(time: u64) -> Self {
+        Self { time }
+    }
+
+    ///
+    /// `None` for a date outside of the range `TIME_FIELDS` supports
+    ///
+    pub fn from_fields(fields: &TimeFields) -> Option<Self> {
+        fields.to_ticks().map(Self::from_raw)
+    }
+
     pub fn update(&mut self) {
         self.time = Self::get_precise();
     }
@@ -68,12 +177,46 @@ impl SystemTime {
 
     #[inline]
     pub fn elapsed_raw(&self) -> u64 {
-        Self::get_precise() - self.time
+        Self::get_precise().saturating_sub(self.time)
     }
 
     #[inline]
     pub fn elapsed_duration(&self) -> Duration {
-        Duration::from_nanos(self.elapsed_raw() * 100)
+        ticks_to_duration(self.elapsed_raw())
+    }
+
+    ///
+    /// `None` if `earlier` is later than `self`, the wall clock can go backwards
+    ///
+    pub fn duration_since(&self, earlier: SystemTime) -> Option<Duration> {
+        self.time.checked_sub(earlier.time).map(ticks_to_duration)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.time
+            .checked_add(duration_to_ticks(duration) as u64)
+            .map(Self::from_raw)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        self.time
+            .checked_sub(duration_to_ticks(duration) as u64)
+            .map(Self::from_raw)
+    }
+
+    pub fn to_fields(&self) -> TimeFields {
+        TimeFields::from_ticks(self.time)
+    }
+
+    ///
+    /// Same instant in the local time zone of the machine
+    ///
+    pub fn to_local(&self) -> LocalTime {
+        let system = self.time as i64;
+        let mut local: i64 = 0;
+        unsafe { ExSystemTimeToLocalTime(&system, &mut local) };
+
+        LocalTime { time: local as u64 }
     }
 
     fn get_precise() -> u64 {
@@ -85,3 +228,100 @@ impl SystemTime {
         return time;
     }
 }
+
+impl Default for SystemTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// ISO-8601, `2024-03-05T08:15:30.250Z`
+///
+impl fmt::Display for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.to_fields(), f)
+    }
+}
+
+///
+/// Wall clock time in the local time zone, only meant for display
+///
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct LocalTime {
+    time: u64,
+}
+
+impl LocalTime {
+    #[inline]
+    pub fn raw_time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn to_fields(&self) -> TimeFields {
+        TimeFields::from_ticks(self.time)
+    }
+}
+
+///
+/// ISO-8601 without a zone designator, `2024-03-05T09:15:30.250`
+///
+impl fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = self.to_fields();
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}",
+            fields.year,
+            fields.month,
+            fields.day,
+            fields.hour,
+            fields.minute,
+            fields.second,
+            fields.milliseconds
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{LocalTime, SystemTime, Timeout};
+
+    extern crate std;
+    use std::string::ToString;
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let relative = Timeout::relative(Duration::from_millis(5));
+        assert!(relative.is_relative());
+        assert_eq!(unsafe { *relative.as_ptr() }, -50_000);
+        assert_eq!(
+            Timeout::timeout(1_000),
+            Timeout::relative(Duration::from_micros(1))
+        );
+
+        let time = SystemTime::UNIX_EPOCH
+            .checked_add(Duration::from_secs(1_709_626_530) + Duration::from_millis(250))
+            .unwrap();
+        assert_eq!(time.to_string(), "2024-03-05T08:15:30.250Z");
+        let local = LocalTime {
+            time: time.raw_time(),
+        };
+        assert_eq!(local.to_string(), "2024-03-05T08:15:30.250");
+
+        let absolute = Timeout::absolute(time);
+        assert!(!absolute.is_relative());
+        assert_eq!(unsafe { *absolute.as_ptr() }, time.raw_time() as i64);
+
+        assert_eq!(
+            time.duration_since(SystemTime::UNIX_EPOCH),
+            Some(Duration::from_millis(1_709_626_530_250))
+        );
+        assert_eq!(SystemTime::UNIX_EPOCH.duration_since(time), None);
+        assert_eq!(SystemTime::from_fields(&time.to_fields()), Some(time));
+
+        Ok(())
+    }
+}
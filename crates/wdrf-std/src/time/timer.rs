@@ -0,0 +1,204 @@
+use core::{
+    cell::UnsafeCell,
+    ffi::c_void,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::{DISPATCH_LEVEL, PASSIVE_LEVEL};
+use windows_sys::{
+    Wdk::{
+        Foundation::KDPC,
+        System::SystemServices::{
+            KeCancelTimer, KeFlushQueuedDpcs, KeInitializeDpc, KeInitializeTimerEx,
+            KeReadStateTimer, KeRemoveQueueDpc, KeSetTimerEx, KTIMER,
+        },
+    },
+    Win32::System::Kernel::NotificationTimer,
+};
+
+use crate::{
+    boxed::{Box, BoxExt},
+    constants::PoolFlags,
+    irql::Dispatch,
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+    sys::{WaitableKernelObject, WaitableObject},
+};
+
+use super::duration_to_ticks;
+
+type TimerCallback = Box<dyn FnMut(&mut Dispatch<'_>) + Send>;
+
+struct TimerInner {
+    timer: UnsafeCell<KTIMER>,
+    dpc: UnsafeCell<KDPC>,
+    callback: UnsafeCell<TimerCallback>,
+    running: AtomicBool,
+}
+
+impl TaggedObject for TimerInner {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"timr")
+    }
+}
+
+///
+/// A `KTIMER` that runs a closure from its DPC when it expires
+///
+/// The callback runs at DISPATCH_LEVEL and receives the `Dispatch` token.
+/// The timer is a notification timer, it stays signaled after expiring
+/// until it is started again so threads can also wait on it.
+///
+/// `cancel` and `Drop` wait for a callback that is already running,
+/// they must be called at PASSIVE_LEVEL and never from the callback itself.
+///
+pub struct Timer {
+    inner: Pin<Box<TimerInner>>,
+}
+
+unsafe impl Send for Timer {}
+unsafe impl Sync for Timer {}
+
+impl Timer {
+    pub fn try_create<F>(callback: F) -> anyhow::Result<Self>
+    where
+        F: FnMut(&mut Dispatch<'_>) + Send + 'static,
+    {
+        let callback: TimerCallback = Box::try_create_in(
+            callback,
+            GlobalKernelAllocator::new(
+                MemoryTag::new_from_bytes(b"tmcb"),
+                PoolFlags::POOL_FLAG_NON_PAGED,
+            ),
+        )?;
+
+        let inner = Box::try_pin(TimerInner {
+            timer: unsafe { core::mem::zeroed() },
+            dpc: unsafe { core::mem::zeroed() },
+            callback: UnsafeCell::new(callback),
+            running: AtomicBool::new(false),
+        })?;
+
+        unsafe {
+            let context: *const TimerInner = &*inner;
+            let routine: unsafe extern "system" fn(
+                *mut KDPC,
+                *mut c_void,
+                *mut c_void,
+                *mut c_void,
+            ) = timer_dpc_routine;
+
+            KeInitializeTimerEx(inner.timer.get(), NotificationTimer);
+            KeInitializeDpc(
+                inner.dpc.get(),
+                Some(core::mem::transmute(routine)),
+                context.cast(),
+            );
+        }
+
+        Ok(Self { inner })
+    }
+
+    ///
+    /// Fires once after `due`, returns true if the timer was already running
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    pub fn start_one_shot(&self, due: Duration) -> bool {
+        self.set(due, 0)
+    }
+
+    ///
+    /// Fires after `period` and then every `period`, with millisecond granularity
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    pub fn start_periodic(&self, period: Duration) -> bool {
+        self.start_periodic_after(period, period)
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    pub fn start_periodic_after(&self, due: Duration, period: Duration) -> bool {
+        let period = period.as_millis().clamp(1, i32::MAX as u128) as i32;
+        self.set(due, period)
+    }
+
+    ///
+    /// Stops the timer and waits for a callback that already started
+    ///
+    /// Returns true if the timer was still queued.
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn cancel(&self) -> bool {
+        let cancelled = self.cancel_nowait();
+
+        //Waits for DPCs that are queued or running on any processor
+        unsafe { KeFlushQueuedDpcs() };
+
+        cancelled
+    }
+
+    ///
+    /// Stops the timer without waiting, the callback may still be running
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    pub fn cancel_nowait(&self) -> bool {
+        unsafe {
+            let cancelled = KeCancelTimer(self.inner.timer.get()) != 0;
+            let dequeued = KeRemoveQueueDpc(self.inner.dpc.get()) != 0;
+
+            cancelled || dequeued
+        }
+    }
+
+    pub fn is_signaled(&self) -> bool {
+        unsafe { KeReadStateTimer(self.inner.timer.get()) != 0 }
+    }
+
+    fn set(&self, due: Duration, period: i32) -> bool {
+        let due_time: i64 = -duration_to_ticks(due);
+
+        unsafe {
+            KeSetTimerEx(
+                self.inner.timer.get(),
+                due_time,
+                period,
+                self.inner.dpc.get(),
+            ) != 0
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let _ = self.cancel();
+    }
+}
+
+unsafe impl WaitableObject for Timer {
+    fn kernel_object(&self) -> &WaitableKernelObject {
+        unsafe { &*(self.inner.timer.get() as *const WaitableKernelObject) }
+    }
+}
+
+unsafe extern "system" fn timer_dpc_routine(
+    _dpc: *mut KDPC,
+    context: *mut c_void,
+    _arg1: *mut c_void,
+    _arg2: *mut c_void,
+) {
+    let inner: &TimerInner = &*(context as *const TimerInner);
+
+    //A periodic timer can queue the DPC again while it still runs on another
+    //processor, that tick is skipped so the callback never runs concurrently
+    if inner.running.swap(true, Ordering::Acquire) {
+        return;
+    }
+
+    let callback = &mut *inner.callback.get();
+    let mut irql = Dispatch::new_unchecked();
+    callback(&mut irql);
+
+    inner.running.store(false, Ordering::Release);
+}
@@ -0,0 +1,243 @@
+//!
+//! Deferring work to PASSIVE_LEVEL
+//!
+//! `WorkQueue` runs closures on the system worker threads through `IO_WORKITEM`s,
+//! `pool::ThreadPool` runs them on a fixed set of driver owned threads.
+//!
+
+pub mod pool;
+
+use core::{ffi::c_void, mem::ManuallyDrop, time::Duration};
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::{DISPATCH_LEVEL, PASSIVE_LEVEL};
+use windows_sys::Wdk::{
+    Foundation::{DEVICE_OBJECT, DRIVER_OBJECT, PIO_WORKITEM},
+    System::SystemServices::{
+        CriticalWorkQueue, DelayedWorkQueue, HyperCriticalWorkQueue, IoAllocateWorkItem,
+        IoFreeWorkItem, IoQueueWorkItemEx, NormalWorkQueue,
+    },
+};
+
+use crate::{
+    boxed::{Box, BoxExt},
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag},
+    sync::{
+        arc::Arc,
+        in_flight::{InFlightCounter, InFlightTokenOwned},
+    },
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WorkQueueType {
+    Normal,
+    Delayed,
+    Critical,
+    HyperCritical,
+}
+
+impl WorkQueueType {
+    fn as_wdm_value(self) -> i32 {
+        match self {
+            WorkQueueType::Normal => NormalWorkQueue,
+            WorkQueueType::Delayed => DelayedWorkQueue,
+            WorkQueueType::Critical => CriticalWorkQueue,
+            WorkQueueType::HyperCritical => HyperCriticalWorkQueue,
+        }
+    }
+}
+
+struct WorkItem<F> {
+    function: F,
+    _in_flight: InFlightTokenOwned,
+}
+
+///
+/// Queues closures as `IO_WORKITEM`s owned by a device or driver object
+///
+/// The I/O manager keeps the object referenced while an item is queued.
+/// Dropping the queue rejects new items and waits for the queued ones,
+/// so it must be dropped at PASSIVE_LEVEL before the driver state they use is torn down.
+///
+pub struct WorkQueue {
+    io_object: *const DEVICE_OBJECT,
+    in_flight: Arc<InFlightCounter>,
+}
+
+unsafe impl Send for WorkQueue {}
+unsafe impl Sync for WorkQueue {}
+
+impl WorkQueue {
+    ///
+    /// # Safety
+    ///
+    /// `device` must stay valid for the lifetime of the queue
+    ///
+    pub unsafe fn from_device(device: *const DEVICE_OBJECT) -> anyhow::Result<Self> {
+        Ok(Self {
+            io_object: device,
+            in_flight: InFlightCounter::try_create_arc()?,
+        })
+    }
+
+    ///
+    /// For drivers without device objects, like minifilters
+    ///
+    /// # Safety
+    ///
+    /// `driver` must stay valid for the lifetime of the queue
+    ///
+    pub unsafe fn from_driver(driver: *const DRIVER_OBJECT) -> anyhow::Result<Self> {
+        //IoAllocateWorkItem accepts a driver object in place of a device object
+        Self::from_device(driver.cast())
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    pub fn queue_work<F>(&self, f: F) -> anyhow::Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.queue_work_in(WorkQueueType::Delayed, f)
+    }
+
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    pub fn queue_work_in<F>(&self, queue: WorkQueueType, f: F) -> anyhow::Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.prepare(f)?.queue(queue);
+
+        Ok(())
+    }
+
+    ///
+    /// Allocates the item up front, queueing it later can not fail
+    ///
+    /// Used to hand work from an executive work item to an `IO_WORKITEM`,
+    /// the item counts as pending on this queue from now on.
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    pub(crate) fn prepare<F>(&self, f: F) -> anyhow::Result<PreparedWork>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let in_flight = self
+            .in_flight
+            .enter_owned()
+            .ok_or(anyhow::Error::msg("WorkQueue is draining"))?;
+
+        let item = Box::try_create_in(
+            WorkItem {
+                function: f,
+                _in_flight: in_flight,
+            },
+            Self::allocator(),
+        )?;
+
+        let io_item = unsafe { IoAllocateWorkItem(self.io_object) };
+        if io_item == 0 {
+            return Err(anyhow::Error::msg("Failed to allocate IO_WORKITEM"));
+        }
+
+        Ok(PreparedWork {
+            io_item,
+            context: Box::into_raw(item).cast(),
+            routine: work_routine::<F>,
+            drop_context: drop_work_item::<F>,
+        })
+    }
+
+    #[inline]
+    pub fn pending(&self) -> u32 {
+        self.in_flight.count()
+    }
+
+    ///
+    /// Rejects new items and waits for the queued ones
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn drain(&self, timeout: Option<Duration>) -> bool {
+        self.in_flight.drain(timeout)
+    }
+
+    fn allocator() -> GlobalKernelAllocator {
+        GlobalKernelAllocator::new(
+            MemoryTag::new_from_bytes(b"wkit"),
+            PoolFlags::POOL_FLAG_NON_PAGED,
+        )
+    }
+}
+
+impl Drop for WorkQueue {
+    fn drop(&mut self) {
+        let _ = self.drain(None);
+    }
+}
+
+///
+/// An allocated `IO_WORKITEM` that has not been queued yet
+///
+pub(crate) struct PreparedWork {
+    io_item: PIO_WORKITEM,
+    context: *mut c_void,
+    routine: unsafe extern "system" fn(*mut c_void, *mut c_void, PIO_WORKITEM),
+    drop_context: unsafe fn(*mut c_void),
+}
+
+unsafe impl Send for PreparedWork {}
+
+impl PreparedWork {
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    pub(crate) fn queue(self, queue: WorkQueueType) {
+        let this = ManuallyDrop::new(self);
+
+        unsafe {
+            IoQueueWorkItemEx(
+                this.io_item,
+                Some(core::mem::transmute(this.routine)),
+                queue.as_wdm_value(),
+                this.context,
+            );
+        }
+    }
+}
+
+impl Drop for PreparedWork {
+    fn drop(&mut self) {
+        unsafe {
+            IoFreeWorkItem(self.io_item);
+            (self.drop_context)(self.context);
+        }
+    }
+}
+
+unsafe fn drop_work_item<F>(context: *mut c_void)
+where
+    F: FnOnce() + Send + 'static,
+{
+    drop(Box::from_raw_in(
+        context as *mut WorkItem<F>,
+        WorkQueue::allocator(),
+    ));
+}
+
+unsafe extern "system" fn work_routine<F>(
+    _io_object: *mut c_void,
+    context: *mut c_void,
+    io_item: PIO_WORKITEM,
+) where
+    F: FnOnce() + Send + 'static,
+{
+    //Freeing the item from its own routine is allowed
+    IoFreeWorkItem(io_item);
+
+    let item = Box::from_raw_in(context as *mut WorkItem<F>, WorkQueue::allocator());
+    let WorkItem {
+        function,
+        _in_flight,
+    } = Box::into_inner(item);
+
+    function();
+}
@@ -0,0 +1,209 @@
+use core::num::NonZeroU32;
+
+use wdrf_macros::irql_check;
+#[cfg(feature = "irql-checks")]
+use windows_sys::Wdk::System::SystemServices::{DISPATCH_LEVEL, PASSIVE_LEVEL};
+
+use crate::{
+    boxed::{Box, BoxExt},
+    collections::vec_deq::{VecDeque, VecDequeExt},
+    constants::PoolFlags,
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
+    sync::{
+        arc::{Arc, ArcExt},
+        cancel::CancellationToken,
+        ExSpinMutex,
+    },
+    sys::{semaphore::KeSemaphore, wait_object_cancellable, WaitResponse},
+    thread::{self, JoinHandle},
+    traits::DispatchSafe,
+    vec::{Vec, VecExt},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TaskPriority {
+    High,
+    Normal,
+    Low,
+}
+
+impl TaskPriority {
+    fn index(self) -> usize {
+        match self {
+            TaskPriority::High => 0,
+            TaskPriority::Normal => 1,
+            TaskPriority::Low => 2,
+        }
+    }
+}
+
+type Task = Box<dyn FnOnce() + Send>;
+
+struct PoolQueues {
+    //Indexed by `TaskPriority::index`
+    tasks: [VecDeque<Task>; 3],
+    len: usize,
+}
+
+unsafe impl DispatchSafe for PoolQueues {}
+
+struct PoolShared {
+    queues: ExSpinMutex<PoolQueues>,
+    available: KeSemaphore,
+    stop: CancellationToken,
+    capacity: usize,
+}
+
+unsafe impl Send for PoolShared {}
+unsafe impl Sync for PoolShared {}
+
+impl TaggedObject for PoolShared {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"tpsh")
+    }
+}
+
+impl PoolShared {
+    fn pop(&self) -> Option<Task> {
+        let mut queues = self.queues.write_raw();
+
+        let task = queues.tasks.iter_mut().find_map(|queue| queue.pop_front());
+        if task.is_some() {
+            queues.len -= 1;
+        }
+
+        task
+    }
+}
+
+///
+/// Fixed set of system threads running queued closures
+///
+/// The queue is bounded, `execute` fails instead of allocating once `capacity`
+/// tasks are pending. Higher priority tasks are picked first.
+/// Dropping the pool stops the workers and discards the tasks that did not start.
+///
+pub struct ThreadPool {
+    shared: Arc<PoolShared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = PASSIVE_LEVEL))]
+    pub fn try_create(threads: usize, capacity: usize) -> anyhow::Result<Self> {
+        if threads == 0 || capacity == 0 {
+            return Err(anyhow::Error::msg(
+                "ThreadPool needs at least one thread and task",
+            ));
+        }
+
+        let mut tasks = [Self::queue(), Self::queue(), Self::queue()];
+        for queue in tasks.iter_mut() {
+            //Reserved upfront so queueing never allocates the slot
+            queue
+                .try_reserve(capacity)
+                .map_err(|_| anyhow::Error::msg("Failed to reserve ThreadPool queue"))?;
+        }
+
+        let shared = Arc::try_create(PoolShared {
+            queues: ExSpinMutex::new(PoolQueues { tasks, len: 0 }),
+            available: unsafe { KeSemaphore::new() },
+            stop: CancellationToken::try_create()?,
+            capacity,
+        })?;
+        unsafe { shared.available.init(0, i32::MAX) };
+
+        let mut pool = Self {
+            shared,
+            workers: Vec::new_in(GlobalKernelAllocator::new(
+                MemoryTag::new_from_bytes(b"tpwk"),
+                PoolFlags::POOL_FLAG_NON_PAGED,
+            )),
+        };
+
+        pool.workers
+            .try_reserve_exact(threads)
+            .map_err(|_| anyhow::Error::msg("Failed to reserve ThreadPool workers"))?;
+        for _ in 0..threads {
+            let shared = pool.shared.clone();
+            let worker = thread::Builder::new()
+                .name("wdrf-pool")
+                .spawn(move || Self::worker_routine(shared))
+                .map_err(|_| anyhow::Error::msg("Failed to spawn ThreadPool worker"))?;
+
+            //Capacity was reserved above
+            pool.workers.try_push(worker)?;
+        }
+
+        Ok(pool)
+    }
+
+    ///
+    /// Fails if the queue is full or the task could not be allocated
+    ///
+    #[cfg_attr(feature = "irql-checks", irql_check(irql = DISPATCH_LEVEL))]
+    pub fn execute<F>(&self, priority: TaskPriority, f: F) -> anyhow::Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let task: Task = Box::try_create_in(
+            f,
+            GlobalKernelAllocator::new(
+                MemoryTag::new_from_bytes(b"tptk"),
+                PoolFlags::POOL_FLAG_NON_PAGED,
+            ),
+        )?;
+
+        {
+            let mut queues = self.shared.queues.write_raw();
+            if queues.len >= self.shared.capacity {
+                return Err(anyhow::Error::msg("ThreadPool queue is full"));
+            }
+
+            queues.tasks[priority.index()].try_push_back(task)?;
+            queues.len += 1;
+        }
+
+        self.shared
+            .available
+            .release(unsafe { NonZeroU32::new_unchecked(1) });
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.shared.queues.read_raw().len
+    }
+
+    fn queue() -> VecDeque<Task> {
+        VecDeque::new_in(GlobalKernelAllocator::new(
+            MemoryTag::new_from_bytes(b"tpqu"),
+            PoolFlags::POOL_FLAG_NON_PAGED,
+        ))
+    }
+
+    fn worker_routine(shared: Arc<PoolShared>) {
+        loop {
+            match wait_object_cancellable(&shared.available, &shared.stop, None) {
+                WaitResponse::Success => {}
+                WaitResponse::Cancelled => break,
+                status => panic!("Unexpected ThreadPool wait status: {:?}", status),
+            }
+
+            if let Some(task) = shared.pop() {
+                task();
+            }
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shared.stop.cancel();
+
+        while let Some(worker) = self.workers.pop() {
+            worker.join();
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//!
+//! Driver settings read from the `Parameters` subkey of the service key
+//!
+//! ```ignore
+//! #[derive(DriverConfig)]
+//! struct Settings {
+//!     #[config(default = 3, range = 0..=5)]
+//!     log_level: u32,
+//!     #[config(name = "ScanTimeoutMs", default = 3000)]
+//!     scan_timeout: u64,
+//! }
+//!
+//! let settings: Settings = wdrf::config::load(registry_path)?;
+//! ```
+//!
+
+use core::marker::PhantomData;
+
+use nt_string::{nt_unicode_str, unicode_string::NtUnicodeStr};
+use wdrf_std::{
+    registry::{Key, NotifyFilter, KEY_CREATE_SUB_KEY, KEY_NOTIFY, KEY_READ},
+    sync::{arc::Arc, event::Event},
+    sys::event::EventType,
+    workqueue::WorkQueue,
+    NtStatusError,
+};
+use windows_sys::Win32::Foundation::STATUS_OBJECT_NAME_NOT_FOUND;
+
+pub use wdrf_macros::DriverConfig;
+pub use wdrf_std::registry::{FromRegValue, MemoryStore, ValueStore};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    ///
+    /// A field without a default has no value
+    ///
+    Missing(&'static str),
+    InvalidType(&'static str),
+    OutOfRange(&'static str),
+    NoMemory,
+    NtStatus(NtStatusError),
+}
+
+impl From<NtStatusError> for ConfigError {
+    fn from(value: NtStatusError) -> Self {
+        ConfigError::NtStatus(value)
+    }
+}
+
+///
+/// Implemented with `#[derive(DriverConfig)]`
+///
+pub trait DriverConfig: Sized {
+    fn load_from<S: ValueStore + ?Sized>(store: &S) -> Result<Self, ConfigError>;
+}
+
+///
+/// Used by the derive, reads one field
+///
+#[doc(hidden)]
+pub fn read_field<T: FromRegValue, S: ValueStore + ?Sized>(
+    store: &S,
+    name: &'static str,
+) -> Result<Option<T>, ConfigError> {
+    match store.read_as::<T>(name)? {
+        Some(Ok(value)) => Ok(Some(value)),
+        Some(Err(_)) => Err(ConfigError::InvalidType(name)),
+        None => Ok(None),
+    }
+}
+
+///
+/// Loads `T` from `registry_path\Parameters`
+///
+/// `registry_path` is the path handed to `DriverEntry`. When the
+/// `Parameters` key does not exist every field takes its default.
+///
+pub fn load<T: DriverConfig>(registry_path: &NtUnicodeStr<'_>) -> Result<T, ConfigError> {
+    let service = match Key::open(registry_path, KEY_READ) {
+        Ok(key) => key,
+        Err(NtStatusError::Status(STATUS_OBJECT_NAME_NOT_FOUND)) => {
+            return T::load_from(&MemoryStore::new())
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    match service.open_subkey(&nt_unicode_str!("Parameters"), KEY_READ) {
+        Ok(parameters) => T::load_from(&parameters),
+        Err(NtStatusError::Status(STATUS_OBJECT_NAME_NOT_FOUND)) => {
+            T::load_from(&MemoryStore::new())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+///
+/// Keeps the `Parameters` key open and signals an event when it changes
+///
+/// Wait on `event()` from a worker thread and call `reload`, which also
+/// re-arms the notification. The notifications are delivered through
+/// `queue`, usually built with `WorkQueue::from_driver`.
+///
+pub struct ConfigWatcher<T: DriverConfig> {
+    //Dropped before the queue, the key waits for its notifications
+    key: Key,
+    queue: WorkQueue,
+    event: Arc<Event>,
+    _config: PhantomData<fn() -> T>,
+}
+
+impl<T: DriverConfig> ConfigWatcher<T> {
+    ///
+    /// Creates the `Parameters` key if it is missing and loads the first
+    /// configuration
+    ///
+    pub fn try_create(
+        registry_path: &NtUnicodeStr<'_>,
+        queue: WorkQueue,
+    ) -> Result<(Self, T), ConfigError> {
+        let service = Key::open(registry_path, KEY_READ | KEY_CREATE_SUB_KEY)?;
+        let (key, _) =
+            service.create_subkey(&nt_unicode_str!("Parameters"), KEY_READ | KEY_NOTIFY)?;
+
+        let event = Event::try_create_arc(EventType::Synchronization, false)
+            .map_err(|_| ConfigError::NoMemory)?;
+
+        let watcher = Self {
+            key,
+            queue,
+            event,
+            _config: PhantomData,
+        };
+        let config = watcher.reload()?;
+
+        Ok((watcher, config))
+    }
+
+    ///
+    /// Signaled once after every change to the key values
+    ///
+    #[inline]
+    pub fn event(&self) -> &Arc<Event> {
+        &self.event
+    }
+
+    ///
+    /// Arms the notification before reading so a change made while
+    /// loading is not lost
+    ///
+    pub fn reload(&self) -> Result<T, ConfigError> {
+        self.key.notify_change(
+            &self.queue,
+            NotifyFilter::LAST_SET,
+            false,
+            self.event.clone(),
+        )?;
+
+        T::load_from(&self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wdrf_std::registry::{RegString, RegValue};
+
+    use super::{ConfigError, DriverConfig, MemoryStore};
+
+    extern crate std;
+
+    #[derive(DriverConfig)]
+    struct Settings {
+        //Same name as the parameter of `load_from`
+        store: u32,
+        #[config(name = "ScanTimeoutMs", default = 3000)]
+        scan_timeout: u64,
+        #[config(default = 3, range = 0..=5)]
+        log_level: u32,
+    }
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let mut store = MemoryStore::new();
+        assert!(matches!(
+            Settings::load_from(&store),
+            Err(ConfigError::Missing("Store"))
+        ));
+
+        store.try_insert("Store", RegValue::Dword(7))?;
+        let settings = Settings::load_from(&store).unwrap();
+        assert_eq!(settings.store, 7);
+        assert_eq!(settings.scan_timeout, 3000);
+        assert_eq!(settings.log_level, 3);
+
+        store.try_insert("scantimeoutms", RegValue::Qword(500))?;
+        store.try_insert("LogLevel", RegValue::Dword(5))?;
+        let settings = Settings::load_from(&store).unwrap();
+        assert_eq!(settings.scan_timeout, 500);
+        assert_eq!(settings.log_level, 5);
+
+        //Only the mapped name is read
+        store.try_insert("ScanTimeout", RegValue::Qword(1))?;
+        assert_eq!(Settings::load_from(&store).unwrap().scan_timeout, 500);
+
+        store.try_insert("LogLevel", RegValue::Dword(6))?;
+        assert!(matches!(
+            Settings::load_from(&store),
+            Err(ConfigError::OutOfRange("LogLevel"))
+        ));
+        store.try_insert("LogLevel", RegValue::Dword(0))?;
+
+        store.try_insert(
+            "ScanTimeoutMs",
+            RegValue::String(RegString::try_from_str("500")?),
+        )?;
+        assert!(matches!(
+            Settings::load_from(&store),
+            Err(ConfigError::InvalidType("ScanTimeoutMs"))
+        ));
+
+        Ok(())
+    }
+}
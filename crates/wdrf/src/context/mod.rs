@@ -1,9 +1,11 @@
 use core::{
     cell::UnsafeCell,
     mem::MaybeUninit,
-    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    sync::atomic::{AtomicU32, AtomicU8, Ordering},
 };
 
+use wdrf_std::sync::{lazy::LazyLock, once::OnceLock};
+
 ///
 /// Usefull for storing static global data
 ///
@@ -21,7 +23,7 @@ unsafe impl<const SIZE: usize> Send for FixedGlobalContextRegistry<SIZE> {}
 unsafe impl<const SIZE: usize> Sync for FixedGlobalContextRegistry<SIZE> {}
 
 pub trait ContextRegistry {
-    fn register<T>(&self, context: &'static Context<T>) -> anyhow::Result<()>;
+    fn register<C: ContextDrop + 'static>(&self, context: &'static C) -> anyhow::Result<()>;
     fn drop_self(&self);
 }
 
@@ -43,11 +45,11 @@ impl<const SIZE: usize> Default for FixedGlobalContextRegistry<SIZE> {
 }
 
 impl<const SIZE: usize> ContextRegistry for FixedGlobalContextRegistry<SIZE> {
-    fn register<T: Sized>(&self, context: &'static Context<T>) -> anyhow::Result<()> {
+    fn register<C: ContextDrop + 'static>(&self, context: &'static C) -> anyhow::Result<()> {
         let inner = unsafe { &mut *self.internal.get() };
         let pos = inner.size.fetch_add(1, Ordering::SeqCst) as usize;
 
-        if pos > SIZE {
+        if pos >= SIZE {
             Err(anyhow::Error::msg("Fixed context registry is full"))
         } else {
             unsafe {
@@ -75,12 +77,19 @@ impl<const SIZE: usize> ContextRegistry for FixedGlobalContextRegistry<SIZE> {
     }
 }
 
-trait ContextDrop {
+///
+/// Implemented by globals that can be torn down by a `ContextRegistry`
+///
+pub trait ContextDrop {
     fn context_drop(&self);
 }
 
+const CONTEXT_UNINIT: u8 = 0;
+const CONTEXT_INITIALIZING: u8 = 1;
+const CONTEXT_READY: u8 = 2;
+
 pub struct Context<T: Sized> {
-    is_init: AtomicBool,
+    state: AtomicU8,
     data: UnsafeCell<MaybeUninit<T>>,
 }
 
@@ -90,14 +99,14 @@ unsafe impl<T: Sync> Sync for Context<T> {}
 impl<T> Context<T> {
     pub const fn uninit() -> Self {
         Self {
-            is_init: AtomicBool::new(false),
+            state: AtomicU8::new(CONTEXT_UNINIT),
             data: UnsafeCell::new(MaybeUninit::uninit()),
         }
     }
 
     pub const fn new(data: T) -> Self {
         Self {
-            is_init: AtomicBool::new(true),
+            state: AtomicU8::new(CONTEXT_READY),
             data: UnsafeCell::new(MaybeUninit::new(data)),
         }
     }
@@ -110,19 +119,22 @@ impl<T> Context<T> {
     where
         F: FnOnce() -> T,
     {
-        let result = self
-            .is_init
-            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst);
+        let result = self.state.compare_exchange(
+            CONTEXT_UNINIT,
+            CONTEXT_INITIALIZING,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
 
         match result {
-            Ok(false) => {
+            Ok(_) => {
                 unsafe {
                     *self.data.get() = MaybeUninit::new(init_function());
                 }
+                self.state.store(CONTEXT_READY, Ordering::Release);
                 registry.register(self)
             }
-            Err(true) => Ok(()),
-            _ => panic!("Should not have gotten here"),
+            Err(_) => Ok(()),
         }
     }
 
@@ -130,6 +142,18 @@ impl<T> Context<T> {
         unsafe { (*self.data.get()).assume_init_ref() }
     }
 
+    ///
+    /// Same as `get` but returns None until `init` has finished and after
+    /// the registry dropped the context
+    ///
+    pub fn try_get(&self) -> Option<&'static T> {
+        if self.state.load(Ordering::Acquire) == CONTEXT_READY {
+            Some(self.get())
+        } else {
+            None
+        }
+    }
+
     ///
     /// # Safety
     ///
@@ -143,9 +167,138 @@ impl<T> Context<T> {
 impl<T> ContextDrop for Context<T> {
     fn context_drop(&self) {
         unsafe {
-            if self.is_init.load(Ordering::SeqCst) {
+            if self.state.load(Ordering::SeqCst) == CONTEXT_READY {
                 (*self.data.get()).assume_init_drop();
+                self.state.store(CONTEXT_UNINIT, Ordering::SeqCst);
             }
         }
     }
 }
+
+impl<T> ContextDrop for OnceLock<T> {
+    fn context_drop(&self) {
+        unsafe {
+            self.teardown();
+        }
+    }
+}
+
+///
+/// Initializes a `OnceLock` global and registers it for teardown
+///
+pub trait OnceLockExt<T> {
+    ///
+    /// Runs `f` if the lock is not initialized and registers the lock with `registry`
+    ///
+    /// Registration only happens for the call that initializes the lock, if it
+    /// fails the value is dropped and the lock stays uninitialized.
+    ///
+    fn get_or_try_init_registered<R, F>(
+        &'static self,
+        registry: &'static R,
+        f: F,
+    ) -> anyhow::Result<&'static T>
+    where
+        R: ContextRegistry,
+        F: FnOnce() -> anyhow::Result<T>;
+}
+
+impl<T: 'static> OnceLockExt<T> for OnceLock<T> {
+    fn get_or_try_init_registered<R, F>(
+        &'static self,
+        registry: &'static R,
+        f: F,
+    ) -> anyhow::Result<&'static T>
+    where
+        R: ContextRegistry,
+        F: FnOnce() -> anyhow::Result<T>,
+    {
+        self.get_or_try_init(|| {
+            let value = f()?;
+            //Tearing down a lock that is not initialized yet does nothing
+            registry.register(self)?;
+
+            Ok(value)
+        })
+    }
+}
+
+impl<T, F> ContextDrop for LazyLock<T, F>
+where
+    F: FnOnce() -> T,
+{
+    fn context_drop(&self) {
+        unsafe {
+            LazyLock::teardown(self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use wdrf_std::sync::once::OnceLock;
+
+    use super::{Context, ContextRegistry, FixedGlobalContextRegistry, OnceLockExt};
+
+    static DROPS: AtomicU32 = AtomicU32::new(0);
+
+    struct Tracked(u32);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    static REGISTRY: FixedGlobalContextRegistry<1> = FixedGlobalContextRegistry::new();
+    static FIRST: OnceLock<Tracked> = OnceLock::new();
+    static SECOND: OnceLock<Tracked> = OnceLock::new();
+
+    #[test]
+    fn test() -> anyhow::Result<()> {
+        let value = FIRST.get_or_try_init_registered(&REGISTRY, || Ok(Tracked(10)))?;
+        assert_eq!(value.0, 10);
+
+        //Already initialized, neither runs the initializer nor registers again
+        let value = FIRST.get_or_try_init_registered(&REGISTRY, || Ok(Tracked(20)))?;
+        assert_eq!(value.0, 10);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+
+        //The registry is full, the value is dropped and the lock stays empty
+        assert!(SECOND
+            .get_or_try_init_registered(&REGISTRY, || Ok(Tracked(30)))
+            .is_err());
+        assert!(SECOND.get().is_none());
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+
+        REGISTRY.drop_self();
+        assert!(FIRST.get().is_none());
+        assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+
+        //After teardown the lock can be initialized and registered again
+        let value = FIRST.get_or_try_init_registered(&REGISTRY, || Ok(Tracked(40)))?;
+        assert_eq!(value.0, 40);
+        REGISTRY.drop_self();
+        assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+
+        Ok(())
+    }
+
+    static CONTEXT_REGISTRY: FixedGlobalContextRegistry<1> = FixedGlobalContextRegistry::new();
+    static CONTEXT: Context<u32> = Context::uninit();
+
+    #[test]
+    fn context_try_get() -> anyhow::Result<()> {
+        assert!(CONTEXT.try_get().is_none());
+
+        CONTEXT.init(&CONTEXT_REGISTRY, || 5)?;
+        assert_eq!(CONTEXT.try_get(), Some(&5));
+
+        CONTEXT_REGISTRY.drop_self();
+        assert!(CONTEXT.try_get().is_none());
+
+        Ok(())
+    }
+}
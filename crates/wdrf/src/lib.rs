@@ -5,11 +5,17 @@
 #![feature(trait_alias)]
 #![feature(box_into_inner)]
 
+//Lets the derives used by the tests name `::wdrf`
+#[cfg(test)]
+extern crate self as wdrf;
+
+pub mod config;
 pub mod context;
 pub mod logger;
 pub mod macros;
 pub mod mm;
 pub mod process;
+pub mod security;
 
 #[cfg(feature = "minifilter")]
 pub mod minifilter;
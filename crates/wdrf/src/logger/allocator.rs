@@ -1,7 +1,8 @@
 use wdrf_std::{
     constants::PoolFlags,
+    irql::DispatchGuard,
     kmalloc::{GlobalKernelAllocator, MemoryTag},
-    sync::{InStackLockHandle, StackSpinMutex},
+    sync::StackSpinMutex,
     vec::{Vec, VecCreate, VecExt},
 };
 
@@ -22,14 +23,21 @@ impl LoggerAllocator {
     }
 
     pub fn try_allocate(&self) -> anyhow::Result<Vec<u8>> {
-        let handle = InStackLockHandle::new();
-        let mut guard = self.free_buffers.lock(&handle);
+        let mut dispatch = DispatchGuard::raise();
+        let free = self
+            .free_buffers
+            .with_lock_at_dpc_level(&mut dispatch.irql(), |buffers| {
+                if !buffers.is_empty() {
+                    Some(buffers.swap_remove(0))
+                } else {
+                    None
+                }
+            });
+        drop(dispatch);
 
-        if !guard.is_empty() {
-            Ok(guard.swap_remove(0))
+        if let Some(buffer) = free {
+            Ok(buffer)
         } else {
-            core::mem::drop(guard);
-
             let mut buffer = Vec::create();
             buffer.try_resize(self.start_buffer_size, 0)?;
 
@@ -39,9 +47,11 @@ impl LoggerAllocator {
 
     pub fn free_allocation(&self, buf: Vec<u8>) {
         if buf.len() == self.start_buffer_size {
-            let handle = InStackLockHandle::new();
-            let mut guard = self.free_buffers.lock(&handle);
-            let _ = guard.try_push(buf);
+            let mut dispatch = DispatchGuard::raise();
+            self.free_buffers
+                .with_lock_at_dpc_level(&mut dispatch.irql(), |buffers| {
+                    let _ = buffers.try_push(buf);
+                });
         }
     }
 }
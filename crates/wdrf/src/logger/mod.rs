@@ -1,18 +1,15 @@
-use core::{
-    fmt::Write,
-    ops::DerefMut,
-    panic,
-    sync::atomic::{AtomicBool, Ordering},
-};
+use core::{fmt::Write, panic};
 
 use allocator::LoggerAllocator;
 use maple::consumer::EventConsumer;
 use wdrf_std::{
     constants::PoolFlags,
+    irql::{DispatchGuard, Passive},
     kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
     sync::{
         arc::{Arc, ArcExt},
-        InStackLockHandle, StackSpinMutex,
+        cancel::CancellationToken,
+        StackSpinMutex,
     },
     sys::{
         event::{EventType, KeEvent},
@@ -34,7 +31,7 @@ const VEC_U8_TAG: MemoryTag = MemoryTag::new_from_bytes(b"logv");
 struct LoggerInner {
     log_event: KeEvent,
     pending_events: StackSpinMutex<Vec<Vec<u8>>>,
-    stop: AtomicBool,
+    stop: CancellationToken,
     allocator: LoggerAllocator,
 }
 
@@ -49,7 +46,6 @@ impl TaggedObject for LoggerInner {
 
 pub struct DbgPrintLogger {
     inner: Arc<LoggerInner>,
-    #[allow(dead_code)]
     log_thread: JoinHandle<()>,
 }
 
@@ -90,12 +86,12 @@ impl DbgPrintLogger {
         let inner = LoggerInner {
             log_event: unsafe { KeEvent::new() },
             pending_events: StackSpinMutex::new(buffer),
-            stop: AtomicBool::new(false),
+            stop: CancellationToken::try_create()?,
             allocator: LoggerAllocator::new(512),
         };
 
         let inner = Arc::try_create(inner)?;
-        inner.log_event.init(EventType::Notification, false);
+        unsafe { inner.log_event.init(EventType::Notification, false) };
 
         let inner_clone = inner.clone();
         let th = spawn(move || Self::worker_routine(inner_clone))
@@ -108,13 +104,17 @@ impl DbgPrintLogger {
     }
 
     pub fn log_event(&self, writtable: DbgWritable) {
-        {
-            let handle = InStackLockHandle::new();
-            let mut guard = self.inner.pending_events.lock(&handle);
-
-            if let Err(_) = guard.try_push(writtable.buffer) {
-                return;
-            }
+        //Events are logged from any IRQL up to DISPATCH_LEVEL
+        let mut dispatch = DispatchGuard::raise();
+        let pushed = self
+            .inner
+            .pending_events
+            .with_lock_at_dpc_level(&mut dispatch.irql(), |events| {
+                events.try_push(writtable.buffer)
+            });
+        drop(dispatch);
+        if pushed.is_err() {
+            return;
         }
 
         self.inner.log_event.signal();
@@ -122,6 +122,7 @@ impl DbgPrintLogger {
 
     fn worker_routine(inner: Arc<LoggerInner>) {
         let logger = inner.as_ref();
+        let mut passive = Passive::try_current().expect("Logger thread runs at PASSIVE_LEVEL");
 
         let mut event_buffer = Vec::new_in(GlobalKernelAllocator::new(
             VEC_U8_TAG,
@@ -129,22 +130,22 @@ impl DbgPrintLogger {
         ));
 
         loop {
-            if logger.stop.load(Ordering::Relaxed) {
-                break;
-            }
-
-            let status = logger.log_event.wait();
-            if status != WaitResponse::Success {
-                panic!("AAA");
-            }
-
+            match logger
+                .log_event
+                .wait_cancellable_irql(&passive, &logger.stop, None)
             {
-                let handle = InStackLockHandle::new();
-                let mut guard = logger.pending_events.lock(&handle);
-                core::mem::swap(guard.deref_mut(), &mut event_buffer);
-                inner.log_event.clear();
+                WaitResponse::Success => {}
+                WaitResponse::Cancelled => break,
+                status => panic!("Unexpected logger wait status: {:?}", status),
             }
 
+            logger
+                .pending_events
+                .with_lock_irql(&mut passive, |events| {
+                    core::mem::swap(events, &mut event_buffer);
+                    inner.log_event.clear();
+                });
+
             for event in &event_buffer {
                 unsafe {
                     DbgPrint(event.as_slice().as_ptr());
@@ -160,11 +161,10 @@ impl DbgPrintLogger {
 
 impl Drop for DbgPrintLogger {
     fn drop(&mut self) {
-        let handle = InStackLockHandle::new();
-        let _guard = self.inner.pending_events.lock(&handle);
+        self.inner.stop.cancel();
 
-        self.inner.stop.store(true, Ordering::SeqCst);
-        self.inner.log_event.signal();
+        let passive = Passive::try_current().expect("Logger dropped above PASSIVE_LEVEL");
+        let _ = self.log_thread.wait_irql(&passive);
     }
 }
 
@@ -1,23 +1,38 @@
 //Single client communication
 
-use core::{num::NonZeroU32, ops::Deref, ptr::NonNull};
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    num::NonZeroU32,
+    ops::Deref,
+    pin::Pin,
+    ptr::NonNull,
+    task::{Context, Poll},
+};
 
 use nt_string::unicode_string::NtUnicodeStr;
 use wdrf_std::{
     boxed::{Box, BoxExt},
-    kmalloc::TaggedObject,
+    constants::PoolFlags,
+    executor::{oneshot, runtime::Runtime},
+    io,
+    kmalloc::{GlobalKernelAllocator, MemoryTag, TaggedObject},
     slice::{
         slice_from_raw_parts_mut_or_empty, slice_from_raw_parts_or_empty,
         tracked_slice::TrackedSlice,
     },
+    sync::arc::{Arc, ArcExt},
     time::Timeout,
+    vec::{Vec, VecExt},
     NtResult, NtResultEx, NtStatusError,
 };
 use windows_sys::{
     Wdk::Storage::FileSystem::Minifilters::{
         FltCloseClientPort, FltSendMessage, PFLT_FILTER, PFLT_PORT,
     },
-    Win32::Foundation::{NTSTATUS, STATUS_NO_MEMORY, STATUS_SUCCESS, STATUS_UNSUCCESSFUL},
+    Win32::Foundation::{
+        NTSTATUS, STATUS_CANCELLED, STATUS_NO_MEMORY, STATUS_SUCCESS, STATUS_UNSUCCESSFUL,
+    },
 };
 
 use crate::minifilter::filter::framework::GLOBAL_MINIFILTER;
@@ -26,7 +41,7 @@ use super::{FltPort, FltPortCommunicationBuilder};
 
 pub trait FltCommunicationCallback {
     fn connect(&self, buffer: Option<&[u8]>) -> anyhow::Result<()>;
-    fn message(&self, input: &[u8], output: Option<&mut TrackedSlice>) -> anyhow::Result<()>;
+    fn message(&self, input: &[u8], output: Option<&mut TrackedSlice>) -> io::Result<()>;
     fn disconnect(&self);
 }
 
@@ -41,40 +56,62 @@ pub struct CommunicationInner<CB: FltCommunicationCallback> {
 }
 
 pub struct FltClient {
+    inner: Arc<ClientPort>,
+}
+
+///
+/// The client port shared with the pending `send_message_with_reply_async` jobs
+///
+/// `FltSendMessage` gets the address of `port`, so `FltCloseClientPort` can
+/// cancel a send that is still waiting and the job never sees a stale port.
+///
+struct ClientPort {
     filter: PFLT_FILTER,
-    port: PFLT_PORT,
+    port: UnsafeCell<PFLT_PORT>,
+}
+
+unsafe impl Send for ClientPort {}
+unsafe impl Sync for ClientPort {}
+
+impl TaggedObject for ClientPort {
+    fn tag() -> MemoryTag {
+        MemoryTag::new_from_bytes(b"fltc")
+    }
 }
 
 impl FltClient {
-    pub fn new() -> Self {
-        Self {
+    pub fn try_create() -> NtResult<Self> {
+        let inner = Arc::try_create(ClientPort {
             filter: GLOBAL_MINIFILTER.get().raw_filter(),
-            port: 0,
-        }
+            port: UnsafeCell::new(0),
+        })
+        .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        Ok(Self { inner })
     }
 
     fn connect(&mut self, port: PFLT_PORT) {
-        self.port = port;
+        unsafe { *self.inner.port.get() = port };
     }
 
     fn invalidate(&mut self) {
-        self.port = 0;
+        unsafe { *self.inner.port.get() = 0 };
     }
 
     fn disconnect(&mut self) {
-        if self.port > 0 {
-            unsafe {
-                FltCloseClientPort(self.filter, &mut self.port);
+        unsafe {
+            if *self.inner.port.get() > 0 {
+                FltCloseClientPort(self.inner.filter, self.inner.port.get());
             }
+            *self.inner.port.get() = 0;
         }
-        self.port = 0;
     }
 
     pub fn send_message(&self, input: &[u8], timeout: Timeout) -> NtResult<()> {
         unsafe {
             let status = FltSendMessage(
-                self.filter,
-                &self.port,
+                self.inner.filter,
+                self.inner.port.get(),
                 input.as_ptr() as _,
                 input.len() as _,
                 core::ptr::null_mut(),
@@ -92,23 +129,87 @@ impl FltClient {
         reply: &'a mut [u8],
         timeout: Timeout,
     ) -> NtResult<&'a [u8]> {
-        unsafe {
-            let mut reply_size: u32 = reply.len() as u32;
-            let status = FltSendMessage(
-                self.filter,
-                &self.port,
-                input.as_ptr() as _,
-                input.len() as _,
-                reply.as_ptr() as _,
-                &mut reply_size,
-                timeout.as_ptr(),
-            );
-            let reply_size: usize = reply_size as _;
-            NtResult::from_status(status, || (&reply[..reply_size]))
+        let reply_size = send_with_reply(&self.inner, input, reply, timeout)?;
+        Ok(&reply[..reply_size])
+    }
+
+    ///
+    /// Sends the message from the runtime blocking pool
+    ///
+    /// The future resolves to the reply, truncated to the size user mode wrote.
+    ///
+    pub fn send_message_with_reply_async(
+        &self,
+        runtime: &Runtime,
+        input: Vec<u8>,
+        reply_size: usize,
+        timeout: Timeout,
+    ) -> NtResult<ReplyFuture> {
+        let mut reply = Vec::new_in(GlobalKernelAllocator::new(
+            MemoryTag::new_from_bytes(b"fltr"),
+            PoolFlags::POOL_FLAG_NON_PAGED,
+        ));
+        reply
+            .try_resize(reply_size, 0u8)
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        //The job keeps the port alive, a disconnect while it waits cancels the send
+        let client = self.inner.clone();
+        let receiver = runtime
+            .spawn_blocking(move || {
+                let mut reply = reply;
+                let size = send_with_reply(&client, &input, &mut reply, timeout)?;
+                reply.truncate(size);
+
+                Ok(reply)
+            })
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        Ok(ReplyFuture { receiver })
+    }
+}
+
+///
+/// Reply of `send_message_with_reply_async`, fails with `STATUS_CANCELLED`
+/// if the runtime dropped the request before sending it
+///
+pub struct ReplyFuture {
+    receiver: oneshot::Receiver<NtResult<Vec<u8>>>,
+}
+
+impl Future for ReplyFuture {
+    type Output = NtResult<Vec<u8>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.receiver).poll(cx) {
+            Poll::Ready(Ok(reply)) => Poll::Ready(reply),
+            Poll::Ready(Err(_)) => Poll::Ready(Err(NtStatusError::Status(STATUS_CANCELLED))),
+            Poll::Pending => Poll::Pending,
         }
     }
 }
 
+fn send_with_reply(
+    client: &ClientPort,
+    input: &[u8],
+    reply: &mut [u8],
+    timeout: Timeout,
+) -> NtResult<usize> {
+    unsafe {
+        let mut reply_size: u32 = reply.len() as u32;
+        let status = FltSendMessage(
+            client.filter,
+            client.port.get(),
+            input.as_ptr() as _,
+            input.len() as _,
+            reply.as_ptr() as _,
+            &mut reply_size,
+            timeout.as_ptr(),
+        );
+        NtResult::from_status(status, || reply_size as usize)
+    }
+}
+
 impl Drop for FltClient {
     fn drop(&mut self) {
         self.disconnect()
@@ -132,7 +233,7 @@ where
         let mut inner = Box::try_create(CommunicationInner {
             port: None,
             callbacks,
-            client: FltClient::new(),
+            client: FltClient::try_create()?,
         })
         .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
 
@@ -166,6 +267,18 @@ where
             .client
             .send_message_with_reply(input, reply, timeout)
     }
+
+    pub fn send_message_with_reply_async(
+        &self,
+        runtime: &Runtime,
+        input: Vec<u8>,
+        reply_size: usize,
+        timeout: Timeout,
+    ) -> NtResult<ReplyFuture> {
+        self.inner
+            .client
+            .send_message_with_reply_async(runtime, input, reply_size, timeout)
+    }
 }
 
 impl<CB> Deref for FltClientCommunication<CB>
@@ -248,6 +361,6 @@ unsafe extern "system" fn flt_comm_notify<CB: FltCommunicationCallback>(
             *return_output_buffer_length = tracked.bytes_written() as u32;
             STATUS_SUCCESS
         }
-        Err(_) => STATUS_UNSUCCESSFUL,
+        Err(e) => e.status(),
     }
 }
@@ -4,7 +4,10 @@ use core::{num::NonZeroU32, ptr::NonNull};
 
 use nt_string::unicode_string::NtUnicodeStr;
 
-use wdrf_std::{kmalloc::TaggedObject, object::attribute::ObjectAttributes, NtResult, NtResultEx};
+use wdrf_std::{
+    kmalloc::TaggedObject, object::attribute::ObjectAttributes, security::SecurityDescriptor,
+    NtResult, NtResultEx,
+};
 use windows_sys::{
     Wdk::{
         Foundation::OBJECT_ATTRIBUTES,
@@ -13,7 +16,10 @@ use windows_sys::{
             PFLT_DISCONNECT_NOTIFY, PFLT_FILTER, PFLT_MESSAGE_NOTIFY, PFLT_PORT,
         },
     },
-    Win32::System::Kernel::{OBJ_CASE_INSENSITIVE, OBJ_KERNEL_HANDLE},
+    Win32::{
+        Security::PSECURITY_DESCRIPTOR,
+        System::Kernel::{OBJ_CASE_INSENSITIVE, OBJ_KERNEL_HANDLE},
+    },
 };
 
 use super::{filter::framework::GLOBAL_MINIFILTER, security_descriptor::FltSecurityDescriptor};
@@ -42,6 +48,7 @@ pub struct FltPortCommunicationBuilder<'a> {
     disconnect: PFLT_DISCONNECT_NOTIFY,
     message: PFLT_MESSAGE_NOTIFY,
     max_connections: NonZeroU32,
+    security_descriptor: Option<&'a SecurityDescriptor>,
 }
 
 impl<'a> FltPortCommunicationBuilder<'a> {
@@ -54,6 +61,7 @@ impl<'a> FltPortCommunicationBuilder<'a> {
             disconnect: None,
             message: None,
             max_connections: unsafe { NonZeroU32::new_unchecked(1) },
+            security_descriptor: None,
         }
     }
 
@@ -82,13 +90,31 @@ impl<'a> FltPortCommunicationBuilder<'a> {
         self
     }
 
+    ///
+    /// Replaces the default descriptor, which only lets administrators and
+    /// SYSTEM connect
+    ///
+    pub fn security_descriptor(mut self, security_descriptor: &'a SecurityDescriptor) -> Self {
+        self.security_descriptor = Some(security_descriptor);
+        self
+    }
+
     pub fn build(self) -> NtResult<FltPort> {
+        match self.security_descriptor {
+            Some(security_descriptor) => self.create_port(security_descriptor),
+            None => self.create_port(&FltSecurityDescriptor::try_default_flt()?),
+        }
+    }
+
+    fn create_port(
+        &self,
+        security_descriptor: &impl AsRef<PSECURITY_DESCRIPTOR>,
+    ) -> NtResult<FltPort> {
         let mut port = 0;
-        let security_descriptor = FltSecurityDescriptor::try_default_flt()?;
         let obj_attribs = ObjectAttributes::new_named_security(
             &self.name,
             OBJ_KERNEL_HANDLE | OBJ_CASE_INSENSITIVE,
-            &security_descriptor,
+            security_descriptor,
         );
 
         let ptr: *mut OBJECT_ATTRIBUTES = obj_attribs.as_ref_mut();
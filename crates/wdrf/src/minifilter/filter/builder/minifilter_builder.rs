@@ -79,7 +79,7 @@ where
 
         let context = MinifilterContext::try_create(self.minifilter_context)?;
 
-        let mut framework = MinifilterFramework::new(context.into_any());
+        let mut framework = MinifilterFramework::new(context.into_any())?;
 
         unsafe {
             framework.register_filter(driver, registration)?;
@@ -58,8 +58,7 @@ where
         PreOpStatus::SuccessNoCallback => FLT_PREOP_SUCCESS_NO_CALLBACK,
         PreOpStatus::SuccessWithCallback(any) => {
             if let Some(context) = any {
-                let leak: *mut Pre::PostContext = PostOpContext::leak(context);
-                *completioncontext = leak as _;
+                *completioncontext = PostOpContext::leak(context);
             } else {
                 *completioncontext = core::ptr::null_mut();
             }
@@ -93,8 +92,8 @@ where
     let context = if completioncontext.is_null() {
         None
     } else {
-        let context: *mut Post::PostContext = (completioncontext as *mut core::ffi::c_void) as _;
-        let context = PostOpContext::from_raw_ptr(context);
+        let context: PostOpContext<Post::PostContext> =
+            PostOpContext::from_raw_ptr(completioncontext as *mut core::ffi::c_void);
 
         Some(context)
     };
@@ -127,7 +126,11 @@ where
 
     //let result = GLOBAL_MINIFILTER.get().filter_operations.unload(mandatory);
     match result {
-        super::UnloadStatus::Unload => STATUS_SUCCESS,
+        super::UnloadStatus::Unload => {
+            //No-op if the driver already dropped the framework from its unload callback
+            MinifilterFramework::unregister();
+            STATUS_SUCCESS
+        }
         super::UnloadStatus::NoDetach => {
             if mandatory {
                 panic!("Mandatory unload but NoDetach was returned from unload");
@@ -1,6 +1,7 @@
-use core::{any::Any, cell::UnsafeCell};
+use core::{any::Any, cell::UnsafeCell, pin::Pin};
 
 use wdrf_std::kmalloc::TaggedObject;
+use wdrf_std::sync::in_flight::InFlightCounter;
 use wdrf_std::{
     boxed::{Box, BoxExt},
     NtResult, NtResultEx, NtStatusError,
@@ -66,6 +67,7 @@ impl MinifilterContextAny {
 pub struct MinifilterFramework {
     pub(crate) minifilter_context: MinifilterContextAny,
     pub(crate) filter: UnsafeCell<PFLT_FILTER>,
+    pub(crate) in_flight: Pin<Box<InFlightCounter>>,
 }
 
 unsafe impl Send for MinifilterFramework {}
@@ -74,11 +76,15 @@ unsafe impl Sync for MinifilterFramework {}
 pub(crate) static GLOBAL_MINIFILTER: Context<MinifilterFramework> = Context::uninit();
 
 impl MinifilterFramework {
-    pub(crate) fn new(context: MinifilterContextAny) -> Self {
-        Self {
+    pub(crate) fn new(context: MinifilterContextAny) -> NtResult<Self> {
+        let in_flight = InFlightCounter::try_create_box()
+            .map_err(|_| NtStatusError::Status(STATUS_NO_MEMORY))?;
+
+        Ok(Self {
             minifilter_context: context,
             filter: UnsafeCell::new(0),
-        }
+            in_flight,
+        })
     }
 
     pub(crate) unsafe fn register_filter(
@@ -97,14 +103,41 @@ impl MinifilterFramework {
         NtResult::from_status(status, || ())
     }
 
+    ///
+    /// Unregisters the filter and waits for the in-flight operations
+    ///
+    /// Does nothing if the framework was never built or was already torn down.
+    ///
     pub fn unregister() {
+        if let Some(framework) = GLOBAL_MINIFILTER.try_get() {
+            framework.unregister_filter();
+        }
+    }
+
+    fn unregister_filter(&self) {
         unsafe {
-            let framework = GLOBAL_MINIFILTER.get();
-            if *framework.filter.get() != 0 {
-                FltUnregisterFilter(*framework.filter.get());
-                *framework.filter.get() = 0;
+            let filter = *self.filter.get();
+            if filter != 0 {
+                FltUnregisterFilter(filter);
+                *self.filter.get() = 0;
             }
         }
+
+        //FltUnregisterFilter calls the pending post operations with the draining flag,
+        //what is left are the tokens held by the driver's own deferred work
+        self.in_flight.drain(None);
+    }
+
+    ///
+    /// Tracks pending post operation contexts, user code can enter it for
+    /// its own deferred work
+    ///
+    /// It is drained by `unregister`, returns None if the framework is not built.
+    ///
+    pub fn in_flight() -> Option<&'static InFlightCounter> {
+        GLOBAL_MINIFILTER
+            .try_get()
+            .map(|framework| &*framework.in_flight)
     }
 
     pub fn raw_filter(&self) -> PFLT_FILTER {
@@ -129,6 +162,6 @@ impl MinifilterFramework {
 
 impl Drop for MinifilterFramework {
     fn drop(&mut self) {
-        Self::unregister();
+        self.unregister_filter();
     }
 }
@@ -3,33 +3,56 @@ use core::ops::{Deref, DerefMut};
 use wdrf_std::{
     boxed::{Box, BoxExt},
     kmalloc::{GlobalKernelAllocator, TaggedObject},
+    sync::in_flight::InFlightToken,
 };
 
+use crate::minifilter::filter::framework::MinifilterFramework;
+
 /*
 This can be implemented by keeping track of the tag etc etc i dont have time etc etc
 */
 
+struct PostOpInner<T> {
+    value: T,
+    //Keeps the minifilter from unloading while the post operation is pending
+    _in_flight: InFlightToken<'static>,
+}
+
 #[repr(transparent)]
-pub struct PostOpContext<T: 'static + Send + TaggedObject>(Box<T>);
+pub struct PostOpContext<T: 'static + Send + TaggedObject>(Box<PostOpInner<T>>);
 
 impl<T: 'static + Send + TaggedObject> PostOpContext<T> {
     pub fn try_create(value: T) -> anyhow::Result<Self> {
-        Box::try_create_in(value, GlobalKernelAllocator::new_for_tagged::<T>()).map(|b| Self(b))
+        let in_flight = MinifilterFramework::in_flight()
+            .and_then(|in_flight| in_flight.enter())
+            .ok_or(anyhow::Error::msg(
+                "Minifilter is not registered or is draining",
+            ))?;
+
+        Box::try_create_in(
+            PostOpInner {
+                value,
+                _in_flight: in_flight,
+            },
+            GlobalKernelAllocator::new_for_tagged::<T>(),
+        )
+        .map(|b| Self(b))
     }
 
-    pub(crate) fn leak(self) -> &'static mut T {
-        Box::leak(self.0)
+    pub(crate) fn leak(self) -> *mut core::ffi::c_void {
+        let leaked: *mut PostOpInner<T> = Box::leak(self.0);
+        leaked.cast()
     }
 
-    pub(crate) unsafe fn from_raw_ptr(raw: *mut T) -> Self {
+    pub(crate) unsafe fn from_raw_ptr(raw: *mut core::ffi::c_void) -> Self {
         Self(Box::from_raw_in(
-            raw,
+            raw.cast(),
             GlobalKernelAllocator::new_for_tagged::<T>(),
         ))
     }
 
     pub fn unwrap(self) -> T {
-        Box::into_inner(self.0)
+        Box::into_inner(self.0).value
     }
 }
 
@@ -37,12 +60,12 @@ impl<T: 'static + Send + TaggedObject> Deref for PostOpContext<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        self.0.deref()
+        &self.0.value
     }
 }
 
 impl<T: 'static + Send + Sync + TaggedObject> DerefMut for PostOpContext<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.0.deref_mut()
+        &mut self.0.value
     }
 }
@@ -0,0 +1,12 @@
+//!
+//! Security descriptors for the objects a driver creates
+//!
+//! ```ignore
+//! let descriptor = SecurityDescriptor::from_sddl("D:P(A;;GA;;;SY)(A;;GRGW;;;BA)")?;
+//! let port = FltPortCommunicationBuilder::new(name)
+//!     .security_descriptor(&descriptor)
+//!     .build()?;
+//! ```
+//!
+
+pub use wdrf_std::security::*;